@@ -9,8 +9,15 @@ pub fn router() -> Router {
     Router::new()
         .route("/api/chat/contacts", post(get_contacts))
         .route("/api/chat/contacts/:wxid", get(get_contact_detail))
+        .route("/api/chat/contacts/:wxid/avatar", post(get_contact_avatar))
+        .route("/api/chat/labels", post(get_contact_labels))
         .route("/api/chat/msg/count", post(get_msg_count))
         .route("/api/chat/msg/list", post(get_msg_list))
+        .route("/api/chat/msg/jump", post(jump_to_date))
         .route("/api/chat/msg/search", post(search_messages))
+        .route("/api/chat/msg/index", post(update_msg_index))
+        .route("/api/chat/events/:wxid", post(get_chat_events))
+        .route("/api/chat/chatrooms/:wxid", post(get_chatroom_detail))
+        .route("/api/chat/chatrooms/:wxid/members", post(get_chatroom_members))
 }
 
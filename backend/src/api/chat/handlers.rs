@@ -1,34 +1,59 @@
 use axum::Json;
+use axum::body::Body;
 use axum::extract::Path;
+use axum::http::header;
+use axum::response::Response;
 use std::collections::HashMap;
-use std::path::PathBuf;
 
-use crate::db::msg::MsgHandler;
-use crate::db::contact::ContactHandler;
-use crate::utils::{AppError, Result};
+use crate::db::contact::ContactKind;
+use crate::db::head_image::image_mime;
+use crate::db::msg_content::SystemEvent;
+use crate::db::msg_list::{MsgCursor, MsgPage};
+use crate::db::utils::Message;
+use crate::db::workspace::{self, Workspace};
+use crate::utils::{AppError, Result, validation};
 use super::models::*;
 
+/// 有聊天记录的联系人，可按分类和标签筛选
 pub async fn get_contacts(Json(req): Json<ChatContactsRequest>) -> Result<Json<ChatContactsResponse>> {
-    validation::validate_db_path(&req.merge_path)?;
-    
-    let db_path = PathBuf::from(&req.merge_path);
-
-    let handler = MsgHandler::new(db_path.to_str().unwrap())?;
-    handler.add_indexes()?;
-
-    let counts = handler.get_msg_count(None)?;
+    let workspace = workspace::resolve(req.workspace_id.as_deref(), req.merge_path.as_deref())?;
+    let (counts, contact_map) = workspace::blocking(workspace, |ws| {
+        let counts = ws.msg()?.get_msg_count(None)?;
+        let contact_map = match ws.contact() {
+            Ok(handler) => handler.get_contact_map()?,
+            Err(_) => HashMap::new(),
+        };
+        Ok((counts, contact_map))
+    }).await?;
     let total = counts.get("total").copied().unwrap_or(0);
 
     let mut contacts = Vec::new();
     for (wxid, count) in counts.iter() {
-        if wxid != "total" {
-            contacts.push(ContactInfo {
-                wxid: wxid.clone(),
-                msg_count: *count,
-                sender_count: 0,
-                receiver_count: 0,
-            });
+        if wxid == "total" {
+            continue;
         }
+        let contact = contact_map.get(wxid);
+        let kind = contact
+            .map(|c| c.kind)
+            .unwrap_or_else(|| ContactKind::classify(wxid, 0, 0, 0));
+        let labels = contact.map(|c| c.labels.clone()).unwrap_or_default();
+        if req.kind.is_some_and(|k| k != kind)
+            || req.label.as_ref().is_some_and(|l| !labels.contains(l))
+        {
+            continue;
+        }
+        contacts.push(ContactInfo {
+            wxid: wxid.clone(),
+            msg_count: *count,
+            sender_count: 0,
+            receiver_count: 0,
+            nickname: contact.and_then(|c| c.nickname.clone()),
+            remark: contact.and_then(|c| c.remark.clone()),
+            head_img_url: contact.and_then(|c| c.head_img_url.clone()),
+            big_head_img_url: contact.and_then(|c| c.big_head_img_url.clone()),
+            kind,
+            labels,
+        });
     }
 
     contacts.sort_by(|a, b| b.msg_count.cmp(&a.msg_count));
@@ -36,110 +61,198 @@ pub async fn get_contacts(Json(req): Json<ChatContactsRequest>) -> Result<Json<C
     Ok(Json(ChatContactsResponse { contacts, total }))
 }
 
-pub async fn get_msg_count(Json(req): Json<MsgCountRequest>) -> Result<Json<MsgCountResponse>> {
-    validation::validate_db_path(&req.merge_path)?;
-    
-    let db_path = PathBuf::from(&req.merge_path);
+/// 联系人标签及各标签的联系人数
+pub async fn get_contact_labels(Json(req): Json<ChatContactsRequest>) -> Result<Json<ContactLabelsResponse>> {
+    let workspace = workspace::resolve(req.workspace_id.as_deref(), req.merge_path.as_deref())?;
+    let labels = workspace::blocking(workspace, |ws| {
+        let handler = ws.contact()?;
+        let contacts = handler.get_contacts()?;
+        Ok(handler
+            .get_labels()?
+            .into_iter()
+            .map(|label| LabelInfo {
+                count: contacts.iter().filter(|c| c.labels.contains(&label.name)).count() as i64,
+                id: label.id,
+                name: label.name,
+            })
+            .collect())
+    }).await?;
 
-    let handler = MsgHandler::new(db_path.to_str().unwrap())?;
-    handler.add_indexes()?;
+    Ok(Json(ContactLabelsResponse { labels }))
+}
 
-    let counts = handler.get_msg_count(req.wxid.as_deref())?;
+/// 本地缓存的联系人头像图片
+pub async fn get_contact_avatar(
+    Path(wxid): Path<String>,
+    Json(req): Json<ChatContactsRequest>
+) -> Result<Response> {
+    let workspace = workspace::resolve(req.workspace_id.as_deref(), req.merge_path.as_deref())?;
+    let query_wxid = wxid.clone();
+    let image = workspace::blocking(workspace, move |ws| {
+        ws.head_image()?.get_head_image(&query_wxid)
+    }).await?
+        .ok_or_else(|| AppError::NotFound(format!("头像不存在: {}", wxid)))?;
 
-    Ok(Json(MsgCountResponse { counts }))
+    Ok(Response::builder()
+        .header(header::CONTENT_TYPE, image_mime(&image))
+        .body(Body::from(image))
+        .map_err(|e| anyhow::anyhow!("Failed to build response: {}", e))?)
 }
 
-pub async fn get_msg_list(Json(req): Json<MsgListRequest>) -> Result<Json<MsgListResponse>> {
-    validation::validate_db_path(&req.merge_path)?;
-    validation::validate_pagination(req.start, req.limit)?;
-    validation::validate_time_range(req.start_time, req.end_time)?;
-    
-    let db_path = PathBuf::from(&req.merge_path);
-    let handler = MsgHandler::new(db_path.to_str().unwrap())?;
-    handler.add_indexes()?;
-
-    let (messages, wxid_list) = handler.get_msg_list_with_users(
-        Some(&req.wxid),
-        req.start,
-        req.limit,
-        req.start_time,
-        req.end_time,
-    )?;
-
-    let total = handler.get_msg_count(Some(&req.wxid))?
-        .get(&req.wxid)
-        .copied()
-        .unwrap_or(0);
+pub async fn get_msg_count(Json(req): Json<MsgCountRequest>) -> Result<Json<MsgCountResponse>> {
+    let workspace = workspace::resolve(req.workspace_id.as_deref(), req.merge_path.as_deref())?;
+    let wxid = req.wxid.clone();
+    let counts = workspace::blocking(workspace, move |ws| {
+        ws.msg()?.get_msg_count(wxid.as_deref())
+    }).await?;
 
-    let micro_db_path = db_path.parent()
-        .and_then(|p| p.parent())
-        .map(|p| p.join("MicroMsg.db"));
-    
+    Ok(Json(MsgCountResponse { counts }))
+}
+
+/// 查询本页消息涉及的联系人信息，群聊成员附带群昵称
+fn collect_users(ws: &Workspace, messages: &[Message]) -> HashMap<String, serde_json::Value> {
     let mut user_map = HashMap::new();
-    if let Some(micro_path) = micro_db_path {
-        if micro_path.exists() {
-            let contact_handler = ContactHandler::new(micro_path.to_str().unwrap()).ok();
-            if let Some(handler) = contact_handler {
-                for wxid in &wxid_list {
-                    if let Ok(Some(contact)) = handler.get_contact(wxid) {
-                        user_map.insert(wxid.clone(), serde_json::json!({
-                            "wxid": contact.wxid,
-                            "nickname": contact.nickname,
-                            "remark": contact.remark,
-                            "head_img_url": contact.head_img_url,
-                        }));
-                    }
-                }
+    let contact_handler = ws.contact().ok();
+    let chatroom_handler = ws.chatroom().ok();
+    if contact_handler.is_none() && chatroom_handler.is_none() {
+        return user_map;
+    }
+    let mut room_nicknames: HashMap<&str, HashMap<String, String>> = HashMap::new();
+
+    for msg in messages {
+        let nicknames = match chatroom_handler {
+            Some(handler) if msg.str_talker.ends_with("@chatroom") => Some(
+                &*room_nicknames
+                    .entry(&msg.str_talker)
+                    .or_insert_with(|| handler.room_nicknames(&msg.str_talker).unwrap_or_default()),
+            ),
+            _ => None,
+        };
+        let reply_sender = msg.reply_to.as_ref().map(|r| &r.sender_wxid);
+        let wxids = [&msg.talker, &msg.str_talker, &msg.sender_wxid];
+        for wxid in wxids.into_iter().chain(reply_sender) {
+            if wxid.is_empty() || user_map.contains_key(wxid) {
+                continue;
+            }
+            let contact = contact_handler.and_then(|h| h.get_contact(wxid).ok().flatten());
+            let room_nickname = nicknames.and_then(|n| n.get(wxid));
+            if contact.is_none() && room_nickname.is_none() {
+                continue;
             }
+            user_map.insert(wxid.clone(), serde_json::json!({
+                "wxid": wxid,
+                "nickname": contact.as_ref().and_then(|c| c.nickname.clone()),
+                "remark": contact.as_ref().and_then(|c| c.remark.clone()),
+                "head_img_url": contact.as_ref().and_then(|c| c.head_img_url.clone()),
+                "room_nickname": room_nickname,
+            }));
         }
     }
+    user_map
+}
 
-    let msg_responses: Vec<MessageResponse> = messages.into_iter().map(|msg| {
-        MessageResponse {
-            id: msg.id,
-            local_id: msg.local_id,
-            msg_svr_id: msg.msg_svr_id,
-            msg_type: msg.msg_type,
-            sub_type: msg.sub_type,
-            type_name: msg.type_name,
-            create_time: msg.create_time,
-            create_time_str: msg.create_time_str,
-            is_sender: msg.is_sender,
-            talker: msg.talker,
-            str_talker: msg.str_talker,
-            content: msg.content,
-            display_content: msg.display_content,
-            src: msg.src,
-            extra: msg.extra,
-        }
-    }).collect();
+fn message_response(msg: Message) -> MessageResponse {
+    MessageResponse {
+        id: msg.id,
+        local_id: msg.local_id,
+        msg_svr_id: msg.msg_svr_id,
+        msg_type: msg.msg_type,
+        sub_type: msg.sub_type,
+        type_name: msg.type_name,
+        create_time: msg.create_time,
+        create_time_str: msg.create_time_str,
+        is_sender: msg.is_sender,
+        talker: msg.talker,
+        str_talker: msg.str_talker,
+        sender_wxid: msg.sender_wxid,
+        content: msg.content,
+        display_content: msg.display_content,
+        src: msg.src,
+        extra: msg.extra,
+        reply_to: msg.reply_to,
+        event: msg.event,
+    }
+}
 
-    Ok(Json(MsgListResponse {
-        messages: msg_responses,
+fn page_response(ws: &Workspace, wxid: &str, page: MsgPage) -> Result<MsgListResponse> {
+    let total = ws.msg()?
+        .get_msg_count(Some(wxid))?
+        .get(wxid)
+        .copied()
+        .unwrap_or(0);
+    let user_list = collect_users(ws, &page.messages);
+
+    Ok(MsgListResponse {
+        messages: page.messages.into_iter().map(message_response).collect(),
         total,
-        user_list: user_map,
-    }))
+        user_list,
+        prev_cursor: page.prev_cursor.map(|c| c.encode()),
+        next_cursor: page.next_cursor.map(|c| c.encode()),
+    })
+}
+
+pub async fn get_msg_list(Json(req): Json<MsgListRequest>) -> Result<Json<MsgListResponse>> {
+    validation::validate_pagination(req.start, req.limit)?;
+    validation::validate_time_range(req.start_time, req.end_time)?;
+    let cursor = req.cursor.as_deref().map(MsgCursor::decode).transpose()?;
+    
+    let workspace = workspace::resolve(req.workspace_id.as_deref(), req.merge_path.as_deref())?;
+    let response = workspace::blocking(workspace, move |ws| {
+        let handler = ws.msg()?;
+        let page = if cursor.is_some() || req.direction.is_some() || req.start == 0 {
+            handler.get_msg_page(
+                Some(&req.wxid),
+                cursor.as_ref(),
+                req.direction.unwrap_or_default(),
+                req.limit,
+                req.start_time,
+                req.end_time,
+            )?
+        } else {
+            // 兼容按偏移分页的旧请求，不返回游标
+            MsgPage {
+                messages: handler.get_msg_list(
+                    Some(&req.wxid),
+                    req.start,
+                    req.limit,
+                    req.start_time,
+                    req.end_time,
+                )?,
+                prev_cursor: None,
+                next_cursor: None,
+            }
+        };
+        page_response(ws, &req.wxid, page)
+    }).await?;
+
+    Ok(Json(response))
+}
+
+pub async fn jump_to_date(Json(req): Json<MsgJumpRequest>) -> Result<Json<MsgListResponse>> {
+    validation::validate_pagination(0, req.limit)?;
+
+    let workspace = workspace::resolve(req.workspace_id.as_deref(), req.merge_path.as_deref())?;
+    let response = workspace::blocking(workspace, move |ws| {
+        let page = ws.msg()?.get_msg_page_at(Some(&req.wxid), req.timestamp, req.limit, None, None)?;
+        page_response(ws, &req.wxid, page)
+    }).await?;
+
+    Ok(Json(response))
 }
 
 pub async fn get_contact_detail(
     Path(wxid): Path<String>,
     Json(req): Json<ChatContactsRequest>
 ) -> Result<Json<serde_json::Value>> {
-    let db_path = PathBuf::from(&req.merge_path);
-    if !db_path.exists() {
-        return Err(AppError::NotFound(format!("Database not found: {}", req.merge_path)).into());
-    }
-
-    let micro_db_path = db_path.parent()
-        .and_then(|p| p.parent())
-        .map(|p| p.join("MicroMsg.db"))
-        .ok_or_else(|| AppError::NotFound("Cannot find MicroMsg.db path".to_string()))?;
+    let workspace = workspace::resolve(req.workspace_id.as_deref(), req.merge_path.as_deref())?;
+    let query_wxid = wxid.clone();
+    let contact = workspace::blocking(workspace, move |ws| match ws.contact() {
+        Ok(handler) => handler.get_contact(&query_wxid),
+        Err(_) => Ok(None),
+    }).await?;
 
-    if micro_db_path.exists() {
-        let handler = ContactHandler::new(micro_db_path.to_str().unwrap())?;
-        if let Some(contact) = handler.get_contact(&wxid)? {
-            return Ok(Json(serde_json::json!(contact)));
-        }
+    if let Some(contact) = contact {
+        return Ok(Json(serde_json::json!(contact)));
     }
 
     Ok(Json(serde_json::json!({
@@ -150,52 +263,157 @@ pub async fn get_contact_detail(
 }
 
 pub async fn search_messages(Json(req): Json<super::models::MsgSearchRequest>) -> Result<Json<super::models::MsgSearchResponse>> {
-    validation::validate_db_path(&req.merge_path)?;
     validation::validate_time_range(req.start_time, req.end_time)?;
     
     if req.keyword.trim().is_empty() {
         return Err(AppError::BadRequest("搜索关键词不能为空".to_string()).into());
     }
     
-    let db_path = PathBuf::from(&req.merge_path);
-    let handler = MsgHandler::new(db_path.to_str().unwrap())?;
-    handler.add_indexes()?;
-
+    let workspace = workspace::resolve(req.workspace_id.as_deref(), req.merge_path.as_deref())?;
+    let query = req.clone();
     let limit = req.limit.unwrap_or(100);
-    let messages = handler.search_messages(
-        req.wxid.as_deref(),
-        &req.keyword,
-        req.start_time,
-        req.end_time,
-        limit,
-    )?;
-
-    let total = messages.len() as i64;
-
-    let msg_responses: Vec<super::models::MessageResponse> = messages.into_iter().map(|msg| {
-        super::models::MessageResponse {
-            id: msg.id,
-            local_id: msg.local_id,
-            msg_svr_id: msg.msg_svr_id,
-            msg_type: msg.msg_type,
-            sub_type: msg.sub_type,
-            type_name: msg.type_name,
-            create_time: msg.create_time,
-            create_time_str: msg.create_time_str,
-            is_sender: msg.is_sender,
-            talker: msg.talker,
-            str_talker: msg.str_talker,
-            content: msg.content,
-            display_content: msg.display_content,
-            src: msg.src,
-            extra: msg.extra,
-        }
-    }).collect();
+    let hits = workspace::blocking(workspace, move |ws| {
+        ws.msg()?.search(
+            &query.keyword,
+            query.wxid.as_deref(),
+            query.start_time,
+            query.end_time,
+            limit,
+        )
+    }).await?;
+
+    let total = hits.len() as i64;
+
+    let messages: Vec<SearchHitResponse> = hits
+        .into_iter()
+        .map(|hit| SearchHitResponse {
+            message: message_response(hit.message),
+            snippet: hit.snippet,
+            highlights: hit.highlights,
+            score: hit.score,
+        })
+        .collect();
 
     Ok(Json(super::models::MsgSearchResponse {
-        messages: msg_responses,
+        messages,
         total,
         keyword: req.keyword,
     }))
 }
 
+/// 群聊中的撤回、拍一拍、进退群事件，按时间升序，用`next_cursor`翻页
+pub async fn get_chat_events(
+    Path(wxid): Path<String>,
+    Json(req): Json<ChatEventsRequest>
+) -> Result<Json<ChatEventsResponse>> {
+    validation::validate_time_range(req.start_time, req.end_time)?;
+    let limit = req.limit.unwrap_or(1000);
+    validation::validate_pagination(0, limit)?;
+    if let Some(kind) = req.kinds.iter().find(|k| !SystemEvent::KINDS.contains(&k.as_str())) {
+        return Err(AppError::BadRequest(format!("未知的事件类型: {}", kind)).into());
+    }
+
+    let cursor = req.cursor.as_deref().map(MsgCursor::decode).transpose()?;
+
+    let workspace = workspace::resolve(req.workspace_id.as_deref(), req.merge_path.as_deref())?;
+    let query_wxid = wxid.clone();
+    let page = workspace::blocking(workspace, move |ws| {
+        ws.msg()?.system_events(&query_wxid, &req.kinds, cursor.as_ref(), req.start_time, req.end_time, limit)
+    }).await?;
+
+    let events: Vec<MessageResponse> = page.messages.into_iter().map(message_response).collect();
+    Ok(Json(ChatEventsResponse {
+        wxid,
+        total: events.len() as i64,
+        events,
+        next_cursor: page.next_cursor.map(|c| c.encode()),
+    }))
+}
+
+/// 群聊详情：群主、群公告和成员数
+pub async fn get_chatroom_detail(
+    Path(wxid): Path<String>,
+    Json(req): Json<ChatContactsRequest>
+) -> Result<Json<ChatroomResponse>> {
+    let workspace = workspace::resolve(req.workspace_id.as_deref(), req.merge_path.as_deref())?;
+    let query_wxid = wxid.clone();
+    let chatroom = workspace::blocking(workspace, move |ws| {
+        ws.chatroom()?.get_chatroom(&query_wxid)
+    }).await?
+        .ok_or_else(|| AppError::NotFound(format!("群聊不存在: {}", wxid)))?;
+
+    Ok(Json(ChatroomResponse {
+        wxid: chatroom.wxid,
+        owner: chatroom.owner,
+        self_display_name: chatroom.self_display_name,
+        member_count: chatroom.members.len(),
+        announcement: chatroom.announcement,
+    }))
+}
+
+/// 群成员列表，附带群昵称和联系人信息
+pub async fn get_chatroom_members(
+    Path(wxid): Path<String>,
+    Json(req): Json<ChatContactsRequest>
+) -> Result<Json<ChatroomMembersResponse>> {
+    let workspace = workspace::resolve(req.workspace_id.as_deref(), req.merge_path.as_deref())?;
+    let response = workspace::blocking(workspace, move |ws| {
+        let chatroom = ws.chatroom()?
+            .get_chatroom(&wxid)?
+            .ok_or_else(|| AppError::NotFound(format!("群聊不存在: {}", wxid)))?;
+        // 一次读取全部联系人，避免大群中逐个成员查询
+        let mut contacts = match ws.contact() {
+            Ok(handler) => handler.get_contact_map()?,
+            Err(_) => HashMap::new(),
+        };
+
+        let members: Vec<ChatroomMemberInfo> = chatroom.members
+            .into_iter()
+            .map(|member| {
+                let contact = contacts.remove(&member.wxid);
+                let display_name = member
+                    .room_nickname
+                    .clone()
+                    .filter(|n| !n.is_empty())
+                    .or_else(|| contact.as_ref().and_then(|c| c.display_name().map(str::to_string)))
+                    .unwrap_or_else(|| member.wxid.clone());
+                ChatroomMemberInfo {
+                    display_name,
+                    nickname: contact.as_ref().and_then(|c| c.nickname.clone()),
+                    remark: contact.as_ref().and_then(|c| c.remark.clone()),
+                    head_img_url: contact.and_then(|c| c.head_img_url),
+                    wxid: member.wxid,
+                    room_nickname: member.room_nickname,
+                    role: member.role,
+                }
+            })
+            .collect();
+
+        Ok(ChatroomMembersResponse {
+            wxid,
+            owner: chatroom.owner,
+            total: members.len() as i64,
+            members,
+        })
+    }).await?;
+
+    Ok(Json(response))
+}
+
+/// 将新消息加入全文索引，首次调用时为全部消息建立索引
+pub async fn update_msg_index(Json(req): Json<MsgIndexRequest>) -> Result<Json<MsgIndexResponse>> {
+    let workspace = workspace::resolve(req.workspace_id.as_deref(), req.merge_path.as_deref())?;
+    let response = workspace::blocking(workspace, |ws| {
+        let msg = ws.msg()?;
+        let added = msg.update_index()?;
+        let index = msg.index().expect("update_index succeeded");
+        Ok(MsgIndexResponse {
+            path: index.path().to_string(),
+            added,
+            indexed: index.indexed_count()?,
+        })
+    }).await?;
+
+    Ok(Json(response))
+}
+
@@ -1,9 +1,20 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+use crate::db::chatroom::{Announcement, ChatroomRole};
+use crate::db::contact::ContactKind;
+use crate::db::msg_list::PageDirection;
+use crate::db::msg_content::SystemEvent;
+use crate::db::utils::ReplyTo;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChatContactsRequest {
-    pub merge_path: String,
+    pub workspace_id: Option<String>,
+    pub merge_path: Option<String>,
+    /// 联系人列表按分类筛选
+    pub kind: Option<ContactKind>,
+    /// 联系人列表按标签名称筛选
+    pub label: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -18,11 +29,31 @@ pub struct ContactInfo {
     pub msg_count: i64,
     pub sender_count: i64,
     pub receiver_count: i64,
+    pub nickname: Option<String>,
+    pub remark: Option<String>,
+    pub head_img_url: Option<String>,
+    pub big_head_img_url: Option<String>,
+    pub kind: ContactKind,
+    pub labels: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LabelInfo {
+    pub id: i64,
+    pub name: String,
+    /// 带有该标签的联系人数
+    pub count: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContactLabelsResponse {
+    pub labels: Vec<LabelInfo>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MsgCountRequest {
-    pub merge_path: String,
+    pub workspace_id: Option<String>,
+    pub merge_path: Option<String>,
     pub wxid: Option<String>,
 }
 
@@ -33,12 +64,18 @@ pub struct MsgCountResponse {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MsgListRequest {
-    pub merge_path: String,
+    pub workspace_id: Option<String>,
+    pub merge_path: Option<String>,
     pub wxid: String,
+    /// 按偏移分页（旧接口）；为0或指定了游标时按游标分页
+    #[serde(default)]
     pub start: i64,
     pub limit: i64,
     pub start_time: Option<i64>,
     pub end_time: Option<i64>,
+    /// 上一次响应中的`next_cursor`或`prev_cursor`
+    pub cursor: Option<String>,
+    pub direction: Option<PageDirection>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -46,6 +83,18 @@ pub struct MsgListResponse {
     pub messages: Vec<MessageResponse>,
     pub total: i64,
     pub user_list: HashMap<String, serde_json::Value>,
+    pub prev_cursor: Option<String>,
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MsgJumpRequest {
+    pub workspace_id: Option<String>,
+    pub merge_path: Option<String>,
+    pub wxid: String,
+    /// 跳转到的时间（Unix时间戳，秒）
+    pub timestamp: i64,
+    pub limit: i64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -61,15 +110,21 @@ pub struct MessageResponse {
     pub is_sender: i32,
     pub talker: String,
     pub str_talker: String,
+    pub sender_wxid: String,
     pub content: String,
     pub display_content: String,
     pub src: String,
     pub extra: serde_json::Value,
+    pub reply_to: Option<ReplyTo>,
+    pub event: Option<SystemEvent>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MsgSearchRequest {
-    pub merge_path: String,
+    pub workspace_id: Option<String>,
+    pub merge_path: Option<String>,
+    /// 搜索查询，如`from:wxid type:image after:2023-01-01 "短语" -排除词`
+    #[serde(alias = "query")]
     pub keyword: String,
     pub wxid: Option<String>,
     pub start_time: Option<i64>,
@@ -79,8 +134,81 @@ pub struct MsgSearchRequest {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MsgSearchResponse {
-    pub messages: Vec<MessageResponse>,
+    pub messages: Vec<SearchHitResponse>,
     pub total: i64,
     pub keyword: String,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchHitResponse {
+    #[serde(flatten)]
+    pub message: MessageResponse,
+    pub snippet: String,
+    /// 摘要中命中词的字符区间[start, end)
+    pub highlights: Vec<(usize, usize)>,
+    pub score: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MsgIndexRequest {
+    pub workspace_id: Option<String>,
+    pub merge_path: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MsgIndexResponse {
+    pub path: String,
+    pub added: usize,
+    pub indexed: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatEventsRequest {
+    pub workspace_id: Option<String>,
+    pub merge_path: Option<String>,
+    /// 事件类型：`revoke` `pat` `join` `leave`，为空时返回全部
+    #[serde(default)]
+    pub kinds: Vec<String>,
+    pub start_time: Option<i64>,
+    pub end_time: Option<i64>,
+    pub limit: Option<i64>,
+    /// 上一次响应中的`next_cursor`
+    pub cursor: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatEventsResponse {
+    pub wxid: String,
+    pub events: Vec<MessageResponse>,
+    pub total: i64,
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatroomResponse {
+    pub wxid: String,
+    pub owner: Option<String>,
+    pub self_display_name: Option<String>,
+    pub member_count: usize,
+    pub announcement: Option<Announcement>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatroomMemberInfo {
+    pub wxid: String,
+    pub room_nickname: Option<String>,
+    pub nickname: Option<String>,
+    pub remark: Option<String>,
+    pub head_img_url: Option<String>,
+    pub role: ChatroomRole,
+    /// 群昵称优先，其次备注、昵称
+    pub display_name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatroomMembersResponse {
+    pub wxid: String,
+    pub owner: Option<String>,
+    pub members: Vec<ChatroomMemberInfo>,
+    pub total: i64,
+}
@@ -1,29 +1,150 @@
 use axum::{Json, Router, routing::post};
 use std::path::PathBuf;
 
-use crate::core::decryption::decrypt_db;
-use crate::models::wx::{DecryptRequest, DecryptResponse};
+use crate::core::decryption::{batch_decrypt, decrypt_db_with_options, DecryptOptions};
+use crate::core::discovery::{decrypt_account, discover};
+use crate::db::workspace;
+use crate::models::wx::{
+    AccountDecryptRequest, AccountDecryptResponse, BatchDecryptRequest, BatchDecryptResponse,
+    CipherProfileParam, DecryptRequest, DecryptResponse, DiscoverRequest, DiscoverResponse,
+};
 use crate::utils::Result;
 
+// 每解密多少页输出一次进度日志
+const PROGRESS_LOG_INTERVAL: u64 = 10_000;
+
 pub fn router() -> Router {
-    Router::new().route("/api/wx/decrypt", post(decrypt_handler))
+    Router::new()
+        .route("/api/wx/decrypt", post(decrypt_handler))
+        .route("/api/wx/decrypt/batch", post(batch_decrypt_handler))
+        .route("/api/wx/decrypt/account", post(account_decrypt_handler))
+        .route("/api/wx/discover", post(discover_handler))
 }
 
 async fn decrypt_handler(Json(req): Json<DecryptRequest>) -> Result<Json<DecryptResponse>> {
     let db_path = PathBuf::from(&req.db_path);
     let out_path = PathBuf::from(&req.out_path);
+    let key = req.key.clone();
+    let profile = CipherProfileParam::resolve_optional(req.profile.as_ref())?;
+    let options = DecryptOptions {
+        on_corrupt: req.on_corrupt,
+        profile,
+        incremental: req.incremental,
+        wal: req.wal,
+    };
+    workspace::ensure_output_free(&out_path)?;
+
+    // 大文件解密耗时较长，放到阻塞线程池中执行
+    let result = tokio::task::spawn_blocking(move || {
+        let progress = |done: u64, total: u64| {
+            if done % PROGRESS_LOG_INTERVAL == 0 || done == total {
+                tracing::info!("Decrypting {:?}: {}/{} pages", db_path, done, total);
+            }
+        };
+        decrypt_db_with_options(&key, &db_path, &out_path, &options, Some(&progress))
+    })
+    .await
+    .map_err(|e| anyhow::anyhow!("Decryption task failed: {}", e))?;
 
-    match decrypt_db(&req.key, &db_path, &out_path) {
-        Ok(_) => Ok(Json(DecryptResponse {
+    match result {
+        Ok(report) => Ok(Json(DecryptResponse {
             success: true,
-            message: "Decryption successful".to_string(),
+            message: if report.is_clean() {
+                "Decryption successful".to_string()
+            } else {
+                format!(
+                    "Decryption finished with {} corrupt pages",
+                    report.corrupt_pages.len()
+                )
+            },
             out_path: Some(req.out_path),
+            report: Some(report),
         })),
         Err(e) => Ok(Json(DecryptResponse {
             success: false,
             message: format!("Decryption failed: {}", e),
             out_path: None,
+            report: None,
+        })),
+    }
+}
+
+async fn batch_decrypt_handler(
+    Json(req): Json<BatchDecryptRequest>,
+) -> Result<Json<BatchDecryptResponse>> {
+    let profile = CipherProfileParam::resolve_optional(req.profile.as_ref())?;
+    let db_paths: Vec<PathBuf> = req.db_paths.iter().map(PathBuf::from).collect();
+    let out_dir = PathBuf::from(&req.out_dir);
+    let key = req.key.clone();
+    let workers = req.workers;
+    workspace::ensure_output_free(&out_dir)?;
+
+    let result = tokio::task::spawn_blocking(move || {
+        batch_decrypt(&key, &db_paths, &out_dir, profile.as_ref(), workers)
+    })
+    .await
+    .map_err(|e| anyhow::anyhow!("Decryption task failed: {}", e))?;
+
+    match result {
+        Ok(results) => {
+            let succeeded = results.iter().filter(|r| r.is_success()).count();
+            Ok(Json(BatchDecryptResponse {
+                success: succeeded == results.len(),
+                message: format!("Decrypted {}/{} databases", succeeded, results.len()),
+                results,
+            }))
+        }
+        Err(e) => Ok(Json(BatchDecryptResponse {
+            success: false,
+            message: format!("Batch decryption failed: {}", e),
+            results: Vec::new(),
         })),
     }
 }
 
+async fn discover_handler(Json(req): Json<DiscoverRequest>) -> Result<Json<DiscoverResponse>> {
+    let wx_dir = PathBuf::from(&req.wx_dir);
+    let databases = tokio::task::spawn_blocking(move || discover(&wx_dir))
+        .await
+        .map_err(|e| anyhow::anyhow!("Discovery task failed: {}", e))??;
+
+    Ok(Json(DiscoverResponse {
+        success: true,
+        message: format!("Found {} databases", databases.len()),
+        databases,
+    }))
+}
+
+async fn account_decrypt_handler(
+    Json(req): Json<AccountDecryptRequest>,
+) -> Result<Json<AccountDecryptResponse>> {
+    let profile = CipherProfileParam::resolve_optional(req.profile.as_ref())?;
+    let wx_dir = PathBuf::from(&req.wx_dir);
+    let out_dir = PathBuf::from(&req.out_dir);
+    let key = req.key.clone();
+    let workers = req.workers;
+    workspace::ensure_output_free(&out_dir)?;
+
+    let result = tokio::task::spawn_blocking(move || {
+        decrypt_account(&key, &wx_dir, &out_dir, profile.as_ref(), workers)
+    })
+    .await
+    .map_err(|e| anyhow::anyhow!("Decryption task failed: {}", e))?;
+
+    match result {
+        Ok(manifest) => Ok(Json(AccountDecryptResponse {
+            success: manifest.failed == 0,
+            message: format!(
+                "Decrypted {}/{} databases",
+                manifest.succeeded,
+                manifest.databases.len()
+            ),
+            manifest: Some(manifest),
+        })),
+        Err(e) => Ok(Json(AccountDecryptResponse {
+            success: false,
+            message: format!("Account decryption failed: {}", e),
+            manifest: None,
+        })),
+    }
+}
@@ -19,7 +19,7 @@ pub async fn decrypt_db_handler(Json(req): Json<DecryptRequest>) -> Result<Json<
         path.to_string_lossy().to_string()
     });
 
-    match decrypt_db(&req.key, &db_path, &PathBuf::from(&output_path)) {
+    match decrypt_db(&req.key, &db_path, &PathBuf::from(&output_path), None) {
         Ok(_) => Ok(Json(DecryptResponse {
             success: true,
             message: format!("Database decrypted successfully to: {}", output_path),
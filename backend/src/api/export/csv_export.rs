@@ -1,3 +1,5 @@
+use crate::db::chatroom::ChatroomHandler;
+use crate::db::contact::{ContactHandler, DisplayNames};
 use crate::db::msg::MsgHandler;
 use crate::utils::Result;
 use std::fs::File;
@@ -9,6 +11,8 @@ pub struct CsvExporter;
 impl CsvExporter {
     pub fn export(
         handler: &MsgHandler,
+        contacts: Option<&ContactHandler>,
+        chatrooms: Option<&ChatroomHandler>,
         wxid: Option<&str>,
         start_time: Option<i64>,
         end_time: Option<i64>,
@@ -19,36 +23,19 @@ impl CsvExporter {
         // 写入CSV头部
         writeln!(file, "ID,时间,发送者,消息类型,内容,文件路径")?;
 
-        let mut start_index = 0;
-        let page_size = 1000;
+        let mut names = DisplayNames::new(contacts).with_chatrooms(chatrooms);
         let mut total_exported = 0;
 
-        loop {
-            let messages = handler.get_msg_list_with_users(
-                wxid,
-                start_index,
-                page_size,
-                start_time,
-                end_time,
-            )?;
-
-            if messages.0.is_empty() {
-                break;
-            }
-
-            for msg in messages.0 {
-                let talker = if msg.is_sender == 1 {
-                    "我".to_string()
-                } else {
-                    msg.talker.clone()
-                };
+        handler.for_each_page(wxid, start_time, end_time, |page| {
+            for msg in page {
+                let talker = names.sender_of(&msg);
 
                 writeln!(
                     file,
-                    "{},{},{},{},{},\"{}\"",
-                    msg.id,
+                    "{},{},\"{}\",{},{},\"{}\"",
+                    total_exported,
                     msg.create_time_str,
-                    talker,
+                    talker.replace("\"", "\"\""),
                     msg.type_name,
                     msg.content.replace("\"", "\"\""),
                     msg.src
@@ -56,9 +43,8 @@ impl CsvExporter {
 
                 total_exported += 1;
             }
-
-            start_index += page_size;
-        }
+            Ok(())
+        })?;
 
         Ok(format!("成功导出 {} 条消息到 {}", total_exported, output_path))
     }
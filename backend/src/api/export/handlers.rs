@@ -1,44 +1,68 @@
 use axum::Json;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::time::{SystemTime, UNIX_EPOCH};
 use std::fs;
 
+use crate::db::chatroom::ChatroomHandler;
+use crate::db::contact::ContactHandler;
 use crate::db::msg::MsgHandler;
+use crate::db::workspace;
 use crate::core::decryption::decrypt_db;
+use crate::core::encryption::encrypt_db;
+use crate::models::wx::CipherProfileParam;
 use crate::utils::{AppError, Result};
 use super::models::*;
 use super::csv_export::CsvExporter;
 use super::json_export::JsonExporter;
 use super::html_export::HtmlExporter;
 
-pub async fn export_csv(Json(req): Json<ExportRequest>) -> Result<Json<ExportResponse>> {
-    let db_path = PathBuf::from(&req.merge_path);
-    if !db_path.exists() {
-        return Ok(Json(ExportResponse {
-            success: false,
-            message: format!("Database not found: {}", req.merge_path),
-            file_path: None,
-        }));
-    }
+/// 各格式导出器的签名
+type ExportFn = fn(
+    &MsgHandler,
+    Option<&ContactHandler>,
+    Option<&ChatroomHandler>,
+    Option<&str>,
+    Option<i64>,
+    Option<i64>,
+    &str,
+) -> Result<String>;
 
-    let handler = MsgHandler::new(db_path.to_str().unwrap())?;
-    handler.add_indexes()?;
+/// 在阻塞线程池中导出聊天记录，导出失败时在响应中返回错误信息
+async fn export_messages(req: ExportRequest, extension: &str, export: ExportFn) -> Result<Json<ExportResponse>> {
+    let workspace = match workspace::resolve(req.workspace_id.as_deref(), req.merge_path.as_deref()) {
+        Ok(workspace) => workspace,
+        Err(e) => {
+            return Ok(Json(ExportResponse {
+                success: false,
+                message: e.to_string(),
+                file_path: None,
+            }));
+        }
+    };
+    workspace.msg()?;
 
     let output_path = req.output_path.unwrap_or_else(|| {
         let timestamp = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .unwrap()
             .as_secs();
-        format!("export_{}.csv", timestamp)
+        format!("export_{}.{}", timestamp, extension)
     });
 
-    match CsvExporter::export(
-        &handler,
-        req.wxid.as_deref(),
-        req.start_time,
-        req.end_time,
-        &output_path,
-    ) {
+    let path = output_path.clone();
+    let result = workspace::blocking(workspace, move |ws| {
+        export(
+            ws.msg()?,
+            ws.contact().ok(),
+            ws.chatroom().ok(),
+            req.wxid.as_deref(),
+            req.start_time,
+            req.end_time,
+            &path,
+        )
+    }).await;
+
+    match result {
         Ok(message) => Ok(Json(ExportResponse {
             success: true,
             message,
@@ -52,37 +76,57 @@ pub async fn export_csv(Json(req): Json<ExportRequest>) -> Result<Json<ExportRes
     }
 }
 
+pub async fn export_csv(Json(req): Json<ExportRequest>) -> Result<Json<ExportResponse>> {
+    export_messages(req, "csv", CsvExporter::export).await
+}
+
 pub async fn export_json(Json(req): Json<ExportRequest>) -> Result<Json<ExportResponse>> {
-    let db_path = PathBuf::from(&req.merge_path);
+    export_messages(req, "json", JsonExporter::export).await
+}
+
+pub async fn export_html(Json(req): Json<ExportRequest>) -> Result<Json<ExportResponse>> {
+    export_messages(req, "html", HtmlExporter::export).await
+}
+
+fn default_db_output(db_path: &Path, prefix: &str) -> String {
+    let file_name = db_path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("database.db");
+    db_path
+        .with_file_name(format!("{}{}", prefix, file_name))
+        .to_string_lossy()
+        .to_string()
+}
+
+pub async fn export_decrypted_db(Json(req): Json<ExportDbRequest>) -> Result<Json<ExportResponse>> {
+    let db_path = PathBuf::from(&req.db_path);
     if !db_path.exists() {
         return Ok(Json(ExportResponse {
             success: false,
-            message: format!("Database not found: {}", req.merge_path),
+            message: format!("Database not found: {}", req.db_path),
             file_path: None,
         }));
     }
 
-    let handler = MsgHandler::new(db_path.to_str().unwrap())?;
-    handler.add_indexes()?;
+    let profile = CipherProfileParam::resolve_optional(req.profile.as_ref())?;
+    let output_path = req
+        .output_path
+        .unwrap_or_else(|| default_db_output(&db_path, "de_"));
+    let out_path = PathBuf::from(&output_path);
+    let key = req.key;
+    workspace::ensure_output_free(&out_path)?;
 
-    let output_path = req.output_path.unwrap_or_else(|| {
-        let timestamp = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
-        format!("export_{}.json", timestamp)
-    });
+    let result = tokio::task::spawn_blocking(move || {
+        decrypt_db(&key, &db_path, &out_path, profile.as_ref())
+    })
+    .await
+    .map_err(|e| anyhow::anyhow!("Decryption task failed: {}", e))?;
 
-    match JsonExporter::export(
-        &handler,
-        req.wxid.as_deref(),
-        req.start_time,
-        req.end_time,
-        &output_path,
-    ) {
-        Ok(message) => Ok(Json(ExportResponse {
+    match result {
+        Ok(report) => Ok(Json(ExportResponse {
             success: true,
-            message,
+            message: format!("解密完成，共 {} 页", report.total_pages),
             file_path: Some(output_path),
         })),
         Err(e) => Ok(Json(ExportResponse {
@@ -93,37 +137,34 @@ pub async fn export_json(Json(req): Json<ExportRequest>) -> Result<Json<ExportRe
     }
 }
 
-pub async fn export_html(Json(req): Json<ExportRequest>) -> Result<Json<ExportResponse>> {
-    let db_path = PathBuf::from(&req.merge_path);
+pub async fn export_encrypted_db(Json(req): Json<ExportDbRequest>) -> Result<Json<ExportResponse>> {
+    let db_path = PathBuf::from(&req.db_path);
     if !db_path.exists() {
         return Ok(Json(ExportResponse {
             success: false,
-            message: format!("Database not found: {}", req.merge_path),
+            message: format!("Database not found: {}", req.db_path),
             file_path: None,
         }));
     }
 
-    let handler = MsgHandler::new(db_path.to_str().unwrap())?;
-    handler.add_indexes()?;
+    let profile = CipherProfileParam::resolve_optional(req.profile.as_ref())?;
+    let output_path = req
+        .output_path
+        .unwrap_or_else(|| default_db_output(&db_path, "en_"));
+    let out_path = PathBuf::from(&output_path);
+    let key = req.key;
 
-    let output_path = req.output_path.unwrap_or_else(|| {
-        let timestamp = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
-        format!("export_{}.html", timestamp)
-    });
+    // 加密后的数据库可被微信客户端直接读取
+    let result = tokio::task::spawn_blocking(move || {
+        encrypt_db(&key, &db_path, &out_path, profile.as_ref(), None)
+    })
+    .await
+    .map_err(|e| anyhow::anyhow!("Encryption task failed: {}", e))?;
 
-    match HtmlExporter::export(
-        &handler,
-        req.wxid.as_deref(),
-        req.start_time,
-        req.end_time,
-        &output_path,
-    ) {
-        Ok(message) => Ok(Json(ExportResponse {
+    match result {
+        Ok(pages) => Ok(Json(ExportResponse {
             success: true,
-            message,
+            message: format!("加密完成，共 {} 页", pages),
             file_path: Some(output_path),
         })),
         Err(e) => Ok(Json(ExportResponse {
@@ -133,4 +174,3 @@ pub async fn export_html(Json(req): Json<ExportRequest>) -> Result<Json<ExportRe
         })),
     }
 }
-
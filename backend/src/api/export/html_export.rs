@@ -1,4 +1,7 @@
+use crate::db::chatroom::ChatroomHandler;
+use crate::db::contact::{ContactHandler, DisplayNames};
 use crate::db::msg::MsgHandler;
+use crate::db::msg_content::ForwardedItem;
 use crate::utils::Result;
 use std::fs::File;
 use std::io::Write;
@@ -8,6 +11,8 @@ pub struct HtmlExporter;
 impl HtmlExporter {
     pub fn export(
         handler: &MsgHandler,
+        contacts: Option<&ContactHandler>,
+        chatrooms: Option<&ChatroomHandler>,
         wxid: Option<&str>,
         start_time: Option<i64>,
         end_time: Option<i64>,
@@ -30,56 +35,67 @@ impl HtmlExporter {
         .message-sender {{ color: #07c160; font-weight: bold; }}
         .message-receiver {{ color: #576b95; }}
         .message-time {{ color: #999; }}
+        .message-quote {{ color: #888; font-size: 13px; border-left: 3px solid #ddd; padding-left: 8px; margin-bottom: 6px; }}
+        .message-quote a {{ color: inherit; text-decoration: none; }}
+        .forwarded {{ border: 1px solid #eee; border-radius: 6px; padding: 6px 10px; margin-top: 6px; background: #fafafa; }}
+        .forwarded-item {{ margin: 4px 0; }}
+        .forwarded-sender {{ color: #576b95; font-size: 12px; }}
     </style>
 </head>
 <body>
     <h1>微信聊天记录</h1>
 "#)?;
 
-        let mut start_index = 0;
-        let page_size = 1000;
+        let mut names = DisplayNames::new(contacts).with_chatrooms(chatrooms);
         let mut total_exported = 0;
 
-        loop {
-            let (messages, _) = handler.get_msg_list_with_users(
-                wxid,
-                start_index,
-                page_size,
-                start_time,
-                end_time,
-            )?;
-
-            if messages.is_empty() {
-                break;
-            }
-
-            for msg in messages {
+        handler.for_each_page(wxid, start_time, end_time, |page| {
+            for msg in page {
                 let talker_class = if msg.is_sender == 1 {
                     "message-sender"
                 } else {
                     "message-receiver"
                 };
 
+                let quote = match &msg.reply_to {
+                    Some(reply) => {
+                        let name = if reply.display_name.is_empty() {
+                            names.name_in(&msg.str_talker, &reply.sender_wxid)
+                        } else {
+                            reply.display_name.clone()
+                        };
+                        let text = format!("{}：{}", html_escape(&name), html_escape(&reply.content));
+                        // 被引用消息在本库中时链接到对应的消息
+                        let text = match reply.local_id {
+                            Some(_) => format!(r##"<a href="#msg-{}">{}</a>"##, reply.msg_svr_id, text),
+                            None => text,
+                        };
+                        format!("\n        <div class=\"message-quote\">{}</div>", text)
+                    }
+                    None => String::new(),
+                };
+
                 writeln!(
                     file,
-                    r#"    <div class="message">
+                    r#"    <div class="message" id="msg-{}">
         <div class="message-header">
             <span class="{}">{}</span>
             <span class="message-time"> - {}</span>
-        </div>
+        </div>{}
         <div class="message-content">{}</div>
     </div>"#,
+                    msg.msg_svr_id,
                     talker_class,
-                    if msg.is_sender == 1 { "我" } else { &msg.talker },
+                    html_escape(&names.sender_of(&msg)),
                     msg.create_time_str,
-                    html_escape(&msg.content)
+                    quote,
+                    html_escape(&msg.content) + &render_forwarded(&msg.forwarded())
                 )?;
 
                 total_exported += 1;
             }
-
-            start_index += page_size;
-        }
+            Ok(())
+        })?;
 
         writeln!(file, r#"
 </body>
@@ -89,6 +105,25 @@ impl HtmlExporter {
     }
 }
 
+/// 合并转发的聊天记录渲染为嵌套的消息列表
+fn render_forwarded(items: &[ForwardedItem]) -> String {
+    if items.is_empty() {
+        return String::new();
+    }
+    let mut html = String::from(r#"<div class="forwarded">"#);
+    for item in items {
+        html.push_str(&format!(
+            r#"<div class="forwarded-item"><div class="forwarded-sender">{} {}</div><div>{}</div>{}</div>"#,
+            html_escape(&item.source_name),
+            html_escape(&item.source_time),
+            html_escape(&item.summary()),
+            render_forwarded(&item.forwarded)
+        ));
+    }
+    html.push_str("</div>");
+    html
+}
+
 fn html_escape(text: &str) -> String {
     text.replace("&", "&amp;")
         .replace("<", "&lt;")
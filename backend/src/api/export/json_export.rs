@@ -1,4 +1,7 @@
+use crate::db::chatroom::ChatroomHandler;
+use crate::db::contact::{ContactHandler, DisplayNames};
 use crate::db::msg::MsgHandler;
+use crate::db::msg_content::ForwardedItem;
 use crate::utils::Result;
 use std::fs::File;
 use std::io::Write;
@@ -9,31 +12,21 @@ pub struct JsonExporter;
 impl JsonExporter {
     pub fn export(
         handler: &MsgHandler,
+        contacts: Option<&ContactHandler>,
+        chatrooms: Option<&ChatroomHandler>,
         wxid: Option<&str>,
         start_time: Option<i64>,
         end_time: Option<i64>,
         output_path: &str,
     ) -> Result<String> {
         let mut messages = Vec::new();
-        let mut start_index = 0;
-        let page_size = 1000;
+        let mut names = DisplayNames::new(contacts).with_chatrooms(chatrooms);
 
-        loop {
-            let (msgs, _) = handler.get_msg_list_with_users(
-                wxid,
-                start_index,
-                page_size,
-                start_time,
-                end_time,
-            )?;
-
-            if msgs.is_empty() {
-                break;
-            }
-
-            for msg in msgs {
-                messages.push(json!({
-                    "id": msg.id,
+        handler.for_each_page(wxid, start_time, end_time, |page| {
+            for msg in page {
+                let forwarded = msg.forwarded();
+                let mut value = json!({
+                    "id": messages.len(),
                     "local_id": msg.local_id,
                     "msg_svr_id": msg.msg_svr_id,
                     "msg_type": msg.msg_type,
@@ -44,15 +37,22 @@ impl JsonExporter {
                     "is_sender": msg.is_sender,
                     "talker": msg.talker,
                     "str_talker": msg.str_talker,
+                    "sender_wxid": msg.sender_wxid,
+                    "sender_name": names.sender_of(&msg),
                     "content": msg.content,
                     "display_content": msg.display_content,
                     "src": msg.src,
                     "extra": msg.extra,
-                }));
+                    "reply_to": msg.reply_to,
+                    "event": msg.event,
+                });
+                if !forwarded.is_empty() {
+                    value["forwarded"] = json!(forwarded_json(&forwarded));
+                }
+                messages.push(value);
             }
-
-            start_index += page_size;
-        }
+            Ok(())
+        })?;
 
         let json_data = json!({
             "total": messages.len(),
@@ -67,3 +67,18 @@ impl JsonExporter {
     }
 }
 
+/// 合并转发的聊天记录展开为发送者、时间和内容
+fn forwarded_json(items: &[ForwardedItem]) -> Vec<serde_json::Value> {
+    items
+        .iter()
+        .map(|item| {
+            json!({
+                "sender_name": item.source_name,
+                "time": item.source_time,
+                "data_type": item.data_type,
+                "content": item.summary(),
+                "forwarded": forwarded_json(&item.forwarded),
+            })
+        })
+        .collect()
+}
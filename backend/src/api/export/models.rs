@@ -1,8 +1,11 @@
 use serde::{Deserialize, Serialize};
 
+use crate::models::wx::CipherProfileParam;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExportRequest {
-    pub merge_path: String,
+    pub workspace_id: Option<String>,
+    pub merge_path: Option<String>,
     pub wxid: Option<String>,
     pub start_time: Option<i64>,
     pub end_time: Option<i64>,
@@ -21,5 +24,8 @@ pub struct ExportDbRequest {
     pub db_path: String,
     pub key: String,
     pub output_path: Option<String>,
+    /// 加密参数，为空时自动识别
+    #[serde(default)]
+    pub profile: Option<CipherProfileParam>,
 }
 
@@ -1,17 +1,12 @@
 use axum::Json;
-use std::path::PathBuf;
 
-use crate::db::favorite::FavoriteHandler;
-use crate::utils::{AppError, Result};
+use crate::db::workspace;
+use crate::utils::Result;
 use super::models::*;
 
 pub async fn get_favorite_list(Json(req): Json<FavoriteListRequest>) -> Result<Json<FavoriteListResponse>> {
-    let db_path = PathBuf::from(&req.merge_path);
-    if !db_path.exists() {
-        return Err(AppError::NotFound(format!("Database not found: {}", req.merge_path)).into());
-    }
-
-    let handler = FavoriteHandler::new(db_path.to_str().unwrap())?;
+    let workspace = workspace::resolve(req.workspace_id.as_deref(), req.merge_path.as_deref())?;
+    let handler = workspace.favorite()?;
     let favorites = handler.get_favorite_list(req.start, req.limit, req.start_time, req.end_time)?;
     let total = handler.get_favorite_count()?;
 
@@ -36,12 +31,8 @@ pub async fn get_favorite_list(Json(req): Json<FavoriteListRequest>) -> Result<J
 }
 
 pub async fn get_favorite_count(Json(req): Json<FavoriteCountRequest>) -> Result<Json<FavoriteCountResponse>> {
-    let db_path = PathBuf::from(&req.merge_path);
-    if !db_path.exists() {
-        return Err(AppError::NotFound(format!("Database not found: {}", req.merge_path)).into());
-    }
-
-    let handler = FavoriteHandler::new(db_path.to_str().unwrap())?;
+    let workspace = workspace::resolve(req.workspace_id.as_deref(), req.merge_path.as_deref())?;
+    let handler = workspace.favorite()?;
     let count = handler.get_favorite_count()?;
 
     Ok(Json(FavoriteCountResponse { count }))
@@ -2,7 +2,8 @@ use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FavoriteListRequest {
-    pub merge_path: String,
+    pub workspace_id: Option<String>,
+    pub merge_path: Option<String>,
     pub start: i64,
     pub limit: i64,
     pub start_time: Option<i64>,
@@ -30,7 +31,8 @@ pub struct FavoriteItemResponse {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FavoriteCountRequest {
-    pub merge_path: String,
+    pub workspace_id: Option<String>,
+    pub merge_path: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -1,23 +1,43 @@
 use axum::{Json, extract::Path, response::Response, body::Body, http::{header, StatusCode}};
-use std::path::PathBuf;
 use tokio::fs;
 
-use crate::db::media::MediaHandler;
+use crate::db::media::{MediaHandler, MediaInfo};
+use crate::db::workspace;
 use crate::utils::{AppError, Result};
 use super::models::*;
 
+/// 依次查询工作区的各媒体分片，合并后按时间倒序截断
+fn collect_shards<F>(req: &MediaListRequest, query: F) -> Result<Vec<MediaInfo>>
+where
+    F: Fn(&MediaHandler) -> Result<Vec<MediaInfo>>,
+{
+    let workspace = workspace::resolve(req.workspace_id.as_deref(), req.media_db_path.as_deref())?;
+
+    let mut media_list = Vec::new();
+    for handler in workspace.media()? {
+        media_list.extend(query(handler)?);
+    }
+
+    media_list.sort_by(|a, b| b.create_time.cmp(&a.create_time));
+    if let Some(limit) = req.limit {
+        media_list.truncate(limit.max(0) as usize);
+    }
+    Ok(media_list)
+}
+
 pub async fn get_media_info(
     Path(msg_id): Path<i64>,
     Json(req): Json<MediaInfoRequest>
 ) -> Result<Json<MediaInfoResponse>> {
-    let db_path = PathBuf::from(&req.media_db_path);
-    if !db_path.exists() {
-        return Err(AppError::NotFound(format!("Media database not found: {}", req.media_db_path)).into());
+    let workspace = workspace::resolve(req.workspace_id.as_deref(), req.media_db_path.as_deref())?;
+    let mut media_info = None;
+    for handler in workspace.media()? {
+        media_info = handler.get_media_info(msg_id)?;
+        if media_info.is_some() {
+            break;
+        }
     }
 
-    let handler = MediaHandler::new(db_path.to_str().unwrap())?;
-    let media_info = handler.get_media_info(msg_id)?;
-
     if let Some(info) = media_info {
         Ok(Json(MediaInfoResponse {
             msg_id: info.msg_id,
@@ -35,13 +55,7 @@ pub async fn get_media_info(
 pub async fn get_media_list(
     Json(req): Json<MediaListRequest>
 ) -> Result<Json<MediaListResponse>> {
-    let db_path = PathBuf::from(&req.media_db_path);
-    if !db_path.exists() {
-        return Err(AppError::NotFound(format!("Media database not found: {}", req.media_db_path)).into());
-    }
-
-    let handler = MediaHandler::new(db_path.to_str().unwrap())?;
-    let media_list = handler.get_contact_media_list(&req.wxid, req.media_type, req.limit)?;
+    let media_list = collect_shards(&req, |handler| handler.get_contact_media_list(&req.wxid, req.media_type, req.limit))?;
 
     let response_list: Vec<MediaInfoResponse> = media_list.into_iter().map(|info| {
         MediaInfoResponse {
@@ -62,13 +76,7 @@ pub async fn get_media_list(
 pub async fn get_image_list(
     Json(req): Json<MediaListRequest>
 ) -> Result<Json<MediaListResponse>> {
-    let db_path = PathBuf::from(&req.media_db_path);
-    if !db_path.exists() {
-        return Err(AppError::NotFound(format!("Media database not found: {}", req.media_db_path)).into());
-    }
-
-    let handler = MediaHandler::new(db_path.to_str().unwrap())?;
-    let media_list = handler.get_image_list(&req.wxid, req.limit)?;
+    let media_list = collect_shards(&req, |handler| handler.get_image_list(&req.wxid, req.limit))?;
 
     let response_list: Vec<MediaInfoResponse> = media_list.into_iter().map(|info| {
         MediaInfoResponse {
@@ -89,13 +97,7 @@ pub async fn get_image_list(
 pub async fn get_video_list(
     Json(req): Json<MediaListRequest>
 ) -> Result<Json<MediaListResponse>> {
-    let db_path = PathBuf::from(&req.media_db_path);
-    if !db_path.exists() {
-        return Err(AppError::NotFound(format!("Media database not found: {}", req.media_db_path)).into());
-    }
-
-    let handler = MediaHandler::new(db_path.to_str().unwrap())?;
-    let media_list = handler.get_video_list(&req.wxid, req.limit)?;
+    let media_list = collect_shards(&req, |handler| handler.get_video_list(&req.wxid, req.limit))?;
 
     let response_list: Vec<MediaInfoResponse> = media_list.into_iter().map(|info| {
         MediaInfoResponse {
@@ -116,13 +118,7 @@ pub async fn get_video_list(
 pub async fn get_file_list(
     Json(req): Json<MediaListRequest>
 ) -> Result<Json<MediaListResponse>> {
-    let db_path = PathBuf::from(&req.media_db_path);
-    if !db_path.exists() {
-        return Err(AppError::NotFound(format!("Media database not found: {}", req.media_db_path)).into());
-    }
-
-    let handler = MediaHandler::new(db_path.to_str().unwrap())?;
-    let media_list = handler.get_file_list(&req.wxid, req.limit)?;
+    let media_list = collect_shards(&req, |handler| handler.get_file_list(&req.wxid, req.limit))?;
 
     let response_list: Vec<MediaInfoResponse> = media_list.into_iter().map(|info| {
         MediaInfoResponse {
@@ -2,13 +2,15 @@ use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MediaInfoRequest {
-    pub media_db_path: String,
+    pub workspace_id: Option<String>,
+    pub media_db_path: Option<String>,
     pub msg_id: i64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MediaListRequest {
-    pub media_db_path: String,
+    pub workspace_id: Option<String>,
+    pub media_db_path: Option<String>,
     pub wxid: String,
     pub media_type: Option<i32>,
     pub limit: Option<i64>,
@@ -12,6 +12,7 @@ mod favorite;
 mod moments;
 mod cleanup;
 mod tools;
+mod workspace;
 mod openapi;
 
 pub fn create_router(wx_offs: HashMap<String, Vec<u32>>) -> Router {
@@ -27,6 +28,7 @@ pub fn create_router(wx_offs: HashMap<String, Vec<u32>>) -> Router {
         .merge(moments::router())
         .merge(cleanup::router())
         .merge(tools::router())
+        .merge(workspace::router())
         .route("/health", axum::routing::get(health_check))
         .merge(openapi::swagger_router())
 }
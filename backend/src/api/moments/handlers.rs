@@ -1,17 +1,12 @@
 use axum::Json;
-use std::path::PathBuf;
 
-use crate::db::sns::SnsHandler;
-use crate::utils::{AppError, Result};
+use crate::db::workspace;
+use crate::utils::Result;
 use super::models::*;
 
 pub async fn get_moments_list(Json(req): Json<MomentsListRequest>) -> Result<Json<MomentsListResponse>> {
-    let db_path = PathBuf::from(&req.merge_path);
-    if !db_path.exists() {
-        return Err(AppError::NotFound(format!("Database not found: {}", req.merge_path)).into());
-    }
-
-    let handler = SnsHandler::new(db_path.to_str().unwrap())?;
+    let workspace = workspace::resolve(req.workspace_id.as_deref(), req.merge_path.as_deref())?;
+    let handler = workspace.sns()?;
     let moments = handler.get_moments_list(req.start, req.limit, req.start_time, req.end_time)?;
     let total = handler.get_moments_count()?;
 
@@ -33,12 +28,8 @@ pub async fn get_moments_list(Json(req): Json<MomentsListRequest>) -> Result<Jso
 }
 
 pub async fn get_moments_count(Json(req): Json<MomentsCountRequest>) -> Result<Json<MomentsCountResponse>> {
-    let db_path = PathBuf::from(&req.merge_path);
-    if !db_path.exists() {
-        return Err(AppError::NotFound(format!("Database not found: {}", req.merge_path)).into());
-    }
-
-    let handler = SnsHandler::new(db_path.to_str().unwrap())?;
+    let workspace = workspace::resolve(req.workspace_id.as_deref(), req.merge_path.as_deref())?;
+    let handler = workspace.sns()?;
     let count = handler.get_moments_count()?;
 
     Ok(Json(MomentsCountResponse { count }))
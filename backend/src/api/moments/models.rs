@@ -2,7 +2,8 @@ use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MomentsListRequest {
-    pub merge_path: String,
+    pub workspace_id: Option<String>,
+    pub merge_path: Option<String>,
     pub start: i64,
     pub limit: i64,
     pub start_time: Option<i64>,
@@ -27,7 +28,8 @@ pub struct MomentItemResponse {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MomentsCountRequest {
-    pub merge_path: String,
+    pub workspace_id: Option<String>,
+    pub merge_path: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
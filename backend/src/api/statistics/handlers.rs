@@ -1,29 +1,26 @@
 use axum::{Json, extract::Path};
 use std::collections::HashMap;
-use std::path::PathBuf;
 use regex::Regex;
 
-use crate::db::msg::MsgHandler;
-use crate::utils::{AppError, Result};
+use crate::db::workspace;
+use crate::utils::Result;
 use super::models::*;
 
 pub async fn get_contact_stat(
     Path(wxid): Path<String>,
     Json(req): Json<ContactStatRequest>
 ) -> Result<Json<ContactStatResponse>> {
-    let db_path = PathBuf::from(&req.merge_path);
-    if !db_path.exists() {
-        return Err(AppError::NotFound(format!("Database not found: {}", req.merge_path)).into());
-    }
-
-    let handler = MsgHandler::new(db_path.to_str().unwrap())?;
-    handler.add_indexes()?;
-
-    let counts = handler.get_msg_count(Some(&wxid))?;
-    let total = counts.get(&wxid).copied().unwrap_or(0);
-
-    // 获取日期统计
-    let date_stats = handler.get_date_count(Some(&wxid), req.start_time, req.end_time)?;
+    let workspace = workspace::resolve(req.workspace_id.as_deref(), req.merge_path.as_deref())?;
+    let query_wxid = wxid.clone();
+    let (total, date_stats) = workspace::blocking(workspace, move |ws| {
+        let handler = ws.msg()?;
+        let counts = handler.get_msg_count(Some(&query_wxid))?;
+        let total = counts.get(&query_wxid).copied().unwrap_or(0);
+
+        // 获取日期统计
+        let date_stats = handler.get_date_count(Some(&query_wxid), req.start_time, req.end_time)?;
+        Ok((total, date_stats))
+    }).await?;
 
     // 计算发送和接收数量
     let mut sender_count = 0;
@@ -49,15 +46,10 @@ pub async fn get_contact_stat(
 pub async fn get_date_chat_stat(
     Json(req): Json<DateChatStatRequest>
 ) -> Result<Json<DateChatStatResponse>> {
-    let db_path = PathBuf::from(&req.merge_path);
-    if !db_path.exists() {
-        return Err(AppError::NotFound(format!("Database not found: {}", req.merge_path)).into());
-    }
-
-    let handler = MsgHandler::new(db_path.to_str().unwrap())?;
-    handler.add_indexes()?;
-
-    let stats = handler.get_date_count(req.wxid.as_deref(), req.start_time, req.end_time)?;
+    let workspace = workspace::resolve(req.workspace_id.as_deref(), req.merge_path.as_deref())?;
+    let stats = workspace::blocking(workspace, move |ws| {
+        ws.msg()?.get_date_count(req.wxid.as_deref(), req.start_time, req.end_time)
+    }).await?;
 
     Ok(Json(DateChatStatResponse { stats }))
 }
@@ -65,15 +57,10 @@ pub async fn get_date_chat_stat(
 pub async fn get_date_heatmap(
     Json(req): Json<DateHeatmapRequest>
 ) -> Result<Json<DateHeatmapResponse>> {
-    let db_path = PathBuf::from(&req.merge_path);
-    if !db_path.exists() {
-        return Err(AppError::NotFound(format!("Database not found: {}", req.merge_path)).into());
-    }
-
-    let handler = MsgHandler::new(db_path.to_str().unwrap())?;
-    handler.add_indexes()?;
-
-    let stats = handler.get_date_count(req.wxid.as_deref(), req.start_time, req.end_time)?;
+    let workspace = workspace::resolve(req.workspace_id.as_deref(), req.merge_path.as_deref())?;
+    let stats = workspace::blocking(workspace, move |ws| {
+        ws.msg()?.get_date_count(req.wxid.as_deref(), req.start_time, req.end_time)
+    }).await?;
 
     let data: Vec<HeatmapData> = stats
         .into_iter()
@@ -92,40 +79,43 @@ pub async fn get_date_heatmap(
 pub async fn get_top_talkers(
     Json(req): Json<TopTalkersRequest>
 ) -> Result<Json<TopTalkersResponse>> {
-    let db_path = PathBuf::from(&req.merge_path);
-    if !db_path.exists() {
-        return Err(AppError::NotFound(format!("Database not found: {}", req.merge_path)).into());
-    }
-
-    let handler = MsgHandler::new(db_path.to_str().unwrap())?;
-    handler.add_indexes()?;
-
+    let workspace = workspace::resolve(req.workspace_id.as_deref(), req.merge_path.as_deref())?;
     let top = req.top.unwrap_or(10);
-    let talkers = handler.get_top_talkers(top, req.start_time, req.end_time)?;
+    let talkers = workspace::blocking(workspace, move |ws| {
+        ws.msg()?.get_top_talkers(top, req.start_time, req.end_time)
+    }).await?;
 
     Ok(Json(TopTalkersResponse { talkers }))
 }
 
+pub async fn get_money_ledger(
+    Path(wxid): Path<String>,
+    Json(req): Json<MoneyLedgerRequest>
+) -> Result<Json<MoneyLedgerResponse>> {
+    let workspace = workspace::resolve(req.workspace_id.as_deref(), req.merge_path.as_deref())?;
+    let ledger = workspace::blocking(workspace, move |ws| {
+        ws.msg()?.money_ledger(&wxid, req.start_time, req.end_time)
+    }).await?;
+
+    Ok(Json(MoneyLedgerResponse { ledger }))
+}
+
 pub async fn get_wordcloud(
     Path(wxid): Path<String>,
     Json(req): Json<WordcloudRequest>
 ) -> Result<Json<WordcloudResponse>> {
-    let db_path = PathBuf::from(&req.merge_path);
-    if !db_path.exists() {
-        return Err(AppError::NotFound(format!("Database not found: {}", req.merge_path)).into());
-    }
-
-    let handler = MsgHandler::new(db_path.to_str().unwrap())?;
-    handler.add_indexes()?;
+    let workspace = workspace::resolve(req.workspace_id.as_deref(), req.merge_path.as_deref())?;
 
     // 获取文本消息内容
-    let messages = handler.get_msg_list(
-        Some(&wxid),
-        req.start_time,
-        req.end_time,
-        None,
-        None,
-    )?;
+    let messages = workspace::blocking(workspace, move |ws| {
+        ws.msg()?.get_msg_list(
+            Some(&wxid),
+            req.start_time,
+            req.end_time,
+            None,
+            None,
+        )
+    }).await?;
 
     // 提取文本并统计词频
     let mut word_count: HashMap<String, i64> = HashMap::new();
@@ -1,9 +1,12 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+use crate::db::money::MoneyLedger;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ContactStatRequest {
-    pub merge_path: String,
+    pub workspace_id: Option<String>,
+    pub merge_path: Option<String>,
     pub wxid: String,
     pub start_time: Option<i64>,
     pub end_time: Option<i64>,
@@ -20,7 +23,8 @@ pub struct ContactStatResponse {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DateChatStatRequest {
-    pub merge_path: String,
+    pub workspace_id: Option<String>,
+    pub merge_path: Option<String>,
     pub wxid: Option<String>,
     pub start_time: Option<i64>,
     pub end_time: Option<i64>,
@@ -33,7 +37,8 @@ pub struct DateChatStatResponse {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DateHeatmapRequest {
-    pub merge_path: String,
+    pub workspace_id: Option<String>,
+    pub merge_path: Option<String>,
     pub wxid: Option<String>,
     pub start_time: Option<i64>,
     pub end_time: Option<i64>,
@@ -52,7 +57,8 @@ pub struct HeatmapData {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TopTalkersRequest {
-    pub merge_path: String,
+    pub workspace_id: Option<String>,
+    pub merge_path: Option<String>,
     pub top: Option<i64>,
     pub start_time: Option<i64>,
     pub end_time: Option<i64>,
@@ -65,7 +71,8 @@ pub struct TopTalkersResponse {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WordcloudRequest {
-    pub merge_path: String,
+    pub workspace_id: Option<String>,
+    pub merge_path: Option<String>,
     pub wxid: String,
     pub start_time: Option<i64>,
     pub end_time: Option<i64>,
@@ -83,3 +90,16 @@ pub struct WordData {
     pub count: i64,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MoneyLedgerRequest {
+    pub workspace_id: Option<String>,
+    pub merge_path: Option<String>,
+    pub start_time: Option<i64>,
+    pub end_time: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MoneyLedgerResponse {
+    #[serde(flatten)]
+    pub ledger: MoneyLedger,
+}
@@ -9,6 +9,7 @@ pub fn router() -> Router {
     Router::new()
         .route("/api/tools/bias", post(get_bias))
         .route("/api/tools/decrypt", post(decrypt_db))
+        .route("/api/tools/key/match", post(match_key))
         .route("/api/tools/merge", post(merge_db))
         .route("/api/tools/wxinfo", get(get_wxinfo))
 }
@@ -4,9 +4,12 @@ use std::path::PathBuf;
 use std::sync::Arc;
 
 use crate::config::{load_wx_offs, save_wx_offs};
-use crate::core::decryption::decrypt_db;
+use crate::core::decryption::{
+    decrypt_db, match_keys, scan_dump_for_keys, DumpScan, KeyMatch,
+};
 use crate::core::wx_info::get_wx_info;
 use crate::db::merge::merge_databases;
+use crate::db::workspace;
 use crate::utils::{AppError, Result};
 use super::models::*;
 
@@ -43,8 +46,9 @@ pub async fn decrypt_db(
             out_path: None,
         }));
     }
+    workspace::ensure_output_free(&out_path)?;
     
-    match decrypt_db(&req.key, &db_path, &out_path) {
+    match decrypt_db(&req.key, &db_path, &out_path, None) {
         Ok(_) => Ok(Json(DecryptDbResponse {
             success: true,
             message: "解密成功".to_string(),
@@ -58,6 +62,97 @@ pub async fn decrypt_db(
     }
 }
 
+pub async fn match_key(
+    Json(req): Json<KeyMatchRequest>,
+) -> Result<Json<KeyMatchResponse>> {
+    let mut db_paths: Vec<PathBuf> = req.db_paths.iter().map(PathBuf::from).collect();
+    if let Some(db_dir) = &req.db_dir {
+        let entries = std::fs::read_dir(db_dir)
+            .map_err(|e| AppError::NotFound(format!("目录不存在: {} ({})", db_dir, e)))?;
+        let mut found: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "db"))
+            .collect();
+        found.sort();
+        db_paths.extend(found);
+    }
+
+    if db_paths.is_empty() {
+        return Ok(Json(KeyMatchResponse {
+            success: false,
+            message: "数据库路径列表为空".to_string(),
+            matches: Vec::new(),
+            dump_candidates: 0,
+            dump_hits: Vec::new(),
+        }));
+    }
+
+    let mut keys = req.keys.clone();
+    let dump_path = req.dump_path.clone();
+    let explicit_dump_db = req.dump_db_path.is_some();
+    let dump_prefilter = req.dump_prefilter;
+    let dump_db_paths: Vec<PathBuf> = match &req.dump_db_path {
+        Some(path) => vec![PathBuf::from(path)],
+        None => db_paths.clone(),
+    };
+
+    // 逐个尝试PBKDF2耗时较长，放到阻塞线程池中执行
+    let result = tokio::task::spawn_blocking(move || -> Result<(Vec<KeyMatch>, DumpScan)> {
+        let mut dump_scan = DumpScan::default();
+        if let Some(dump_path) = dump_path {
+            let dump_path = PathBuf::from(dump_path);
+            if !dump_path.is_file() {
+                return Err(AppError::NotFound(format!("Memory dump not found: {:?}", dump_path)));
+            }
+            // 目录中排在前面的文件不一定属于该账号，找到匹配的密钥前依次尝试
+            for db_path in &dump_db_paths {
+                let scan = match scan_dump_for_keys(&dump_path, db_path, None, dump_prefilter) {
+                    Ok(scan) => scan,
+                    Err(e) if explicit_dump_db => return Err(e),
+                    Err(e) => {
+                        tracing::warn!("Skipping {:?} for dump key scan: {}", db_path, e);
+                        continue;
+                    }
+                };
+                dump_scan.candidates += scan.candidates;
+                dump_scan.hits = scan.hits;
+                if !dump_scan.hits.is_empty() {
+                    break;
+                }
+            }
+            for hit in &dump_scan.hits {
+                if !keys.contains(&hit.key) {
+                    keys.push(hit.key.clone());
+                }
+            }
+        }
+        let matches = match_keys(&keys, &db_paths)?;
+        Ok((matches, dump_scan))
+    })
+    .await
+    .map_err(|e| anyhow::anyhow!("Key match task failed: {}", e))?;
+
+    match result {
+        Ok((matches, dump_scan)) => {
+            let matched = matches.iter().filter(|m| m.key.is_some()).count();
+            Ok(Json(KeyMatchResponse {
+                success: matched > 0,
+                message: format!("{}/{} 个数据库找到匹配的密钥", matched, matches.len()),
+                matches,
+                dump_candidates: dump_scan.candidates,
+                dump_hits: dump_scan.hits,
+            }))
+        }
+        Err(e) => Ok(Json(KeyMatchResponse {
+            success: false,
+            message: format!("密钥匹配失败: {}", e),
+            matches: Vec::new(),
+            dump_candidates: 0,
+            dump_hits: Vec::new(),
+        })),
+    }
+}
+
 pub async fn merge_db(
     Json(req): Json<MergeDbRequest>,
 ) -> Result<Json<MergeDbResponse>> {
@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 
+use crate::core::decryption::{DumpKeyHit, KeyMatch};
+
 #[derive(Debug, Deserialize)]
 pub struct GetBiasRequest {
     pub version: String,
@@ -26,6 +28,34 @@ pub struct DecryptDbResponse {
     pub out_path: Option<String>,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct KeyMatchRequest {
+    /// 候选密钥（64位十六进制或`x'...'`原始密钥）
+    #[serde(default)]
+    pub keys: Vec<String>,
+    #[serde(default)]
+    pub db_paths: Vec<String>,
+    /// 目录下所有.db文件都参与匹配
+    pub db_dir: Option<String>,
+    /// 内存转储文件，扫描出的密钥校验通过后加入候选
+    pub dump_path: Option<String>,
+    /// 校验转储中密钥所用的数据库；为空时依次尝试各个数据库，直到找到匹配的密钥
+    pub dump_db_path: Option<String>,
+    /// 只校验看起来像随机数据的候选，转储较大时更快，但可能漏掉真实密钥
+    #[serde(default)]
+    pub dump_prefilter: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct KeyMatchResponse {
+    pub success: bool,
+    pub message: String,
+    pub matches: Vec<KeyMatch>,
+    /// 内存转储中参与校验的候选密钥数量
+    pub dump_candidates: usize,
+    pub dump_hits: Vec<DumpKeyHit>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct MergeDbRequest {
     pub db_paths: Vec<String>,
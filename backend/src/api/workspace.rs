@@ -0,0 +1,12 @@
+mod models;
+mod handlers;
+
+use axum::Router;
+use axum::routing::get;
+use handlers::*;
+
+pub fn router() -> Router {
+    Router::new()
+        .route("/api/workspace", get(list_workspaces).post(register_workspace))
+        .route("/api/workspace/:id", get(get_workspace).delete(remove_workspace))
+}
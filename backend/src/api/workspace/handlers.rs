@@ -0,0 +1,50 @@
+use axum::Json;
+use axum::extract::Path;
+use std::path::PathBuf;
+
+use crate::db::workspace;
+use crate::utils::{AppError, Result};
+use super::models::*;
+
+pub async fn register_workspace(Json(req): Json<RegisterWorkspaceRequest>) -> Result<Json<WorkspaceResponse>> {
+    let path = PathBuf::from(&req.path);
+    let merged_msg = req.merged_msg.map(PathBuf::from);
+
+    // 首次打开消息库会建立索引，放到阻塞线程池中执行
+    let workspace = tokio::task::spawn_blocking(move || {
+        workspace::register(&path, merged_msg.as_deref())
+    })
+    .await
+    .map_err(|e| anyhow::anyhow!("Workspace task failed: {}", e))??;
+
+    Ok(Json(WorkspaceResponse {
+        workspace: workspace.info().clone(),
+    }))
+}
+
+pub async fn list_workspaces() -> Result<Json<WorkspaceListResponse>> {
+    let workspaces = workspace::list()
+        .iter()
+        .map(|ws| ws.info().clone())
+        .collect();
+
+    Ok(Json(WorkspaceListResponse { workspaces }))
+}
+
+pub async fn get_workspace(Path(id): Path<String>) -> Result<Json<WorkspaceResponse>> {
+    let workspace = workspace::get(&id)?;
+
+    Ok(Json(WorkspaceResponse {
+        workspace: workspace.info().clone(),
+    }))
+}
+
+pub async fn remove_workspace(Path(id): Path<String>) -> Result<Json<RemoveWorkspaceResponse>> {
+    workspace::remove(&id)
+        .ok_or_else(|| AppError::NotFound(format!("Workspace not found: {}", id)))?;
+
+    Ok(Json(RemoveWorkspaceResponse {
+        success: true,
+        message: format!("Workspace {} removed", id),
+    }))
+}
@@ -0,0 +1,25 @@
+use serde::{Deserialize, Serialize};
+
+use crate::db::workspace::WorkspaceInfo;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegisterWorkspaceRequest {
+    pub path: String,
+    pub merged_msg: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkspaceResponse {
+    pub workspace: WorkspaceInfo,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkspaceListResponse {
+    pub workspaces: Vec<WorkspaceInfo>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoveWorkspaceResponse {
+    pub success: bool,
+    pub message: String,
+}
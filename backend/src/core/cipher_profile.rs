@@ -0,0 +1,119 @@
+use serde::{Deserialize, Serialize};
+
+/// 初始化向量长度（AES块大小）
+pub const IV_SIZE: usize = 16;
+/// SQLite允许的最大页大小
+const MAX_PAGE_SIZE: usize = 65536;
+
+/// PBKDF2与页HMAC使用的哈希算法
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HmacAlgorithm {
+    Sha1,
+    Sha512,
+}
+
+impl HmacAlgorithm {
+    /// HMAC输出长度
+    pub fn digest_size(&self) -> usize {
+        match self {
+            HmacAlgorithm::Sha1 => 20,
+            HmacAlgorithm::Sha512 => 64,
+        }
+    }
+}
+
+/// SQLCipher加密参数
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct CipherProfile {
+    pub name: String,
+    /// PBKDF2迭代次数
+    pub kdf_iter: u32,
+    pub hmac: HmacAlgorithm,
+    pub page_size: usize,
+    /// 每页末尾的保留区大小（IV + HMAC，按16字节对齐）
+    pub reserve_size: usize,
+}
+
+impl CipherProfile {
+    /// 微信3.x（SQLCipher 3）：PBKDF2-HMAC-SHA1，64000轮，保留区48字节
+    pub fn wechat_v3() -> Self {
+        Self {
+            name: "wechat_v3".to_string(),
+            kdf_iter: 64000,
+            hmac: HmacAlgorithm::Sha1,
+            page_size: 4096,
+            reserve_size: 48,
+        }
+    }
+
+    /// 微信4.x（SQLCipher 4默认参数）：HMAC-SHA512，256000轮，保留区80字节
+    pub fn wechat_v4() -> Self {
+        Self {
+            name: "wechat_v4".to_string(),
+            kdf_iter: 256000,
+            hmac: HmacAlgorithm::Sha512,
+            page_size: 4096,
+            reserve_size: 80,
+        }
+    }
+
+    /// 自动检测时依次尝试的预设
+    pub fn presets() -> Vec<Self> {
+        vec![Self::wechat_v3(), Self::wechat_v4()]
+    }
+
+    /// 根据名称查找预设
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::presets().into_iter().find(|p| p.name.eq_ignore_ascii_case(name))
+    }
+
+    /// HMAC在页内的起始位置
+    pub fn hmac_offset(&self) -> usize {
+        self.page_size - self.reserve_size + IV_SIZE
+    }
+
+    /// 校验参数是否自洽
+    pub fn is_valid(&self) -> bool {
+        self.page_size.is_power_of_two()
+            && self.page_size >= 512
+            && self.page_size <= MAX_PAGE_SIZE
+            && self.reserve_size >= IV_SIZE + self.hmac.digest_size()
+            && self.reserve_size.is_multiple_of(IV_SIZE)
+            && self.reserve_size < self.page_size
+    }
+}
+
+impl Default for CipherProfile {
+    fn default() -> Self {
+        Self::wechat_v3()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_presets_are_valid() {
+        for profile in CipherProfile::presets() {
+            assert!(profile.is_valid(), "{} should be valid", profile.name);
+        }
+        assert_eq!(CipherProfile::wechat_v3().hmac_offset(), 4064);
+    }
+
+    #[test]
+    fn test_page_size_bounds() {
+        let mut profile = CipherProfile::wechat_v3();
+        profile.page_size = MAX_PAGE_SIZE;
+        assert!(profile.is_valid());
+        profile.page_size = 1 << 30;
+        assert!(!profile.is_valid());
+    }
+
+    #[test]
+    fn test_from_name() {
+        assert_eq!(CipherProfile::from_name("WeChat_V4"), Some(CipherProfile::wechat_v4()));
+        assert!(CipherProfile::from_name("unknown").is_none());
+    }
+}
@@ -1,25 +1,438 @@
-use crate::utils::Result;
+use crate::core::cipher_profile::{CipherProfile, HmacAlgorithm, IV_SIZE};
+use crate::core::wal;
+use crate::utils::{cache::Cache, validation, AppError, Result};
 use anyhow::Context;
 use aes::Aes256;
-use cbc::cipher::{BlockDecryptMut, KeyIvInit};
+use cbc::cipher::{block_padding::NoPadding, BlockDecryptMut, BlockEncryptMut, KeyIvInit};
 use hmac::{Hmac, Mac};
 use pbkdf2::pbkdf2_hmac;
+use serde::{Deserialize, Serialize};
 use sha1::Sha1;
+use sha2::Sha512;
+use std::collections::HashSet;
 use std::fs;
-use std::fs::File;
-use std::io::Write;
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{LazyLock, Mutex};
+use std::time::Instant;
 
-const SQLITE_FILE_HEADER: &[u8] = b"SQLite format 3\x00";
+pub(crate) const SQLITE_FILE_HEADER: &[u8] = b"SQLite format 3\x00";
 const KEY_SIZE: usize = 32;
-const PAGE_SIZE: usize = 4096;
+pub(crate) const SALT_SIZE: usize = 16;
+// 读写缓冲区大小
+pub(crate) const IO_BUFFER_SIZE: usize = 256 * 1024;
 
-pub fn decrypt_db(key: &str, db_path: &Path, out_path: &Path) -> Result<()> {
+type Aes256CbcDec = cbc::Decryptor<Aes256>;
+type Aes256CbcEnc = cbc::Encryptor<Aes256>;
+
+// 增量解密清单中每页记录的HMAC字节数
+const MANIFEST_MAC_SIZE: usize = 8;
+
+// 口令密钥派生结果缓存时间（秒）
+const DERIVED_KEY_TTL_SECS: u64 = 3600;
+
+/// 派生缓存键：（口令密钥, salt, 加密参数）
+type DerivedKeyId = ([u8; KEY_SIZE], Vec<u8>, CipherProfile);
+
+/// 口令密钥的PBKDF2派生结果，同一salt只计算一次
+static DERIVED_KEYS: LazyLock<Cache<DerivedKeyId, [u8; KEY_SIZE]>> =
+    LazyLock::new(|| Cache::new(DERIVED_KEY_TTL_SECS));
+
+/// 解密进度回调，参数为（已完成页数, 总页数）
+pub type ProgressCallback<'a> = &'a dyn Fn(u64, u64);
+
+/// 页HMAC校验失败时的处理方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CorruptPageMode {
+    /// 遇到损坏页立即终止
+    #[default]
+    Fail,
+    /// 照常写入解密结果（保持页对齐），仅记录到报告中
+    /// 损坏页的内容是无法校验的数据，SQLite可能会报告输出文件已损坏，
+    /// 适合用工具逐页恢复数据；需要能直接打开的数据库时用`ZeroFill`
+    Skip,
+    /// 以全零页代替损坏页
+    ZeroFill,
+}
+
+/// `-wal`文件的处理方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WalMode {
+    /// 忽略WAL，只解密主数据库
+    Ignore,
+    /// 在输出旁写出解密后的`-wal`，打开数据库时由SQLite应用
+    #[default]
+    Decrypt,
+    /// 解密后执行检查点，把WAL合并进输出数据库
+    Checkpoint,
+}
+
+/// 解密选项
+#[derive(Debug, Clone, Default)]
+pub struct DecryptOptions {
+    pub on_corrupt: CorruptPageMode,
+    /// 加密参数，为空时按预设自动检测
+    pub profile: Option<CipherProfile>,
+    /// 增量解密：输出文件和清单都存在时只重新解密变化的页，并更新清单
+    pub incremental: bool,
+    pub wal: WalMode,
+}
+
+/// HMAC校验失败的页
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CorruptPage {
+    /// 页号（从1开始）
+    pub page_no: u64,
+    /// 页在加密文件中的偏移
+    pub offset: u64,
+}
+
+/// 解密结果报告
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DecryptReport {
+    /// 实际使用的加密参数名称
+    pub profile: String,
+    pub total_pages: u64,
+    pub corrupt_pages: Vec<CorruptPage>,
+    pub mode: CorruptPageMode,
+    /// 实际解密写入的页数，增量解密时只包含变化的页
+    #[serde(default)]
+    pub updated_pages: u64,
+    /// 是否基于上次的清单增量解密
+    #[serde(default)]
+    pub incremental: bool,
+    /// 解密的WAL帧数
+    #[serde(default)]
+    pub wal_frames: u64,
+    /// WAL是否已合并进输出数据库
+    #[serde(default)]
+    pub wal_checkpointed: bool,
+}
+
+impl DecryptReport {
+    /// 所有页均通过校验
+    pub fn is_clean(&self) -> bool {
+        self.corrupt_pages.is_empty()
+    }
+}
+
+/// 计算HMAC，`parts`按顺序拼接
+fn compute_hmac(algorithm: HmacAlgorithm, key: &[u8], parts: &[&[u8]]) -> Result<Vec<u8>> {
+    match algorithm {
+        HmacAlgorithm::Sha1 => {
+            let mut mac = Hmac::<Sha1>::new_from_slice(key)
+                .map_err(|e| anyhow::anyhow!("Failed to create HMAC: {}", e))?;
+            for part in parts {
+                mac.update(part);
+            }
+            Ok(mac.finalize().into_bytes().to_vec())
+        }
+        HmacAlgorithm::Sha512 => {
+            let mut mac = Hmac::<Sha512>::new_from_slice(key)
+                .map_err(|e| anyhow::anyhow!("Failed to create HMAC: {}", e))?;
+            for part in parts {
+                mac.update(part);
+            }
+            Ok(mac.finalize().into_bytes().to_vec())
+        }
+    }
+}
+
+fn pbkdf2(algorithm: HmacAlgorithm, password: &[u8], salt: &[u8], rounds: u32) -> [u8; KEY_SIZE] {
+    let mut out = [0u8; KEY_SIZE];
+    match algorithm {
+        HmacAlgorithm::Sha1 => pbkdf2_hmac::<Sha1>(password, salt, rounds, &mut out),
+        HmacAlgorithm::Sha512 => pbkdf2_hmac::<Sha512>(password, salt, rounds, &mut out),
+    }
+    out
+}
+
+/// 由密钥和salt派生出的页密钥
+pub(crate) struct PageKeys {
+    enc_key: [u8; KEY_SIZE],
+    mac_key: [u8; KEY_SIZE],
+    profile: CipherProfile,
+}
+
+impl PageKeys {
+    /// 由任意形式的密钥得到页密钥，口令密钥的派生结果按salt缓存
+    /// 口令密钥优先使用缓存的派生结果；新派生的结果不写入缓存，校验通过后由`cache_derived`写入
+    pub(crate) fn from_key(key: &DecryptKey, salt: &[u8], profile: &CipherProfile) -> Self {
+        match key {
+            DecryptKey::Passphrase(password) => {
+                let cache_key = (*password, salt.to_vec(), profile.clone());
+                let enc_key = DERIVED_KEYS
+                    .get(&cache_key)
+                    .unwrap_or_else(|| pbkdf2(profile.hmac, password, salt, profile.kdf_iter));
+                Self::from_enc_key(enc_key, salt, profile)
+            }
+            DecryptKey::Raw { key, .. } => Self::from_enc_key(*key, salt, profile),
+        }
+    }
+
+    /// 缓存已通过校验的口令密钥派生结果，候选密钥中的无效密钥不会占用缓存
+    fn cache_derived(&self, key: &DecryptKey, salt: &[u8]) {
+        if let DecryptKey::Passphrase(password) = key {
+            DERIVED_KEYS.set((*password, salt.to_vec(), self.profile.clone()), self.enc_key);
+        }
+    }
+
+    /// 已派生的加密密钥只需再计算mac_key（2轮）
+    fn from_enc_key(enc_key: [u8; KEY_SIZE], salt: &[u8], profile: &CipherProfile) -> Self {
+        let mac_salt: Vec<u8> = salt.iter().map(|&b| b ^ 58).collect();
+        let mac_key = pbkdf2(profile.hmac, &enc_key, &mac_salt, 2);
+
+        Self {
+            enc_key,
+            mac_key,
+            profile: profile.clone(),
+        }
+    }
+
+    pub(crate) fn page_size(&self) -> usize {
+        self.profile.page_size
+    }
+
+    /// 页内保存的HMAC前缀，密文变化时随之变化
+    fn stored_mac<'a>(&self, page: &'a [u8]) -> &'a [u8] {
+        let mac_start = self.profile.hmac_offset();
+        &page[mac_start..mac_start + MANIFEST_MAC_SIZE]
+    }
+
+    /// 校验单页的HMAC（页数据 + 页号）
+    pub(crate) fn verify_page(&self, page: &[u8], page_no: u32) -> Result<bool> {
+        let offset = if page_no == 1 { SALT_SIZE } else { 0 };
+        let mac_start = self.profile.hmac_offset();
+        let mac_size = self.profile.hmac.digest_size();
+
+        let computed_mac = compute_hmac(
+            self.profile.hmac,
+            &self.mac_key,
+            &[&page[offset..mac_start], &page_no.to_le_bytes()],
+        )?;
+
+        Ok(computed_mac.as_slice() == &page[mac_start..mac_start + mac_size])
+    }
+
+    /// 解密单页，返回与原页等长的明文页
+    pub(crate) fn decrypt_page(&self, page: &[u8], page_no: u32) -> Result<Vec<u8>> {
+        let offset = if page_no == 1 { SALT_SIZE } else { 0 };
+        let reserve_start = self.profile.page_size - self.profile.reserve_size;
+        let iv = &page[reserve_start..reserve_start + IV_SIZE];
+
+        let mut decrypted = Vec::with_capacity(self.profile.page_size);
+        if page_no == 1 {
+            decrypted.extend_from_slice(SQLITE_FILE_HEADER);
+        }
+        decrypted.extend_from_slice(&page[offset..reserve_start]);
+
+        let cipher = Aes256CbcDec::new_from_slices(&self.enc_key, iv)
+            .map_err(|e| anyhow::anyhow!("Failed to create decryptor: {}", e))?;
+        cipher
+            .decrypt_padded_mut::<NoPadding>(&mut decrypted[offset..])
+            .map_err(|e| anyhow::anyhow!("Decryption failed: {}", e))?;
+
+        // 保留区（IV + HMAC）原样写回，保证页大小与SQLite头中的reserve一致
+        decrypted.extend_from_slice(&page[reserve_start..]);
+        Ok(decrypted)
+    }
+
+    /// 加密单页（`decrypt_page`的逆过程），第一页的SQLite文件头替换为salt
+    pub(crate) fn encrypt_page(
+        &self,
+        plain: &[u8],
+        page_no: u32,
+        salt: &[u8],
+        iv: &[u8; IV_SIZE],
+    ) -> Result<Vec<u8>> {
+        let offset = if page_no == 1 { SALT_SIZE } else { 0 };
+        let reserve_start = self.profile.page_size - self.profile.reserve_size;
+        let mac_start = self.profile.hmac_offset();
+        let mac_size = self.profile.hmac.digest_size();
+
+        let mut page = vec![0u8; self.profile.page_size];
+        if page_no == 1 {
+            page[..SALT_SIZE].copy_from_slice(salt);
+        }
+        page[offset..reserve_start].copy_from_slice(&plain[offset..reserve_start]);
+
+        let cipher = Aes256CbcEnc::new_from_slices(&self.enc_key, iv)
+            .map_err(|e| anyhow::anyhow!("Failed to create encryptor: {}", e))?;
+        cipher
+            .encrypt_padded_mut::<NoPadding>(&mut page[offset..reserve_start], reserve_start - offset)
+            .map_err(|e| anyhow::anyhow!("Encryption failed: {}", e))?;
+        page[reserve_start..reserve_start + IV_SIZE].copy_from_slice(iv);
+
+        let mac = compute_hmac(
+            self.profile.hmac,
+            &self.mac_key,
+            &[&page[offset..mac_start], &page_no.to_le_bytes()],
+        )?;
+        page[mac_start..mac_start + mac_size].copy_from_slice(&mac);
+
+        Ok(page)
+    }
+}
+
+/// 解密密钥
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecryptKey {
+    /// 从微信内存中读取的密钥（64位十六进制），需按salt做PBKDF2派生
+    Passphrase([u8; KEY_SIZE]),
+    /// 已派生的页密钥，SQLCipher字面量`x'<64 hex>'`或`x'<64 hex><32 hex salt>'`
+    Raw {
+        key: [u8; KEY_SIZE],
+        salt: Option<[u8; SALT_SIZE]>,
+    },
+}
+
+impl DecryptKey {
+    /// 解析密钥字符串：64位十六进制为口令密钥，`x'...'`为原始密钥
+    pub fn parse(key: &str) -> Result<Self> {
+        let key = key.trim();
+
+        let literal = key
+            .strip_prefix("x'")
+            .or_else(|| key.strip_prefix("X'"))
+            .and_then(|k| k.strip_suffix('\''));
+
+        match literal {
+            Some(literal) => {
+                let bytes = hex::decode(literal).with_context(|| "Failed to decode raw key")?;
+                match bytes.len() {
+                    KEY_SIZE => Ok(DecryptKey::Raw {
+                        key: to_array(&bytes),
+                        salt: None,
+                    }),
+                    n if n == KEY_SIZE + SALT_SIZE => Ok(DecryptKey::Raw {
+                        key: to_array(&bytes[..KEY_SIZE]),
+                        salt: Some(to_array(&bytes[KEY_SIZE..])),
+                    }),
+                    _ => Err(AppError::ValidationFailed(
+                        "Raw key must be x'<64 hex>' or x'<64 hex><32 hex salt>'".to_string(),
+                    )),
+                }
+            }
+            None => {
+                if key.len() != KEY_SIZE * 2 {
+                    return Err(anyhow::anyhow!("Key length must be 64 hex characters").into());
+                }
+                let bytes = hex::decode(key).with_context(|| "Failed to decode hex key")?;
+                Ok(DecryptKey::Passphrase(to_array(&bytes)))
+            }
+        }
+    }
+
+    /// 原始密钥附带的salt
+    pub fn salt(&self) -> Option<&[u8; SALT_SIZE]> {
+        match self {
+            DecryptKey::Raw { salt, .. } => salt.as_ref(),
+            DecryptKey::Passphrase(_) => None,
+        }
+    }
+}
+
+fn to_array<const N: usize>(bytes: &[u8]) -> [u8; N] {
+    let mut out = [0u8; N];
+    out.copy_from_slice(bytes);
+    out
+}
+
+/// 读取数据库开头足够容纳任一候选参数第一页的数据
+fn read_first_page(db_path: &Path, candidates: &[CipherProfile]) -> Result<Vec<u8>> {
+    let max_page_size = candidates.iter().map(|p| p.page_size).max().unwrap_or(0);
+    let mut head = Vec::with_capacity(max_page_size);
+    File::open(db_path)
+        .with_context(|| format!("Failed to open database: {:?}", db_path))?
+        .take(max_page_size as u64)
+        .read_to_end(&mut head)
+        .with_context(|| format!("Failed to read first page: {:?}", db_path))?;
+    Ok(head)
+}
+
+/// 用第一页的HMAC依次验证候选参数，返回第一个验证通过的页密钥
+fn derive_verified_keys(
+    key: &DecryptKey,
+    first_page: &[u8],
+    candidates: &[CipherProfile],
+) -> Result<PageKeys> {
+    if let Some(salt) = key.salt() {
+        if first_page.len() < SALT_SIZE || &first_page[..SALT_SIZE] != salt {
+            return Err(AppError::DecryptionFailed(
+                "Raw key salt does not match database salt".to_string(),
+            ));
+        }
+    }
+
+    for profile in candidates {
+        if !profile.is_valid() {
+            return Err(AppError::ValidationFailed(format!(
+                "Invalid cipher profile: {}",
+                profile.name
+            )));
+        }
+        if first_page.len() < profile.page_size {
+            continue;
+        }
+
+        let salt = &first_page[..SALT_SIZE];
+        let keys = PageKeys::from_key(key, salt, profile);
+        if keys.verify_page(&first_page[..profile.page_size], 1)? {
+            keys.cache_derived(key, salt);
+            return Ok(keys);
+        }
+    }
+
+    Err(AppError::DecryptionFailed(
+        "Key verification failed - incorrect key or unsupported cipher profile".to_string(),
+    ))
+}
+
+/// 检测数据库使用的加密参数
+pub fn detect_profile(key: &str, db_path: &Path) -> Result<CipherProfile> {
+    let key = DecryptKey::parse(key)?;
+    let candidates = CipherProfile::presets();
+    let first_page = read_first_page(db_path, &candidates)?;
+    let keys = derive_verified_keys(&key, &first_page, &candidates)?;
+    Ok(keys.profile)
+}
+
+/// 解密数据库，`profile`为空时自动检测加密参数
+pub fn decrypt_db(
+    key: &str,
+    db_path: &Path,
+    out_path: &Path,
+    profile: Option<&CipherProfile>,
+) -> Result<DecryptReport> {
+    let options = DecryptOptions {
+        profile: profile.cloned(),
+        ..Default::default()
+    };
+    decrypt_db_with_options(key, db_path, out_path, &options, None)
+}
+
+/// 逐页流式解密数据库，内存占用与文件大小无关
+/// 每一页都会校验HMAC，损坏页按`options.on_corrupt`处理并记录到报告中
+/// `options.incremental`为true时对比上次的清单，只重新解密密文变化的页
+/// 数据库旁有`-wal`文件时按`options.wal`解密或合并
+pub fn decrypt_db_with_options(
+    key: &str,
+    db_path: &Path,
+    out_path: &Path,
+    options: &DecryptOptions,
+    progress: Option<ProgressCallback>,
+) -> Result<DecryptReport> {
     // 验证输入
     if !db_path.exists() || !db_path.is_file() {
         return Err(anyhow::anyhow!("Database file not found: {:?}", db_path).into());
     }
 
+    validation::validate_output_path(db_path, out_path)?;
+
     if let Some(parent) = out_path.parent() {
         if !parent.exists() {
             fs::create_dir_all(parent)
@@ -27,177 +440,1151 @@ pub fn decrypt_db(key: &str, db_path: &Path, out_path: &Path) -> Result<()> {
         }
     }
 
-    if key.len() != 64 {
-        return Err(anyhow::anyhow!("Key length must be 64 hex characters").into());
-    }
+    let key = DecryptKey::parse(key)?;
 
-    // 读取加密数据库
-    let encrypted_data = fs::read(db_path)
-        .with_context(|| format!("Failed to read database: {:?}", db_path))?;
+    let candidates = match &options.profile {
+        Some(profile) => vec![profile.clone()],
+        None => CipherProfile::presets(),
+    };
 
-    if encrypted_data.len() < 16 {
+    // 读取第一页，提取salt并验证密钥
+    let first_page = read_first_page(db_path, &candidates)?;
+    let min_page_size = candidates.iter().map(|p| p.page_size).min().unwrap_or(0);
+    if first_page.len() < min_page_size {
         return Err(anyhow::anyhow!("Database file too small").into());
     }
 
-    // 提取salt和第一页
-    let salt = &encrypted_data[0..16];
-    let first_page = &encrypted_data[16..4096];
+    let keys = derive_verified_keys(&key, &first_page, &candidates)?;
+    let page_size = keys.page_size() as u64;
+
+    let file = File::open(db_path)
+        .with_context(|| format!("Failed to open database: {:?}", db_path))?;
+    let file_size = file
+        .metadata()
+        .with_context(|| format!("Failed to read metadata: {:?}", db_path))?
+        .len();
 
-    if salt.len() != 16 {
-        return Err(anyhow::anyhow!("Invalid salt size").into());
+    // 最后一页不完整时无法解密，直接忽略
+    let total_pages = file_size / page_size;
+    if file_size % page_size != 0 {
+        tracing::warn!(
+            "Database {:?} has a trailing partial page ({} bytes), ignored",
+            db_path,
+            file_size % page_size
+        );
     }
 
-    // 解析密钥
-    let password = hex::decode(key.trim())
-        .with_context(|| "Failed to decode hex key")?;
+    let mut report = DecryptReport {
+        profile: keys.profile.name.clone(),
+        total_pages,
+        corrupt_pages: Vec::new(),
+        mode: options.on_corrupt,
+        updated_pages: 0,
+        incremental: false,
+        wal_frames: 0,
+        wal_checkpointed: false,
+    };
 
-    // 计算mac_salt
-    let mac_salt: Vec<u8> = salt.iter().map(|&b| b ^ 58).collect();
+    let salt = hex::encode(&first_page[..SALT_SIZE]);
+    let manifest_file = manifest_path(out_path);
+    let previous = if options.incremental {
+        load_manifest(&manifest_file, &keys.profile, &salt, out_path)
+    } else {
+        // 全量解密会改写输出，旧清单不再描述其内容，留着会让之后的增量解密跳过已变化的页
+        let _ = fs::remove_file(&manifest_file);
+        None
+    };
 
-    // PBKDF2 派生密钥
-    let mut byte_hmac = [0u8; KEY_SIZE];
-    pbkdf2_hmac::<Sha1>(&password, salt, 64000, &mut byte_hmac);
+    // 不论是否处理WAL，都先删除输出旁的旧WAL，否则SQLite打开时会把旧帧应用到新文件上
+    wal::remove_sidecars(out_path);
 
-    // 计算mac_key
-    let mut mac_key = [0u8; KEY_SIZE];
-    pbkdf2_hmac::<Sha1>(&byte_hmac, &mac_salt, 2, &mut mac_key);
+    let mut reader = BufReader::with_capacity(IO_BUFFER_SIZE, file);
+    let mut macs = match previous {
+        Some(previous) => {
+            report.incremental = true;
+            decrypt_changed_pages(&keys, &mut reader, out_path, &previous, &mut report, progress)?
+        }
+        None => {
+            let output = File::create(out_path)
+                .with_context(|| format!("Failed to create output file: {:?}", out_path))?;
+            let mut writer = BufWriter::with_capacity(IO_BUFFER_SIZE, output);
+
+            let result = decrypt_pages(&keys, &mut reader, &mut writer, &mut report, progress);
+
+            let macs = match result {
+                Ok(macs) => macs,
+                Err(e) => {
+                    // 失败时删除不完整的输出文件
+                    drop(writer);
+                    let _ = fs::remove_file(out_path);
+                    return Err(e);
+                }
+            };
+
+            writer
+                .flush()
+                .with_context(|| format!("Failed to flush output file: {:?}", out_path))?;
+            macs
+        }
+    };
+
+    if options.wal != WalMode::Ignore {
+        let wal_pages = wal::decrypt_wal(&keys, db_path, out_path)?;
+        report.wal_frames = wal_pages.len() as u64;
+
+        if options.wal == WalMode::Checkpoint && !wal_pages.is_empty() {
+            wal::checkpoint(out_path)?;
+            report.wal_checkpointed = true;
+
+            // 检查点改写了这些页（以及文件头），下次增量解密时需要重新解密
+            for page_no in wal_pages.into_iter().chain([1]) {
+                let idx = (page_no as usize).saturating_sub(1) * MANIFEST_MAC_SIZE;
+                if let Some(mac) = macs.get_mut(idx..idx + MANIFEST_MAC_SIZE) {
+                    mac.fill(0);
+                }
+            }
+        }
+    }
 
-    // 验证MAC
-    let mut mac = Hmac::<Sha1>::new_from_slice(&mac_key)
-        .map_err(|e| anyhow::anyhow!("Failed to create HMAC: {}", e))?;
-    mac.update(&encrypted_data[16..4064]);
-    mac.update(b"\x01\x00\x00\x00");
-    let computed_mac = mac.finalize().into_bytes();
+    if options.incremental {
+        let manifest = PageManifest {
+            profile: keys.profile.name.clone(),
+            salt,
+            page_size: keys.page_size(),
+            macs: hex::encode(macs),
+        };
+        save_manifest(&manifest_file, &manifest)?;
+    }
 
-    let stored_mac = &first_page[4064 - 32 - 12..4064 - 12];
-    if computed_mac.as_slice() != stored_mac {
-        return Err(anyhow::anyhow!("Key verification failed - incorrect key").into());
+    if !report.is_clean() {
+        tracing::warn!(
+            "Database {:?} has {} corrupt pages (mode: {:?})",
+            db_path,
+            report.corrupt_pages.len(),
+            report.mode
+        );
     }
 
-    // 解密数据库
-    let mut output = File::create(out_path)
-        .with_context(|| format!("Failed to create output file: {:?}", out_path))?;
+    Ok(report)
+}
 
-    // 写入SQLite文件头
-    output.write_all(SQLITE_FILE_HEADER)
-        .with_context(|| "Failed to write SQLite header")?;
+/// 校验并解密单页，返回要写入输出文件的明文页
+/// 损坏页按`report.mode`处理并记录到报告中
+fn process_page(
+    keys: &PageKeys,
+    page: &[u8],
+    page_no: u64,
+    report: &mut DecryptReport,
+) -> Result<Vec<u8>> {
+    // 第一页已在密钥验证时校验过
+    if page_no > 1 && !keys.verify_page(page, page_no as u32)? {
+        let corrupt = CorruptPage {
+            page_no,
+            offset: (page_no - 1) * keys.page_size() as u64,
+        };
 
-    // 解密每一页
-    let total_pages = (encrypted_data.len() + PAGE_SIZE - 1) / PAGE_SIZE;
-    
-    for page_num in 0..total_pages {
-        let page_start = if page_num == 0 { 16 } else { page_num * PAGE_SIZE };
-        let page_end = ((page_num + 1) * PAGE_SIZE).min(encrypted_data.len());
-        
-        if page_start >= encrypted_data.len() {
-            break;
+        match report.mode {
+            CorruptPageMode::Fail => {
+                return Err(AppError::DecryptionFailed(format!(
+                    "Page {} failed HMAC verification (offset {})",
+                    corrupt.page_no, corrupt.offset
+                )));
+            }
+            CorruptPageMode::Skip => {
+                // 保留解密出的原始字节供恢复工具分析，输出不保证是有效的数据库
+                report.corrupt_pages.push(corrupt);
+            }
+            CorruptPageMode::ZeroFill => {
+                report.corrupt_pages.push(corrupt);
+                return Ok(vec![0u8; keys.page_size()]);
+            }
         }
+    }
 
-        let page_data = &encrypted_data[page_start..page_end];
-        
-        if page_data.len() < 48 {
-            // 最后一页可能不足4096字节
-            output.write_all(page_data)?;
-            break;
+    keys.decrypt_page(page, page_no as u32)
+}
+
+/// 逐页校验并解密，返回每页HMAC前缀（用于增量解密清单）
+fn decrypt_pages<R: Read, W: Write>(
+    keys: &PageKeys,
+    reader: &mut R,
+    writer: &mut W,
+    report: &mut DecryptReport,
+    progress: Option<ProgressCallback>,
+) -> Result<Vec<u8>> {
+    let total_pages = report.total_pages;
+    let mut page = vec![0u8; keys.page_size()];
+    let mut macs = Vec::with_capacity(total_pages as usize * MANIFEST_MAC_SIZE);
+
+    for page_no in 1..=total_pages {
+        reader
+            .read_exact(&mut page)
+            .with_context(|| format!("Failed to read page {}", page_no))?;
+
+        let corrupt_before = report.corrupt_pages.len();
+        let decrypted = process_page(keys, &page, page_no, report)?;
+        writer
+            .write_all(&decrypted)
+            .with_context(|| format!("Failed to write page {}", page_no))?;
+        report.updated_pages += 1;
+        record_mac(&mut macs, keys, &page, report.corrupt_pages.len() > corrupt_before);
+
+        if let Some(callback) = progress {
+            callback(page_no, total_pages);
         }
+    }
 
-        // 提取IV和加密数据
-        let iv = &page_data[page_data.len() - 48..page_data.len() - 32];
-        let encrypted_page = &page_data[..page_data.len() - 48];
-        let footer = &page_data[page_data.len() - 48..];
+    Ok(macs)
+}
 
-        // 解密 - 简化实现，使用AES-256-CBC
-        type Aes256CbcDec = cbc::Decryptor<Aes256>;
-        let cipher = Aes256CbcDec::new_from_slices(&byte_hmac, iv)
-            .map_err(|e| anyhow::anyhow!("Failed to create decryptor: {}", e))?;
+/// 与上次的清单对比，只解密HMAC发生变化的页并原地写回
+fn decrypt_changed_pages<R: Read>(
+    keys: &PageKeys,
+    reader: &mut R,
+    out_path: &Path,
+    previous: &[u8],
+    report: &mut DecryptReport,
+    progress: Option<ProgressCallback>,
+) -> Result<Vec<u8>> {
+    let total_pages = report.total_pages;
+    let page_size = keys.page_size();
+    let mut page = vec![0u8; page_size];
+    let mut macs = Vec::with_capacity(total_pages as usize * MANIFEST_MAC_SIZE);
 
-        // 解密数据（需要对齐到16字节）
-        let mut decrypted = encrypted_page.to_vec();
-        let padding = (16 - (decrypted.len() % 16)) % 16;
-        decrypted.extend(vec![0u8; padding]);
+    let mut output = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(out_path)
+        .with_context(|| format!("Failed to open output file: {:?}", out_path))?;
 
-        cipher.decrypt_padded_mut::<cbc::cipher::block_padding::Pkcs7>(&mut decrypted)
-            .map_err(|e| anyhow::anyhow!("Decryption failed: {}", e))?;
+    for page_no in 1..=total_pages {
+        reader
+            .read_exact(&mut page)
+            .with_context(|| format!("Failed to read page {}", page_no))?;
+
+        let idx = (page_no - 1) as usize * MANIFEST_MAC_SIZE;
+        let unchanged = previous.get(idx..idx + MANIFEST_MAC_SIZE) == Some(keys.stored_mac(&page));
 
-        // 移除填充
-        if decrypted.len() > encrypted_page.len() {
-            decrypted.truncate(encrypted_page.len());
+        if unchanged {
+            macs.extend_from_slice(keys.stored_mac(&page));
+        } else {
+            let corrupt_before = report.corrupt_pages.len();
+            let decrypted = process_page(keys, &page, page_no, report)?;
+            output
+                .seek(SeekFrom::Start((page_no - 1) * page_size as u64))
+                .and_then(|_| output.write_all(&decrypted))
+                .with_context(|| format!("Failed to write page {}", page_no))?;
+            report.updated_pages += 1;
+            record_mac(&mut macs, keys, &page, report.corrupt_pages.len() > corrupt_before);
         }
 
-        output.write_all(&decrypted)?;
-        output.write_all(footer)?;
+        if let Some(callback) = progress {
+            callback(page_no, total_pages);
+        }
     }
 
+    // 数据库变小时截掉多余的页
+    output
+        .set_len(total_pages * page_size as u64)
+        .with_context(|| format!("Failed to truncate output file: {:?}", out_path))?;
+
+    Ok(macs)
+}
+
+/// 记录页HMAC前缀，损坏页记为全零，下次增量解密时会重新处理
+fn record_mac(macs: &mut Vec<u8>, keys: &PageKeys, page: &[u8], corrupt: bool) {
+    if corrupt {
+        macs.extend_from_slice(&[0u8; MANIFEST_MAC_SIZE]);
+    } else {
+        macs.extend_from_slice(keys.stored_mac(page));
+    }
+}
+
+/// 增量解密清单，记录上次解密时每页的HMAC
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PageManifest {
+    profile: String,
+    /// 数据库salt（十六进制），重新加密后salt会变化
+    salt: String,
+    page_size: usize,
+    /// 每页HMAC的前8字节依次拼接（十六进制）
+    macs: String,
+}
+
+/// 清单文件路径：`<输出文件>.manifest.json`
+pub fn manifest_path(out_path: &Path) -> PathBuf {
+    let mut path = out_path.as_os_str().to_owned();
+    path.push(".manifest.json");
+    PathBuf::from(path)
+}
+
+/// 读取上次的清单，参数或输出文件与清单不一致时返回None（需全量解密）
+fn load_manifest(
+    manifest_file: &Path,
+    profile: &CipherProfile,
+    salt: &str,
+    out_path: &Path,
+) -> Option<Vec<u8>> {
+    let content = fs::read_to_string(manifest_file).ok()?;
+    let manifest: PageManifest = match serde_json::from_str(&content) {
+        Ok(manifest) => manifest,
+        Err(e) => {
+            tracing::warn!("Ignoring invalid manifest {:?}: {}", manifest_file, e);
+            return None;
+        }
+    };
+
+    if manifest.profile != profile.name
+        || manifest.salt != salt
+        || manifest.page_size != profile.page_size
+    {
+        return None;
+    }
+
+    let macs = hex::decode(&manifest.macs).ok()?;
+    let pages = (macs.len() / MANIFEST_MAC_SIZE) as u64;
+    let out_size = fs::metadata(out_path).ok()?.len();
+    if out_size != pages * profile.page_size as u64 {
+        return None;
+    }
+
+    Some(macs)
+}
+
+fn save_manifest(manifest_file: &Path, manifest: &PageManifest) -> Result<()> {
+    let content = serde_json::to_string(manifest)
+        .with_context(|| "Failed to serialize manifest")?;
+    fs::write(manifest_file, content)
+        .with_context(|| format!("Failed to write manifest: {:?}", manifest_file))?;
     Ok(())
 }
 
+/// 批量解密中单个文件的状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchJobStatus {
+    Success,
+    Failed,
+}
+
+/// 批量解密失败原因分类
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchErrorKind {
+    /// 文件不存在
+    NotFound,
+    /// 密钥或加密参数不匹配
+    KeyMismatch,
+    /// 页HMAC校验失败
+    CorruptPage,
+    /// 参数或文件格式不合法
+    InvalidInput,
+    /// 读写文件失败
+    Io,
+    Other,
+}
+
+/// 批量解密中单个文件的结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchJobResult {
+    pub db_path: PathBuf,
+    pub out_path: PathBuf,
+    pub status: BatchJobStatus,
+    pub error_kind: Option<BatchErrorKind>,
+    pub error: Option<String>,
+    /// 耗时（毫秒）
+    pub elapsed_ms: u64,
+    pub total_pages: u64,
+    pub corrupt_pages: usize,
+    /// 输出文件大小（字节）
+    pub output_size: u64,
+    pub profile: Option<String>,
+}
+
+impl BatchJobResult {
+    pub fn is_success(&self) -> bool {
+        self.status == BatchJobStatus::Success
+    }
+}
+
+/// 根据错误类型归类失败原因
+fn classify_error(err: &AppError) -> BatchErrorKind {
+    match err {
+        AppError::DecryptionFailed(msg) if msg.contains("HMAC verification") => {
+            BatchErrorKind::CorruptPage
+        }
+        AppError::DecryptionFailed(_) => BatchErrorKind::KeyMismatch,
+        AppError::ValidationFailed(_) => BatchErrorKind::InvalidInput,
+        AppError::Internal(e) if e.chain().any(|c| c.is::<std::io::Error>()) => {
+            BatchErrorKind::Io
+        }
+        _ => BatchErrorKind::Other,
+    }
+}
+
+/// 默认并发数：CPU核数，最多8个
+fn default_workers() -> usize {
+    std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+        .min(8)
+}
+
+/// 解密单个文件并记录结果
+fn run_batch_job(
+    key: &str,
+    db_path: &Path,
+    out_path: &Path,
+    options: &DecryptOptions,
+) -> BatchJobResult {
+    let started = Instant::now();
+
+    let mut result = BatchJobResult {
+        db_path: db_path.to_path_buf(),
+        out_path: out_path.to_path_buf(),
+        status: BatchJobStatus::Failed,
+        error_kind: None,
+        error: None,
+        elapsed_ms: 0,
+        total_pages: 0,
+        corrupt_pages: 0,
+        output_size: 0,
+        profile: None,
+    };
+
+    let outcome = if !db_path.is_file() {
+        Err(AppError::NotFound(format!("Database file not found: {:?}", db_path)))
+    } else {
+        decrypt_db_with_options(key, db_path, out_path, options, None)
+    };
+
+    match outcome {
+        Ok(report) => {
+            result.status = BatchJobStatus::Success;
+            result.total_pages = report.total_pages;
+            result.corrupt_pages = report.corrupt_pages.len();
+            result.output_size = fs::metadata(out_path).map(|m| m.len()).unwrap_or(0);
+            result.profile = Some(report.profile);
+        }
+        Err(e) => {
+            tracing::error!("Failed to decrypt {:?}: {}", db_path, e);
+            result.error_kind = Some(match &e {
+                AppError::NotFound(_) => BatchErrorKind::NotFound,
+                other => classify_error(other),
+            });
+            result.error = Some(e.to_string());
+        }
+    }
+
+    result.elapsed_ms = started.elapsed().as_millis() as u64;
+    result
+}
+
+/// 并发批量解密，输出文件名为`de_<原文件名>`
+/// 返回与`db_paths`顺序一致的逐文件结果；`workers`为0时按CPU核数决定并发数
 pub fn batch_decrypt(
     key: &str,
     db_paths: &[PathBuf],
     out_dir: &Path,
-) -> Result<Vec<(PathBuf, PathBuf)>> {
+    profile: Option<&CipherProfile>,
+    workers: usize,
+) -> Result<Vec<BatchJobResult>> {
     if !out_dir.exists() {
         fs::create_dir_all(out_dir)
             .with_context(|| format!("Failed to create output directory: {:?}", out_dir))?;
     }
 
-    let mut results = Vec::new();
+    let jobs: Vec<(PathBuf, PathBuf)> = db_paths
+        .iter()
+        .map(|db_path| {
+            let file_name = db_path
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default();
+            (db_path.clone(), out_dir.join(format!("de_{}", file_name)))
+        })
+        .collect();
+
+    decrypt_jobs(key, &jobs, profile, workers)
+}
+
+/// 并发解密（数据库, 输出路径）列表，返回与`jobs`顺序一致的逐文件结果
+pub fn decrypt_jobs(
+    key: &str,
+    jobs: &[(PathBuf, PathBuf)],
+    profile: Option<&CipherProfile>,
+    workers: usize,
+) -> Result<Vec<BatchJobResult>> {
+    // 密钥格式错误时所有文件都会失败，直接返回
+    DecryptKey::parse(key)?;
+
+    // 同一账号的数据库使用相同的加密参数，检测一次后复用
+    let profile = match profile {
+        Some(profile) => Some(profile.clone()),
+        None => jobs
+            .iter()
+            .map(|(db_path, _)| db_path)
+            .filter(|p| p.is_file())
+            .find_map(|p| detect_profile(key, p).ok()),
+    };
+    let options = DecryptOptions {
+        profile,
+        ..Default::default()
+    };
 
-    for db_path in db_paths {
-        let file_name = db_path
-            .file_name()
-            .and_then(|n| n.to_str())
-            .ok_or_else(|| anyhow::anyhow!("Invalid file name"))?;
+    Ok(parallel_map(jobs, workers, |(db_path, out_path)| {
+        run_batch_job(key, db_path, out_path, &options)
+    }))
+}
 
-        let out_path = out_dir.join(format!("de_{}", file_name));
+/// 用固定数量的工作线程并发处理`items`，结果顺序与输入一致
+/// `workers`为0时按CPU核数决定并发数
+fn parallel_map<T, R, F>(items: &[T], workers: usize, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let workers = if workers == 0 { default_workers() } else { workers };
+    let workers = workers.min(items.len()).max(1);
 
-        match decrypt_db(key, db_path, &out_path) {
-            Ok(_) => {
-                results.push((db_path.clone(), out_path));
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<R>>> = Mutex::new((0..items.len()).map(|_| None).collect());
+
+    std::thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                let idx = next.fetch_add(1, Ordering::Relaxed);
+                let Some(item) = items.get(idx) else {
+                    break;
+                };
+                let result = f(item);
+                if let Ok(mut results) = results.lock() {
+                    results[idx] = Some(result);
+                }
+            });
+        }
+    });
+
+    results
+        .into_inner()
+        .unwrap_or_else(|e| e.into_inner())
+        .into_iter()
+        .flatten()
+        .collect()
+}
+
+/// 候选密钥与数据库的匹配结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyMatch {
+    pub db_path: PathBuf,
+    /// 能打开该数据库的候选密钥，为空表示没有匹配
+    pub key: Option<String>,
+    pub profile: Option<String>,
+}
+
+/// 用每个候选密钥验证每个数据库第一页的HMAC，返回数据库与密钥的对应关系
+pub fn match_keys(candidates: &[String], db_paths: &[PathBuf]) -> Result<Vec<KeyMatch>> {
+    let keys: Vec<(&String, DecryptKey)> = candidates
+        .iter()
+        .filter_map(|text| match DecryptKey::parse(text) {
+            Ok(key) => Some((text, key)),
+            Err(e) => {
+                tracing::warn!("Skipping invalid candidate key: {}", e);
+                None
             }
+        })
+        .collect();
+
+    if keys.is_empty() {
+        return Err(AppError::ValidationFailed(
+            "No valid candidate keys".to_string(),
+        ));
+    }
+
+    let presets = CipherProfile::presets();
+
+    Ok(parallel_map(db_paths, 0, |db_path| {
+        let mut result = KeyMatch {
+            db_path: db_path.clone(),
+            key: None,
+            profile: None,
+        };
+
+        let first_page = match read_first_page(db_path, &presets) {
+            Ok(page) => page,
             Err(e) => {
-                tracing::error!("Failed to decrypt {:?}: {}", db_path, e);
+                tracing::warn!("Failed to read {:?}: {}", db_path, e);
+                return result;
+            }
+        };
+
+        for (text, key) in &keys {
+            if let Ok(page_keys) = derive_verified_keys(key, &first_page, &presets) {
+                result.key = Some(text.to_string());
+                result.profile = Some(page_keys.profile.name.clone());
+                break;
+            }
+        }
+
+        result
+    }))
+}
+
+/// 内存转储中验证通过的密钥
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DumpKeyHit {
+    /// 密钥在转储文件中首次出现的偏移
+    pub offset: u64,
+    pub key: String,
+    pub profile: String,
+}
+
+// 扫描内存转储时每次读取的大小（需为KEY_SIZE的整数倍）
+const DUMP_CHUNK_SIZE: usize = 4 * 1024 * 1024;
+// 候选去重集合的上限（约32MB）
+const MAX_SEEN_CANDIDATES: usize = 1 << 20;
+
+/// 粗略判断32字节是否像随机密钥，过滤掉指针、字符串和填充数据
+/// 真实密钥也可能被误判，只在调用方要求预筛时使用
+fn looks_like_key(bytes: &[u8]) -> bool {
+    let mut seen = [false; 256];
+    let mut distinct = 0;
+    let mut high = 0;
+    for &b in bytes {
+        if !seen[b as usize] {
+            seen[b as usize] = true;
+            distinct += 1;
+        }
+        if b >= 0x80 {
+            high += 1;
+        }
+    }
+    distinct >= 24 && high >= 4
+}
+
+/// 尽量读满缓冲区，返回实际读取的字节数
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        let n = reader
+            .read(&mut buf[filled..])
+            .with_context(|| "Failed to read memory dump")?;
+        if n == 0 {
+            break;
+        }
+        filled += n;
+    }
+    Ok(filled)
+}
+
+/// 内存转储的扫描结果
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DumpScan {
+    /// 实际参与校验的候选密钥数量
+    pub candidates: usize,
+    /// 每个加密参数最多一条，按偏移排序
+    pub hits: Vec<DumpKeyHit>,
+}
+
+/// 扫描内存转储文件中32字节对齐的候选密钥，找出能通过`db_path`第一页HMAC校验的密钥
+///
+/// 候选按读取块逐块校验，每种加密参数找到第一个密钥后不再尝试，全部找到后提前结束
+/// 默认校验每个对齐位置的候选；`prefilter`为true时跳过不像随机数据的候选，速度更快但可能漏掉密钥
+pub fn scan_dump_for_keys(
+    dump_path: &Path,
+    db_path: &Path,
+    profile: Option<&CipherProfile>,
+    prefilter: bool,
+) -> Result<DumpScan> {
+    if !dump_path.is_file() {
+        return Err(AppError::NotFound(format!("Memory dump not found: {:?}", dump_path)));
+    }
+
+    let mut profiles = match profile {
+        Some(profile) => vec![profile.clone()],
+        None => CipherProfile::presets(),
+    };
+    let first_page = read_first_page(db_path, &profiles)?;
+    if first_page.len() < SALT_SIZE {
+        return Err(anyhow::anyhow!("Database file too small").into());
+    }
+    let salt = &first_page[..SALT_SIZE];
+    profiles.retain(|profile| first_page.len() >= profile.page_size);
+
+    let file = File::open(dump_path)
+        .with_context(|| format!("Failed to open memory dump: {:?}", dump_path))?;
+    let mut reader = BufReader::with_capacity(IO_BUFFER_SIZE, file);
+    let mut buf = vec![0u8; DUMP_CHUNK_SIZE];
+    let mut base = 0u64;
+    // 已校验过的候选，超过上限后清空，重复候选最多多算一次
+    let mut seen: HashSet<[u8; KEY_SIZE]> = HashSet::new();
+    let mut scan = DumpScan::default();
+
+    while !profiles.is_empty() {
+        let n = read_full(&mut reader, &mut buf)?;
+        let mut candidates: Vec<([u8; KEY_SIZE], u64)> = Vec::new();
+        for (idx, chunk) in buf[..n].chunks_exact(KEY_SIZE).enumerate() {
+            if prefilter && !looks_like_key(chunk) {
+                continue;
+            }
+            if seen.len() >= MAX_SEEN_CANDIDATES {
+                seen.clear();
+            }
+            let candidate = to_array(chunk);
+            if seen.insert(candidate) {
+                candidates.push((candidate, base + (idx * KEY_SIZE) as u64));
             }
         }
+        scan.candidates += candidates.len();
+
+        // 候选密钥大多是无效数据，不写入派生缓存
+        let results = parallel_map(&candidates, 0, |(password, offset)| {
+            profiles
+                .iter()
+                .filter(|profile| {
+                    let enc_key = pbkdf2(profile.hmac, password, salt, profile.kdf_iter);
+                    let keys = PageKeys::from_enc_key(enc_key, salt, profile);
+                    matches!(keys.verify_page(&first_page[..profile.page_size], 1), Ok(true))
+                })
+                .map(|profile| DumpKeyHit {
+                    offset: *offset,
+                    key: hex::encode(password),
+                    profile: profile.name.clone(),
+                })
+                .collect::<Vec<_>>()
+        });
+        // 结果按偏移排列，每种参数只保留块内第一个
+        for hit in results.into_iter().flatten() {
+            if let Some(pos) = profiles.iter().position(|p| p.name == hit.profile) {
+                profiles.remove(pos);
+                scan.hits.push(hit);
+            }
+        }
+
+        base += n as u64;
+        if n < buf.len() {
+            break;
+        }
     }
 
-    Ok(results)
+    tracing::info!(
+        "Checked {} candidate keys from {:?}, {} matched",
+        scan.candidates,
+        dump_path,
+        scan.hits.len()
+    );
+
+    scan.hits.sort_by_key(|hit| hit.offset);
+    Ok(scan)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::cell::Cell;
     use tempfile::TempDir;
 
+    const TEST_KEY: &str = "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
+    const PAGE_SIZE: usize = 4096;
+    const RESERVE_SIZE: usize = 48;
+
+    /// 按微信格式加密明文页，生成测试用的加密数据库
+    fn encrypt_fixture(key: &str, plain: &[u8], profile: &CipherProfile) -> Vec<u8> {
+        let key = DecryptKey::parse(key).unwrap();
+        let salt = [7u8; SALT_SIZE];
+        let keys = PageKeys::from_key(&key, &salt, profile);
+
+        let mut encrypted = Vec::new();
+        for (idx, chunk) in plain.chunks(profile.page_size).enumerate() {
+            let page_no = idx as u32 + 1;
+            let iv = [page_no as u8; IV_SIZE];
+            let page = keys.encrypt_page(chunk, page_no, &salt, &iv).unwrap();
+            encrypted.extend_from_slice(&page);
+        }
+        encrypted
+    }
+
+    fn sample_plain(pages: usize) -> Vec<u8> {
+        let mut plain: Vec<u8> = (0..PAGE_SIZE * pages).map(|i| (i % 251) as u8).collect();
+        plain[..SALT_SIZE].copy_from_slice(SQLITE_FILE_HEADER);
+        plain
+    }
+
     #[test]
     fn test_key_validation() {
         // 测试密钥长度验证
         let temp_dir = TempDir::new().unwrap();
         let db_path = temp_dir.path().join("test.db");
         let out_path = temp_dir.path().join("out.db");
-        
+
         // 创建测试文件
         std::fs::write(&db_path, vec![0u8; 100]).unwrap();
-        
+
         // 测试密钥长度错误
-        let result = decrypt_db("invalid_key", &db_path, &out_path);
+        let result = decrypt_db("invalid_key", &db_path, &out_path, None);
         assert!(result.is_err());
-        
+
         // 测试64字符密钥（但内容无效）
         let long_key = "a".repeat(64);
-        let result = decrypt_db(&long_key, &db_path, &out_path);
+        let result = decrypt_db(&long_key, &db_path, &out_path, None);
         // 这个会失败，因为密钥验证失败，但至少长度检查通过了
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_parse_key_formats() {
+        assert_eq!(
+            DecryptKey::parse(TEST_KEY).unwrap(),
+            DecryptKey::Passphrase(to_array(&hex::decode(TEST_KEY).unwrap()))
+        );
+
+        let raw = format!("x'{}'", "ab".repeat(32));
+        assert!(matches!(
+            DecryptKey::parse(&raw).unwrap(),
+            DecryptKey::Raw { salt: None, .. }
+        ));
+
+        let raw_with_salt = format!("X'{}{}'", "ab".repeat(32), "07".repeat(16));
+        let key = DecryptKey::parse(&raw_with_salt).unwrap();
+        assert_eq!(key.salt(), Some(&[7u8; SALT_SIZE]));
+
+        assert!(matches!(
+            DecryptKey::parse("x'abcd'"),
+            Err(AppError::ValidationFailed(_))
+        ));
+        assert!(DecryptKey::parse(&format!("x'{}'", "zz".repeat(32))).is_err());
+    }
+
+    #[test]
+    fn test_decrypt_with_raw_key() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("MSG0.db");
+        let profile = CipherProfile::wechat_v3();
+        let plain = sample_plain(2);
+        fs::write(&db_path, encrypt_fixture(TEST_KEY, &plain, &profile)).unwrap();
+
+        // 口令密钥解密后，派生结果应已缓存
+        let passphrase = DecryptKey::parse(TEST_KEY).unwrap();
+        let DecryptKey::Passphrase(password) = passphrase else {
+            unreachable!()
+        };
+        let salt = [7u8; SALT_SIZE];
+        decrypt_db(TEST_KEY, &db_path, &temp_dir.path().join("a.db"), None).unwrap();
+        let enc_key = DERIVED_KEYS
+            .get(&(password, salt.to_vec(), profile.clone()))
+            .expect("derived key should be cached");
+
+        // 用派生后的原始密钥解密，结果一致
+        let raw = format!("x'{}{}'", hex::encode(enc_key), hex::encode(salt));
+        let out_path = temp_dir.path().join("b.db");
+        let report = decrypt_db(&raw, &db_path, &out_path, None).unwrap();
+        assert_eq!(report.profile, profile.name);
+        assert_eq!(fs::read(&out_path).unwrap(), fs::read(temp_dir.path().join("a.db")).unwrap());
+
+        let no_salt = format!("x'{}'", hex::encode(enc_key));
+        assert!(decrypt_db(&no_salt, &db_path, &out_path, None).is_ok());
+
+        // salt与数据库不一致
+        let wrong_salt = format!("x'{}{}'", hex::encode(enc_key), "00".repeat(16));
+        let result = decrypt_db(&wrong_salt, &db_path, &out_path, None);
+        assert!(matches!(result, Err(AppError::DecryptionFailed(_))));
+    }
+
+    #[test]
+    fn test_match_keys() {
+        let temp_dir = TempDir::new().unwrap();
+        let profile = CipherProfile::wechat_v3();
+        let other_key = "b".repeat(64);
+        let msg = temp_dir.path().join("MSG0.db");
+        let micro = temp_dir.path().join("MicroMsg.db");
+        let unknown = temp_dir.path().join("Unknown.db");
+        fs::write(&msg, encrypt_fixture(TEST_KEY, &sample_plain(1), &profile)).unwrap();
+        fs::write(&micro, encrypt_fixture(&other_key, &sample_plain(1), &profile)).unwrap();
+        fs::write(&unknown, encrypt_fixture(&"c".repeat(64), &sample_plain(1), &profile)).unwrap();
+
+        let wrong_key = "d".repeat(64);
+        let candidates = vec!["not a key".to_string(), wrong_key.clone(), other_key.clone(), TEST_KEY.to_string()];
+        let matches = match_keys(&candidates, &[msg, micro, unknown]).unwrap();
+
+        assert_eq!(matches[0].key.as_deref(), Some(TEST_KEY));
+        assert_eq!(matches[0].profile.as_deref(), Some("wechat_v3"));
+        assert_eq!(matches[1].key.as_deref(), Some(other_key.as_str()));
+        assert!(matches[2].key.is_none());
+
+        // 只有校验通过的密钥写入派生缓存
+        let password = |key: &str| to_array(&hex::decode(key).unwrap());
+        let salt = [7u8; SALT_SIZE].to_vec();
+        assert!(DERIVED_KEYS.get(&(password(TEST_KEY), salt.clone(), profile.clone())).is_some());
+        assert!(DERIVED_KEYS.get(&(password(&wrong_key), salt, profile.clone())).is_none());
+
+        assert!(matches!(
+            match_keys(&["short".to_string()], &[]),
+            Err(AppError::ValidationFailed(_))
+        ));
+    }
+
+    #[test]
+    fn test_scan_dump_for_keys() {
+        let temp_dir = TempDir::new().unwrap();
+        let profile = CipherProfile::wechat_v3();
+        let key: Vec<u8> = (0..KEY_SIZE).map(|i| (i * 37 + 11) as u8).collect();
+        let key_hex = hex::encode(&key);
+        let db_path = temp_dir.path().join("MSG0.db");
+        fs::write(&db_path, encrypt_fixture(&key_hex, &sample_plain(1), &profile)).unwrap();
+
+        // 密钥前后放入一段随机噪声和大量零填充
+        let mut dump = vec![0u8; KEY_SIZE * 64];
+        for (i, b) in dump[KEY_SIZE..KEY_SIZE * 2].iter_mut().enumerate() {
+            *b = (i * 91 + 7) as u8;
+        }
+        dump[KEY_SIZE * 10..KEY_SIZE * 11].copy_from_slice(&key);
+        // 非对齐位置的副本不计入
+        dump[KEY_SIZE * 20 + 3..KEY_SIZE * 21 + 3].copy_from_slice(&key);
+        let dump_path = temp_dir.path().join("WeChatWin.dmp");
+        fs::write(&dump_path, &dump).unwrap();
+
+        // 零填充、噪声、密钥和非对齐副本所在的两段
+        let scan = scan_dump_for_keys(&dump_path, &db_path, Some(&profile), false).unwrap();
+        assert_eq!(scan.candidates, 5);
+        // 预筛只保留噪声、密钥和非对齐副本的前半段
+        let scan = scan_dump_for_keys(&dump_path, &db_path, Some(&profile), true).unwrap();
+        assert_eq!(scan.candidates, 3);
+        let hits = scan.hits;
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].offset, (KEY_SIZE * 10) as u64);
+        assert_eq!(hits[0].key, key_hex);
+        assert_eq!(hits[0].profile, profile.name);
+
+        // 低熵的真实密钥会被预筛漏掉，默认仍能找到
+        let weak: Vec<u8> = (0..KEY_SIZE).map(|i| (i % 16) as u8).collect();
+        let weak_hex = hex::encode(&weak);
+        fs::write(&db_path, encrypt_fixture(&weak_hex, &sample_plain(1), &profile)).unwrap();
+        dump[KEY_SIZE * 10..KEY_SIZE * 11].copy_from_slice(&weak);
+        fs::write(&dump_path, &dump).unwrap();
+        let scan = scan_dump_for_keys(&dump_path, &db_path, Some(&profile), true).unwrap();
+        assert!(scan.hits.is_empty());
+        let scan = scan_dump_for_keys(&dump_path, &db_path, Some(&profile), false).unwrap();
+        assert_eq!(scan.hits.len(), 1);
+        assert_eq!(scan.hits[0].key, weak_hex);
+    }
+
+    #[test]
+    fn test_incremental_decrypt() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("MSG0.db");
+        let out_path = temp_dir.path().join("de_MSG0.db");
+        let profile = CipherProfile::wechat_v3();
+        let options = DecryptOptions {
+            incremental: true,
+            ..Default::default()
+        };
+
+        let plain = sample_plain(3);
+        fs::write(&db_path, encrypt_fixture(TEST_KEY, &plain, &profile)).unwrap();
+
+        // 第一次没有清单，全量解密并生成清单
+        let report = decrypt_db_with_options(TEST_KEY, &db_path, &out_path, &options, None).unwrap();
+        assert!(!report.incremental);
+        assert_eq!(report.updated_pages, 3);
+        assert!(manifest_path(&out_path).exists());
+
+        // 密文未变化时不写入任何页
+        let report = decrypt_db_with_options(TEST_KEY, &db_path, &out_path, &options, None).unwrap();
+        assert!(report.incremental);
+        assert_eq!(report.updated_pages, 0);
+
+        // 修改第2页并追加一页，只重新解密这两页
+        let mut changed = plain.clone();
+        changed[PAGE_SIZE + 100] ^= 0xff;
+        changed.extend_from_slice(&sample_plain(2)[PAGE_SIZE..]);
+        fs::write(&db_path, encrypt_fixture(TEST_KEY, &changed, &profile)).unwrap();
+
+        let report = decrypt_db_with_options(TEST_KEY, &db_path, &out_path, &options, None).unwrap();
+        assert!(report.incremental);
+        assert_eq!(report.total_pages, 4);
+        assert_eq!(report.updated_pages, 2);
+
+        let full_path = temp_dir.path().join("full.db");
+        decrypt_db(TEST_KEY, &db_path, &full_path, None).unwrap();
+        assert_eq!(fs::read(&out_path).unwrap(), fs::read(&full_path).unwrap());
+
+        // 数据库变小时截断输出
+        fs::write(&db_path, encrypt_fixture(TEST_KEY, &changed[..PAGE_SIZE * 2], &profile)).unwrap();
+        let report = decrypt_db_with_options(TEST_KEY, &db_path, &out_path, &options, None).unwrap();
+        assert_eq!(report.updated_pages, 0);
+        assert_eq!(fs::metadata(&out_path).unwrap().len(), (PAGE_SIZE * 2) as u64);
+
+        // 全量解密改写输出后删除旧清单，下次增量解密重新全量解密
+        decrypt_db(TEST_KEY, &db_path, &out_path, None).unwrap();
+        assert!(!manifest_path(&out_path).exists());
+        let report = decrypt_db_with_options(TEST_KEY, &db_path, &out_path, &options, None).unwrap();
+        assert!(!report.incremental);
+        assert_eq!(report.updated_pages, 2);
+    }
+
+    #[test]
+    fn test_streaming_decrypt_with_progress() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("MSG0.db");
+        let out_path = temp_dir.path().join("out").join("de_MSG0.db");
+
+        // 构造3页明文，第一页以SQLite文件头开头
+        let plain = sample_plain(3);
+        let profile = CipherProfile::wechat_v3();
+        std::fs::write(&db_path, encrypt_fixture(TEST_KEY, &plain, &profile)).unwrap();
+
+        let calls = Cell::new(Vec::new());
+        let progress = |done: u64, total: u64| {
+            let mut seen = calls.take();
+            seen.push((done, total));
+            calls.set(seen);
+        };
+        let report = decrypt_db_with_options(
+            TEST_KEY,
+            &db_path,
+            &out_path,
+            &DecryptOptions::default(),
+            Some(&progress),
+        )
+        .unwrap();
+        assert_eq!(report.total_pages, 3);
+        assert_eq!(report.profile, "wechat_v3");
+        assert!(report.is_clean());
+
+        let decrypted = std::fs::read(&out_path).unwrap();
+        assert_eq!(decrypted.len(), plain.len());
+        for (page, expected) in decrypted.chunks(PAGE_SIZE).zip(plain.chunks(PAGE_SIZE)) {
+            assert_eq!(&page[..PAGE_SIZE - RESERVE_SIZE], &expected[..PAGE_SIZE - RESERVE_SIZE]);
+        }
+        assert_eq!(calls.take(), vec![(1, 3), (2, 3), (3, 3)]);
+    }
+
+    #[test]
+    fn test_wechat_v4_profile_detected() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("message_0.db");
+        let out_path = temp_dir.path().join("de_message_0.db");
+
+        let plain = sample_plain(2);
+        let profile = CipherProfile::wechat_v4();
+        std::fs::write(&db_path, encrypt_fixture(TEST_KEY, &plain, &profile)).unwrap();
+
+        assert_eq!(detect_profile(TEST_KEY, &db_path).unwrap(), profile);
+
+        // 指定错误的参数时应校验失败
+        let v3 = CipherProfile::wechat_v3();
+        let result = decrypt_db(TEST_KEY, &db_path, &out_path, Some(&v3));
+        assert!(matches!(result, Err(AppError::DecryptionFailed(_))));
+
+        let report = decrypt_db(TEST_KEY, &db_path, &out_path, Some(&profile)).unwrap();
+        assert_eq!(report.profile, "wechat_v4");
+        let decrypted = std::fs::read(&out_path).unwrap();
+        let data_end = PAGE_SIZE - profile.reserve_size;
+        assert_eq!(&decrypted[..data_end], &plain[..data_end]);
+        assert_eq!(&decrypted[PAGE_SIZE..PAGE_SIZE + data_end], &plain[PAGE_SIZE..PAGE_SIZE + data_end]);
+    }
+
+    #[test]
+    fn test_wrong_key_rejected() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("MSG0.db");
+        let out_path = temp_dir.path().join("de_MSG0.db");
+
+        let plain = vec![0u8; PAGE_SIZE];
+        let profile = CipherProfile::wechat_v3();
+        std::fs::write(&db_path, encrypt_fixture(TEST_KEY, &plain, &profile)).unwrap();
+
+        let result = decrypt_db(&"b".repeat(64), &db_path, &out_path, Some(&profile));
+        assert!(matches!(result, Err(AppError::DecryptionFailed(_))));
+    }
+
+    #[test]
+    fn test_corrupt_page_modes() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("MSG0.db");
+        let out_path = temp_dir.path().join("de_MSG0.db");
+
+        let plain = sample_plain(3);
+        let profile = CipherProfile::wechat_v3();
+        let mut encrypted = encrypt_fixture(TEST_KEY, &plain, &profile);
+        // 破坏第2页的密文（模拟微信运行时复制出的残缺文件）
+        encrypted[PAGE_SIZE + 100] ^= 0xff;
+        std::fs::write(&db_path, encrypted).unwrap();
+
+        let fail = decrypt_db(TEST_KEY, &db_path, &out_path, Some(&profile));
+        assert!(matches!(fail, Err(AppError::DecryptionFailed(_))));
+        assert!(!out_path.exists());
+
+        let skip = DecryptOptions {
+            on_corrupt: CorruptPageMode::Skip,
+            profile: Some(profile.clone()),
+            ..Default::default()
+        };
+        let report = decrypt_db_with_options(TEST_KEY, &db_path, &out_path, &skip, None).unwrap();
+        assert_eq!(
+            report.corrupt_pages,
+            vec![CorruptPage { page_no: 2, offset: PAGE_SIZE as u64 }]
+        );
+        assert_eq!(std::fs::read(&out_path).unwrap().len(), PAGE_SIZE * 3);
+
+        let zero = DecryptOptions {
+            on_corrupt: CorruptPageMode::ZeroFill,
+            profile: Some(profile),
+            ..Default::default()
+        };
+        let report = decrypt_db_with_options(TEST_KEY, &db_path, &out_path, &zero, None).unwrap();
+        assert_eq!(report.corrupt_pages.len(), 1);
+        let decrypted = std::fs::read(&out_path).unwrap();
+        assert!(decrypted[PAGE_SIZE..PAGE_SIZE * 2].iter().all(|&b| b == 0));
+        let data_end = PAGE_SIZE * 3 - RESERVE_SIZE;
+        assert_eq!(&decrypted[PAGE_SIZE * 2..data_end], &plain[PAGE_SIZE * 2..data_end]);
+    }
+
     #[test]
     fn test_batch_decrypt_empty() {
         // 测试空列表
         let temp_dir = TempDir::new().unwrap();
         let out_dir = temp_dir.path();
-        let result = batch_decrypt("a".repeat(64).as_str(), &[], out_dir);
+        let result = batch_decrypt("a".repeat(64).as_str(), &[], out_dir, None, 0);
         assert!(result.is_ok());
         assert_eq!(result.unwrap().len(), 0);
     }
-}
 
+    #[test]
+    fn test_batch_decrypt_job_report() {
+        let temp_dir = TempDir::new().unwrap();
+        let profile = CipherProfile::wechat_v3();
+        let plain = sample_plain(2);
+
+        let good = temp_dir.path().join("MSG0.db");
+        let other = temp_dir.path().join("MSG1.db");
+        let wrong_key = temp_dir.path().join("MSG2.db");
+        let missing = temp_dir.path().join("MSG3.db");
+        fs::write(&good, encrypt_fixture(TEST_KEY, &plain, &profile)).unwrap();
+        fs::write(&other, encrypt_fixture(TEST_KEY, &plain, &profile)).unwrap();
+        fs::write(&wrong_key, encrypt_fixture(&"b".repeat(64), &plain, &profile)).unwrap();
+
+        let out_dir = temp_dir.path().join("out");
+        let paths = vec![good, wrong_key, other, missing];
+        let results = batch_decrypt(TEST_KEY, &paths, &out_dir, None, 2).unwrap();
+
+        // 结果顺序与输入一致
+        assert_eq!(results.len(), 4);
+        for (result, path) in results.iter().zip(&paths) {
+            assert_eq!(&result.db_path, path);
+        }
+
+        assert!(results[0].is_success());
+        assert_eq!(results[0].total_pages, 2);
+        assert_eq!(results[0].output_size, plain.len() as u64);
+        assert_eq!(results[0].out_path, out_dir.join("de_MSG0.db"));
+        assert!(results[2].is_success());
+
+        assert_eq!(results[1].status, BatchJobStatus::Failed);
+        assert_eq!(results[1].error_kind, Some(BatchErrorKind::KeyMismatch));
+        assert!(!results[1].out_path.exists());
+
+        assert_eq!(results[3].error_kind, Some(BatchErrorKind::NotFound));
+    }
+}
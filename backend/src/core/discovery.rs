@@ -0,0 +1,344 @@
+use crate::core::cipher_profile::CipherProfile;
+use crate::core::decryption::{decrypt_jobs, BatchJobResult};
+use crate::utils::{validation, AppError, Result};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// 解密输出目录下的清单文件名
+pub const ACCOUNT_MANIFEST_FILE: &str = "manifest.json";
+
+/// 账号目录下数据库在目录树中的最大深度
+const MAX_DEPTH: usize = 4;
+
+/// 数据库在账号中的用途
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DbRole {
+    /// 联系人、群聊（MicroMsg.db / contact.db）
+    Contact,
+    /// 聊天记录分片（Multi/MSG*.db / message_*.db）
+    Message,
+    /// 语音等媒体分片（MediaMSG*.db / media_*.db）
+    Media,
+    Favorite,
+    Sns,
+    OpenImContact,
+    OpenImMessage,
+    OpenImMedia,
+    /// 图片、视频、文件的硬链接索引
+    HardLink,
+    Emotion,
+    /// 头像等杂项（Misc.db / head_image.db）
+    Misc,
+    PublicMsg,
+    /// 全文检索索引（FTS*.db）
+    FullTextSearch,
+    Other,
+}
+
+/// 发现的数据库文件
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiscoveredDb {
+    pub role: DbRole,
+    /// 分片序号（MSG0.db为0）
+    pub shard: Option<u32>,
+    pub path: PathBuf,
+    /// 相对账号目录的路径，解密输出按此路径镜像
+    pub relative_path: PathBuf,
+    pub size: u64,
+}
+
+/// 账号清单中的单个数据库
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountDbEntry {
+    pub role: DbRole,
+    pub shard: Option<u32>,
+    pub relative_path: PathBuf,
+    pub result: BatchJobResult,
+}
+
+/// 整个账号目录的解密结果清单
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountManifest {
+    pub wx_dir: PathBuf,
+    pub out_dir: PathBuf,
+    pub profile: Option<String>,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub databases: Vec<AccountDbEntry>,
+}
+
+impl AccountManifest {
+    /// 指定用途的已解密数据库，按分片序号排序
+    pub fn decrypted(&self, role: DbRole) -> Vec<&AccountDbEntry> {
+        self.databases
+            .iter()
+            .filter(|e| e.role == role && e.result.is_success())
+            .collect()
+    }
+
+    /// 读取输出目录中的清单
+    pub fn load(out_dir: &Path) -> Result<Self> {
+        let path = out_dir.join(ACCOUNT_MANIFEST_FILE);
+        let content = fs::read_to_string(&path)
+            .map_err(|e| AppError::NotFound(format!("Manifest not found: {:?} ({})", path, e)))?;
+        let manifest = serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse manifest: {:?}", path))?;
+        Ok(manifest)
+    }
+}
+
+/// `prefix`后跟数字和`.db`时返回分片序号
+fn shard_of(name: &str, prefix: &str) -> Option<u32> {
+    name.strip_prefix(prefix)?.strip_suffix(".db")?.parse().ok()
+}
+
+/// 按文件名识别数据库用途（兼容微信3.x和4.x的命名）
+pub fn classify(file_name: &str) -> (DbRole, Option<u32>) {
+    let name = file_name.to_ascii_lowercase();
+
+    if let Some(shard) = shard_of(&name, "msg").or_else(|| shard_of(&name, "message_")) {
+        return (DbRole::Message, Some(shard));
+    }
+    if let Some(shard) = shard_of(&name, "mediamsg").or_else(|| shard_of(&name, "media_")) {
+        return (DbRole::Media, Some(shard));
+    }
+    if name.starts_with("fts") || name.ends_with("_fts.db") {
+        let shard = name
+            .trim_end_matches(".db")
+            .trim_start_matches(|c: char| !c.is_ascii_digit())
+            .parse()
+            .ok();
+        return (DbRole::FullTextSearch, shard);
+    }
+
+    let role = match name.as_str() {
+        "micromsg.db" | "contact.db" => DbRole::Contact,
+        "favorite.db" => DbRole::Favorite,
+        "sns.db" => DbRole::Sns,
+        "openimcontact.db" => DbRole::OpenImContact,
+        "openimmsg.db" => DbRole::OpenImMessage,
+        "openimmedia.db" => DbRole::OpenImMedia,
+        "emotion.db" | "emoticon.db" => DbRole::Emotion,
+        "misc.db" | "head_image.db" => DbRole::Misc,
+        "publicmsg.db" => DbRole::PublicMsg,
+        _ if name.starts_with("hardlink") => DbRole::HardLink,
+        _ => DbRole::Other,
+    };
+    (role, None)
+}
+
+/// 遍历账号目录（`WeChatInfo.wx_dir`），列出所有数据库并识别用途
+/// 3.x只遍历`Msg`目录，4.x只遍历`db_storage`目录，避免扫描庞大的FileStorage
+pub fn discover(wx_dir: &Path) -> Result<Vec<DiscoveredDb>> {
+    if !wx_dir.is_dir() {
+        return Err(AppError::NotFound(format!(
+            "WeChat directory not found: {:?}",
+            wx_dir
+        )));
+    }
+
+    let roots: Vec<PathBuf> = ["Msg", "db_storage"]
+        .iter()
+        .map(|name| wx_dir.join(name))
+        .filter(|p| p.is_dir())
+        .collect();
+    let roots = if roots.is_empty() {
+        vec![wx_dir.to_path_buf()]
+    } else {
+        roots
+    };
+
+    let mut databases = Vec::new();
+    for root in roots {
+        for entry in WalkDir::new(&root).max_depth(MAX_DEPTH).into_iter() {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    tracing::warn!("Skipping unreadable entry under {:?}: {}", root, e);
+                    continue;
+                }
+            };
+            if !entry.file_type().is_file() {
+                continue;
+            }
+
+            let path = entry.path();
+            let is_db = path
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("db"));
+            if !is_db {
+                continue;
+            }
+
+            let file_name = entry.file_name().to_string_lossy();
+            let (role, shard) = classify(&file_name);
+            let relative_path = path.strip_prefix(wx_dir).unwrap_or(path).to_path_buf();
+            let size = entry.metadata().map(|m| m.len()).unwrap_or(0);
+
+            databases.push(DiscoveredDb {
+                role,
+                shard,
+                path: path.to_path_buf(),
+                relative_path,
+                size,
+            });
+        }
+    }
+
+    databases.sort_by(|a, b| {
+        (a.role, a.shard, &a.relative_path).cmp(&(b.role, b.shard, &b.relative_path))
+    });
+    Ok(databases)
+}
+
+/// 解密整个账号目录，输出按原目录结构镜像到`out_dir`，并写出清单
+pub fn decrypt_account(
+    key: &str,
+    wx_dir: &Path,
+    out_dir: &Path,
+    profile: Option<&CipherProfile>,
+    workers: usize,
+) -> Result<AccountManifest> {
+    validation::validate_output_path(wx_dir, out_dir)?;
+    let databases = discover(wx_dir)?;
+    if databases.is_empty() {
+        return Err(AppError::NotFound(format!(
+            "No databases found under {:?}",
+            wx_dir
+        )));
+    }
+
+    fs::create_dir_all(out_dir)
+        .with_context(|| format!("Failed to create output directory: {:?}", out_dir))?;
+
+    let jobs: Vec<(PathBuf, PathBuf)> = databases
+        .iter()
+        .map(|db| (db.path.clone(), out_dir.join(&db.relative_path)))
+        .collect();
+    let results = decrypt_jobs(key, &jobs, profile, workers)?;
+
+    let succeeded = results.iter().filter(|r| r.is_success()).count();
+    let manifest = AccountManifest {
+        wx_dir: wx_dir.to_path_buf(),
+        out_dir: out_dir.to_path_buf(),
+        profile: results.iter().find_map(|r| r.profile.clone()),
+        succeeded,
+        failed: results.len() - succeeded,
+        databases: databases
+            .into_iter()
+            .zip(results)
+            .map(|(db, result)| AccountDbEntry {
+                role: db.role,
+                shard: db.shard,
+                relative_path: db.relative_path,
+                result,
+            })
+            .collect(),
+    };
+
+    let manifest_path = out_dir.join(ACCOUNT_MANIFEST_FILE);
+    let content = serde_json::to_string_pretty(&manifest)
+        .with_context(|| "Failed to serialize manifest")?;
+    fs::write(&manifest_path, content)
+        .with_context(|| format!("Failed to write manifest: {:?}", manifest_path))?;
+
+    Ok(manifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::encryption::encrypt_db;
+    use tempfile::TempDir;
+
+    const TEST_KEY: &str = "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
+
+    /// 写入一个按微信3.x格式加密的两页数据库
+    fn write_encrypted_db(dir: &Path, path: &Path) {
+        let profile = CipherProfile::wechat_v3();
+        let mut plain = vec![0u8; profile.page_size * 2];
+        plain[..16].copy_from_slice(b"SQLite format 3\x00");
+        plain[16..18].copy_from_slice(&(profile.page_size as u16).to_be_bytes());
+        plain[20] = profile.reserve_size as u8;
+
+        let plain_path = dir.join("plain.tmp");
+        fs::write(&plain_path, &plain).unwrap();
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        encrypt_db(TEST_KEY, &plain_path, path, Some(&profile), None).unwrap();
+    }
+
+    #[test]
+    fn test_classify() {
+        assert_eq!(classify("MicroMsg.db"), (DbRole::Contact, None));
+        assert_eq!(classify("MSG12.db"), (DbRole::Message, Some(12)));
+        assert_eq!(classify("message_0.db"), (DbRole::Message, Some(0)));
+        assert_eq!(classify("MediaMSG3.db"), (DbRole::Media, Some(3)));
+        assert_eq!(classify("FTSMSG1.db"), (DbRole::FullTextSearch, Some(1)));
+        assert_eq!(classify("HardLinkImage.db"), (DbRole::HardLink, None));
+        assert_eq!(classify("OpenIMContact.db"), (DbRole::OpenImContact, None));
+        assert_eq!(classify("MSG.db"), (DbRole::Other, None));
+    }
+
+    #[test]
+    fn test_decrypt_account_tree() {
+        let temp_dir = TempDir::new().unwrap();
+        let wx_dir = temp_dir.path().join("wxid_test");
+        let msg_dir = wx_dir.join("Msg");
+        write_encrypted_db(temp_dir.path(), &msg_dir.join("MicroMsg.db"));
+        write_encrypted_db(temp_dir.path(), &msg_dir.join("Multi").join("MSG1.db"));
+        write_encrypted_db(temp_dir.path(), &msg_dir.join("Multi").join("MSG0.db"));
+        write_encrypted_db(temp_dir.path(), &msg_dir.join("Multi").join("MediaMSG0.db"));
+        // 无法解密的文件记录为失败，不影响其他文件
+        fs::write(msg_dir.join("Sns.db"), vec![0u8; 8192]).unwrap();
+        // FileStorage等目录不参与遍历
+        fs::create_dir_all(wx_dir.join("FileStorage")).unwrap();
+        fs::write(wx_dir.join("FileStorage").join("cache.db"), b"x").unwrap();
+
+        let found = discover(&wx_dir).unwrap();
+        let roles: Vec<(DbRole, Option<u32>)> = found.iter().map(|d| (d.role, d.shard)).collect();
+        assert_eq!(
+            roles,
+            vec![
+                (DbRole::Contact, None),
+                (DbRole::Message, Some(0)),
+                (DbRole::Message, Some(1)),
+                (DbRole::Media, Some(0)),
+                (DbRole::Sns, None),
+            ]
+        );
+
+        let out_dir = temp_dir.path().join("decrypted");
+        let manifest = decrypt_account(TEST_KEY, &wx_dir, &out_dir, None, 2).unwrap();
+        assert_eq!(manifest.succeeded, 4);
+        assert_eq!(manifest.failed, 1);
+        assert_eq!(manifest.profile.as_deref(), Some("wechat_v3"));
+        assert!(out_dir.join("Msg").join("Multi").join("MSG1.db").is_file());
+        assert!(!out_dir.join("Msg").join("Sns.db").exists());
+
+        let loaded = AccountManifest::load(&out_dir).unwrap();
+        let messages = loaded.decrypted(DbRole::Message);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].shard, Some(0));
+    }
+
+    #[test]
+    fn test_decrypt_account_rejects_output_in_source() {
+        let temp_dir = TempDir::new().unwrap();
+        let wx_dir = temp_dir.path().join("wxid_test");
+        let db_path = wx_dir.join("Msg").join("MicroMsg.db");
+        write_encrypted_db(temp_dir.path(), &db_path);
+        let original = fs::read(&db_path).unwrap();
+
+        for out_dir in [wx_dir.clone(), wx_dir.join("Msg").join("..").join("out")] {
+            let result = decrypt_account(TEST_KEY, &wx_dir, &out_dir, None, 1);
+            assert!(matches!(result, Err(AppError::BadRequest(_))));
+        }
+        let result = crate::core::decryption::decrypt_db(TEST_KEY, &db_path, &db_path, None);
+        assert!(matches!(result, Err(AppError::BadRequest(_))));
+        assert_eq!(fs::read(&db_path).unwrap(), original);
+    }
+}
@@ -0,0 +1,301 @@
+use crate::core::cipher_profile::{CipherProfile, IV_SIZE};
+use crate::core::decryption::{
+    DecryptKey, PageKeys, ProgressCallback, IO_BUFFER_SIZE, SALT_SIZE, SQLITE_FILE_HEADER,
+};
+use crate::core::wal;
+use crate::utils::{validation, AppError, Result};
+use anyhow::Context;
+use std::fs;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+/// SQLite文件头中页大小（大端u16）的位置
+const HEADER_PAGE_SIZE_OFFSET: usize = 16;
+/// SQLite文件头中每页保留字节数的位置
+const HEADER_RESERVE_OFFSET: usize = 20;
+
+/// 从SQLite文件头读取页大小和保留字节数
+fn read_sqlite_header(header: &[u8]) -> Result<(usize, usize)> {
+    if header.len() < 100 || &header[..SQLITE_FILE_HEADER.len()] != SQLITE_FILE_HEADER {
+        return Err(AppError::ValidationFailed(
+            "Not a plain SQLite database".to_string(),
+        ));
+    }
+
+    let raw_page_size = u16::from_be_bytes([
+        header[HEADER_PAGE_SIZE_OFFSET],
+        header[HEADER_PAGE_SIZE_OFFSET + 1],
+    ]);
+    // 页大小为1表示65536
+    let page_size = if raw_page_size == 1 { 65536 } else { raw_page_size as usize };
+    let reserve_size = header[HEADER_RESERVE_OFFSET] as usize;
+
+    Ok((page_size, reserve_size))
+}
+
+/// 根据SQLite文件头中的页大小和保留字节数匹配预设参数
+fn profile_for_header(page_size: usize, reserve_size: usize) -> Option<CipherProfile> {
+    CipherProfile::presets()
+        .into_iter()
+        .find(|p| p.page_size == page_size && p.reserve_size == reserve_size)
+}
+
+/// 逐页加密并写出，`header`为已从`reader`读出的第一页开头
+fn encrypt_pages<R: Read, W: Write>(
+    keys: &PageKeys,
+    salt: &[u8; SALT_SIZE],
+    header: &[u8],
+    reader: &mut R,
+    writer: &mut W,
+    total_pages: u64,
+    progress: Option<ProgressCallback>,
+) -> Result<()> {
+    let mut page = vec![0u8; keys.page_size()];
+    page[..header.len()].copy_from_slice(header);
+
+    for page_no in 1..=total_pages {
+        if page_no == 1 {
+            reader
+                .read_exact(&mut page[header.len()..])
+                .with_context(|| "Failed to read page 1")?;
+        } else {
+            reader
+                .read_exact(&mut page)
+                .with_context(|| format!("Failed to read page {}", page_no))?;
+        }
+
+        let iv: [u8; IV_SIZE] = rand::random();
+        let encrypted = keys.encrypt_page(&page, page_no as u32, salt, &iv)?;
+        writer
+            .write_all(&encrypted)
+            .with_context(|| format!("Failed to write page {}", page_no))?;
+
+        if let Some(callback) = progress {
+            callback(page_no, total_pages);
+        }
+    }
+
+    Ok(())
+}
+
+/// 将明文SQLite数据库加密为微信格式（`decrypt_db`的逆过程）
+/// `profile`为空时根据文件头中的保留字节数选择预设
+pub fn encrypt_db(
+    key: &str,
+    plain_path: &Path,
+    out_path: &Path,
+    profile: Option<&CipherProfile>,
+    progress: Option<ProgressCallback>,
+) -> Result<u64> {
+    if !plain_path.exists() || !plain_path.is_file() {
+        return Err(anyhow::anyhow!("Database file not found: {:?}", plain_path).into());
+    }
+
+    validation::validate_output_path(plain_path, out_path)?;
+
+    // 未检查点的写入只在WAL中，直接加密主文件会丢失这些数据
+    let wal_file = wal::wal_path(plain_path);
+    if fs::metadata(&wal_file).is_ok_and(|m| m.len() > 0) {
+        return Err(AppError::ValidationFailed(format!(
+            "Database has an uncheckpointed WAL {:?}, checkpoint or close it before encrypting",
+            wal_file
+        )));
+    }
+
+    if let Some(parent) = out_path.parent() {
+        if !parent.exists() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create output directory: {:?}", parent))?;
+        }
+    }
+
+    let key = DecryptKey::parse(key)?;
+    // 原始密钥已由某个salt派生，换用其他salt后微信无法再派生出同一密钥
+    if matches!(key, DecryptKey::Raw { salt: None, .. }) {
+        return Err(AppError::ValidationFailed(
+            "Raw key must include the salt it was derived with: x'<64 hex><32 hex salt>'".to_string(),
+        ));
+    }
+
+    let file = File::open(plain_path)
+        .with_context(|| format!("Failed to open database: {:?}", plain_path))?;
+    let file_size = file
+        .metadata()
+        .with_context(|| format!("Failed to read metadata: {:?}", plain_path))?
+        .len();
+    let mut reader = BufReader::with_capacity(IO_BUFFER_SIZE, file);
+
+    let mut header = [0u8; 100];
+    reader
+        .read_exact(&mut header)
+        .with_context(|| format!("Failed to read SQLite header: {:?}", plain_path))?;
+    let (page_size, reserve_size) = read_sqlite_header(&header)?;
+
+    let profile = match profile {
+        Some(profile) => profile.clone(),
+        None => profile_for_header(page_size, reserve_size).ok_or_else(|| {
+            AppError::ValidationFailed(format!(
+                "No cipher profile matches page size {} with {} reserved bytes",
+                page_size, reserve_size
+            ))
+        })?,
+    };
+
+    // 保留区放不下IV和HMAC的自定义参数会在加密时越界
+    if !profile.is_valid() {
+        return Err(AppError::ValidationFailed(format!(
+            "Invalid cipher profile: {}",
+            profile.name
+        )));
+    }
+
+    // 加密数据写在每页的保留区内，保留区大小必须与参数一致
+    if profile.page_size != page_size || profile.reserve_size != reserve_size {
+        return Err(AppError::ValidationFailed(format!(
+            "Database layout (page {}, reserve {}) does not match profile {} (page {}, reserve {})",
+            page_size, reserve_size, profile.name, profile.page_size, profile.reserve_size
+        )));
+    }
+
+    if file_size % page_size as u64 != 0 {
+        return Err(AppError::ValidationFailed(format!(
+            "Database size {} is not a multiple of page size {}",
+            file_size, page_size
+        )));
+    }
+    let total_pages = file_size / page_size as u64;
+
+    // 原始密钥沿用其salt，口令密钥随机生成
+    let salt: [u8; SALT_SIZE] = key.salt().copied().unwrap_or_else(rand::random);
+    let keys = PageKeys::from_key(&key, &salt, &profile);
+
+    let output = File::create(out_path)
+        .with_context(|| format!("Failed to create output file: {:?}", out_path))?;
+    let mut writer = BufWriter::with_capacity(IO_BUFFER_SIZE, output);
+
+    let result =
+        encrypt_pages(&keys, &salt, &header, &mut reader, &mut writer, total_pages, progress);
+    if let Err(e) = result {
+        // 失败时删除不完整的输出文件
+        drop(writer);
+        let _ = fs::remove_file(out_path);
+        return Err(e);
+    }
+
+    writer
+        .flush()
+        .with_context(|| format!("Failed to flush output file: {:?}", out_path))?;
+
+    Ok(total_pages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::decryption::decrypt_db;
+    use tempfile::TempDir;
+
+    const TEST_KEY: &str = "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
+
+    /// 构造带保留区的明文数据库镜像
+    fn plain_image(profile: &CipherProfile, pages: usize) -> Vec<u8> {
+        let mut plain: Vec<u8> = (0..profile.page_size * pages)
+            .map(|i| (i * 7 % 256) as u8)
+            .collect();
+        plain[..SQLITE_FILE_HEADER.len()].copy_from_slice(SQLITE_FILE_HEADER);
+        plain[HEADER_PAGE_SIZE_OFFSET..HEADER_PAGE_SIZE_OFFSET + 2]
+            .copy_from_slice(&(profile.page_size as u16).to_be_bytes());
+        plain[HEADER_RESERVE_OFFSET] = profile.reserve_size as u8;
+        plain
+    }
+
+    #[test]
+    fn test_encrypt_decrypt_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let profile = CipherProfile::wechat_v3();
+        let plain = plain_image(&profile, 4);
+        let plain_path = temp_dir.path().join("de_MSG0.db");
+        let enc_path = temp_dir.path().join("MSG0.db");
+        let dec_path = temp_dir.path().join("round_trip.db");
+        fs::write(&plain_path, &plain).unwrap();
+
+        let pages = encrypt_db(TEST_KEY, &plain_path, &enc_path, None, None).unwrap();
+        assert_eq!(pages, 4);
+        let encrypted = fs::read(&enc_path).unwrap();
+        assert_eq!(encrypted.len(), plain.len());
+        assert_ne!(&encrypted[..SALT_SIZE], SQLITE_FILE_HEADER);
+
+        let report = decrypt_db(TEST_KEY, &enc_path, &dec_path, None).unwrap();
+        assert!(report.is_clean());
+        assert_eq!(report.profile, profile.name);
+
+        // 除保留区（IV + HMAC）外，解密结果应与原始明文逐字节一致
+        let decrypted = fs::read(&dec_path).unwrap();
+        let data_end = profile.page_size - profile.reserve_size;
+        for (dec, orig) in decrypted
+            .chunks(profile.page_size)
+            .zip(plain.chunks(profile.page_size))
+        {
+            assert_eq!(&dec[..data_end], &orig[..data_end]);
+        }
+    }
+
+    #[test]
+    fn test_reject_layout_mismatch() {
+        let temp_dir = TempDir::new().unwrap();
+        let plain_path = temp_dir.path().join("plain.db");
+        let enc_path = temp_dir.path().join("enc.db");
+
+        // 普通SQLite数据库没有保留区，无法写入IV和HMAC
+        let conn = rusqlite::Connection::open(&plain_path).unwrap();
+        conn.execute_batch("CREATE TABLE t (id INTEGER); INSERT INTO t VALUES (1);")
+            .unwrap();
+        drop(conn);
+
+        let result = encrypt_db(TEST_KEY, &plain_path, &enc_path, None, None);
+        assert!(matches!(result, Err(AppError::ValidationFailed(_))));
+
+        let v3 = CipherProfile::wechat_v3();
+        let result = encrypt_db(TEST_KEY, &plain_path, &enc_path, Some(&v3), None);
+        assert!(matches!(result, Err(AppError::ValidationFailed(_))));
+    }
+
+    #[test]
+    fn test_reject_unsalted_raw_key_and_pending_wal() {
+        let temp_dir = TempDir::new().unwrap();
+        let profile = CipherProfile::wechat_v3();
+        let plain_path = temp_dir.path().join("de_MSG0.db");
+        let enc_path = temp_dir.path().join("MSG0.db");
+        fs::write(&plain_path, plain_image(&profile, 2)).unwrap();
+
+        let raw = format!("x'{}'", "ab".repeat(32));
+        let result = encrypt_db(&raw, &plain_path, &enc_path, None, None);
+        assert!(matches!(result, Err(AppError::ValidationFailed(_))));
+        let salted = format!("x'{}{}'", "ab".repeat(32), "07".repeat(16));
+        encrypt_db(&salted, &plain_path, &enc_path, None, None).unwrap();
+        assert_eq!(&fs::read(&enc_path).unwrap()[..SALT_SIZE], &[7u8; SALT_SIZE]);
+
+        fs::write(wal::wal_path(&plain_path), b"pending frames").unwrap();
+        let result = encrypt_db(TEST_KEY, &plain_path, &enc_path, None, None);
+        assert!(matches!(result, Err(AppError::ValidationFailed(_))));
+    }
+
+    #[test]
+    fn test_reject_invalid_profile() {
+        let temp_dir = TempDir::new().unwrap();
+        // 保留区放不下IV和SHA1摘要，但与文件头一致
+        let profile = CipherProfile {
+            name: "custom".to_string(),
+            reserve_size: 16,
+            ..CipherProfile::wechat_v3()
+        };
+        let plain_path = temp_dir.path().join("de_MSG0.db");
+        let enc_path = temp_dir.path().join("MSG0.db");
+        fs::write(&plain_path, plain_image(&profile, 2)).unwrap();
+
+        let result = encrypt_db(TEST_KEY, &plain_path, &enc_path, Some(&profile), None);
+        assert!(matches!(result, Err(AppError::ValidationFailed(_))));
+        assert!(!enc_path.exists());
+    }
+}
@@ -2,6 +2,10 @@ pub mod process;
 pub mod memory;
 pub mod memory_map;
 pub mod decryption;
+pub mod cipher_profile;
+pub mod encryption;
+pub mod wal;
+pub mod discovery;
 pub mod version;
 pub mod version_detection;
 pub mod wx_info;
@@ -0,0 +1,319 @@
+use crate::core::decryption::{PageKeys, IO_BUFFER_SIZE};
+use crate::utils::{AppError, Result};
+use anyhow::Context;
+use rusqlite::Connection;
+use std::fs;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// WAL文件头大小
+const WAL_HEADER_SIZE: usize = 32;
+/// 每个帧头大小
+const WAL_FRAME_HEADER_SIZE: usize = 24;
+/// WAL魔数，最低位为1时校验和按大端计算
+const WAL_MAGIC: u32 = 0x377f0682;
+
+/// `<数据库>-wal`
+pub fn wal_path(db_path: &Path) -> PathBuf {
+    let mut path = db_path.as_os_str().to_owned();
+    path.push("-wal");
+    PathBuf::from(path)
+}
+
+/// `<数据库>-shm`
+fn shm_path(db_path: &Path) -> PathBuf {
+    let mut path = db_path.as_os_str().to_owned();
+    path.push("-shm");
+    PathBuf::from(path)
+}
+
+/// 删除输出旁上次留下的WAL和共享内存索引，避免旧帧被应用到新的输出上
+pub(crate) fn remove_sidecars(out_path: &Path) {
+    let _ = fs::remove_file(wal_path(out_path));
+    let _ = fs::remove_file(shm_path(out_path));
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+/// SQLite WAL校验和，`data`长度须为8的整数倍
+fn wal_checksum(big_endian: bool, data: &[u8], (mut s0, mut s1): (u32, u32)) -> (u32, u32) {
+    let word = |chunk: &[u8]| {
+        let bytes = [chunk[0], chunk[1], chunk[2], chunk[3]];
+        if big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        }
+    };
+
+    for chunk in data.chunks_exact(8) {
+        s0 = s0.wrapping_add(word(&chunk[..4])).wrapping_add(s1);
+        s1 = s1.wrapping_add(word(&chunk[4..])).wrapping_add(s0);
+    }
+    (s0, s1)
+}
+
+/// 读满缓冲区，文件结束时返回false
+fn read_frame<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<bool> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        let n = reader
+            .read(&mut buf[filled..])
+            .with_context(|| "Failed to read WAL frame")?;
+        if n == 0 {
+            return Ok(false);
+        }
+        filled += n;
+    }
+    Ok(true)
+}
+
+/// 解密`db_path`的WAL文件，在`out_path`旁写出明文WAL（重新计算校验和），返回写出帧对应的页号
+/// 调用前须已用`remove_sidecars`清理输出旁的旧文件
+///
+/// WAL文件头和帧头是明文，只有页数据按数据库页的方式加密。
+/// 与SQLite一致，遇到salt或校验和不匹配的帧即停止，后面的帧视为无效。
+pub(crate) fn decrypt_wal(keys: &PageKeys, db_path: &Path, out_path: &Path) -> Result<Vec<u32>> {
+    let page_size = keys.page_size();
+    let wal_file = wal_path(db_path);
+    let out_wal = wal_path(out_path);
+
+    if !wal_file.is_file() {
+        return Ok(Vec::new());
+    }
+
+    let file = File::open(&wal_file)
+        .with_context(|| format!("Failed to open WAL file: {:?}", wal_file))?;
+    let mut reader = BufReader::with_capacity(IO_BUFFER_SIZE, file);
+
+    let mut header = [0u8; WAL_HEADER_SIZE];
+    if !read_frame(&mut reader, &mut header)? {
+        // 检查点之后WAL会被清空
+        return Ok(Vec::new());
+    }
+
+    let magic = read_u32(&header, 0);
+    if magic & !1 != WAL_MAGIC {
+        return Err(AppError::ValidationFailed(format!(
+            "Invalid WAL header: {:?}",
+            wal_file
+        )));
+    }
+    let big_endian = magic & 1 == 1;
+
+    let wal_page_size = read_u32(&header, 8) as usize;
+    if wal_page_size != page_size {
+        return Err(AppError::ValidationFailed(format!(
+            "WAL page size {} does not match database page size {}",
+            wal_page_size, page_size
+        )));
+    }
+
+    let mut checksum = wal_checksum(big_endian, &header[..24], (0, 0));
+    if checksum != (read_u32(&header, 24), read_u32(&header, 28)) {
+        tracing::warn!("WAL header checksum mismatch, ignored: {:?}", wal_file);
+        return Ok(Vec::new());
+    }
+
+    let output = File::create(&out_wal)
+        .with_context(|| format!("Failed to create WAL file: {:?}", out_wal))?;
+    let mut writer = BufWriter::with_capacity(IO_BUFFER_SIZE, output);
+    writer
+        .write_all(&header)
+        .with_context(|| format!("Failed to write WAL header: {:?}", out_wal))?;
+
+    // 明文帧的校验和链与密文不同，需要单独计算
+    let mut out_checksum = checksum;
+    let mut frame_header = [0u8; WAL_FRAME_HEADER_SIZE];
+    let mut page = vec![0u8; page_size];
+    let mut pages = Vec::new();
+
+    loop {
+        if !read_frame(&mut reader, &mut frame_header)? || !read_frame(&mut reader, &mut page)? {
+            break;
+        }
+
+        // 旧的帧（WAL重置前写入）salt不同
+        if frame_header[8..16] != header[16..24] {
+            break;
+        }
+
+        checksum = wal_checksum(big_endian, &frame_header[..8], checksum);
+        checksum = wal_checksum(big_endian, &page, checksum);
+        if checksum != (read_u32(&frame_header, 16), read_u32(&frame_header, 20)) {
+            break;
+        }
+
+        let page_no = read_u32(&frame_header, 0);
+        if !keys.verify_page(&page, page_no)? {
+            tracing::warn!(
+                "WAL frame {} (page {}) failed HMAC verification, remaining frames ignored",
+                pages.len() + 1,
+                page_no
+            );
+            break;
+        }
+
+        let decrypted = keys.decrypt_page(&page, page_no)?;
+        out_checksum = wal_checksum(big_endian, &frame_header[..8], out_checksum);
+        out_checksum = wal_checksum(big_endian, &decrypted, out_checksum);
+        frame_header[16..20].copy_from_slice(&out_checksum.0.to_be_bytes());
+        frame_header[20..24].copy_from_slice(&out_checksum.1.to_be_bytes());
+
+        writer
+            .write_all(&frame_header)
+            .and_then(|_| writer.write_all(&decrypted))
+            .with_context(|| format!("Failed to write WAL frame to {:?}", out_wal))?;
+        pages.push(page_no);
+    }
+
+    writer
+        .flush()
+        .with_context(|| format!("Failed to flush WAL file: {:?}", out_wal))?;
+
+    Ok(pages)
+}
+
+/// 把WAL合并进数据库并切换为DELETE日志模式，输出不再依赖`-wal`文件
+pub(crate) fn checkpoint(db_path: &Path) -> Result<()> {
+    let conn = Connection::open(db_path)
+        .map_err(|e| AppError::Database(format!("Failed to open {:?}: {}", db_path, e)))?;
+    conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))
+        .map_err(|e| AppError::Database(format!("WAL checkpoint failed: {}", e)))?;
+    conn.query_row("PRAGMA journal_mode=DELETE", [], |_| Ok(()))
+        .map_err(|e| AppError::Database(format!("Failed to switch journal mode: {}", e)))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::cipher_profile::{CipherProfile, IV_SIZE};
+    use crate::core::decryption::{decrypt_db_with_options, DecryptKey, DecryptOptions, WalMode};
+    use crate::core::encryption::encrypt_db;
+    use std::os::raw::{c_int, c_void};
+    use tempfile::TempDir;
+
+    const TEST_KEY: &str = "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
+
+    /// 按微信格式加密明文WAL（校验和基于密文重新计算）
+    fn encrypt_wal(plain_wal: &[u8], salt: &[u8], profile: &CipherProfile) -> Vec<u8> {
+        let key = DecryptKey::parse(TEST_KEY).unwrap();
+        let keys = PageKeys::from_key(&key, salt, profile);
+        let big_endian = read_u32(plain_wal, 0) & 1 == 1;
+        let mut checksum = wal_checksum(big_endian, &plain_wal[..24], (0, 0));
+
+        let mut out = plain_wal[..WAL_HEADER_SIZE].to_vec();
+        let frame_size = WAL_FRAME_HEADER_SIZE + profile.page_size;
+        for frame in plain_wal[WAL_HEADER_SIZE..].chunks_exact(frame_size) {
+            let mut frame_header = frame[..WAL_FRAME_HEADER_SIZE].to_vec();
+            let page_no = read_u32(&frame_header, 0);
+            let iv = [page_no as u8; IV_SIZE];
+            let page = keys
+                .encrypt_page(&frame[WAL_FRAME_HEADER_SIZE..], page_no, salt, &iv)
+                .unwrap();
+
+            checksum = wal_checksum(big_endian, &frame_header[..8], checksum);
+            checksum = wal_checksum(big_endian, &page, checksum);
+            frame_header[16..20].copy_from_slice(&checksum.0.to_be_bytes());
+            frame_header[20..24].copy_from_slice(&checksum.1.to_be_bytes());
+            out.extend_from_slice(&frame_header);
+            out.extend_from_slice(&page);
+        }
+        out
+    }
+
+    fn count_rows(db_path: &Path) -> i64 {
+        let conn = Connection::open(db_path).unwrap();
+        conn.query_row("SELECT COUNT(*) FROM MSG", [], |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_decrypt_and_checkpoint_wal() {
+        let temp_dir = TempDir::new().unwrap();
+        let profile = CipherProfile::wechat_v3();
+        let plain_path = temp_dir.path().join("plain.db");
+
+        // 构造带48字节保留区的WAL模式数据库：50条已合并，20条仍在WAL中
+        let conn = Connection::open(&plain_path).unwrap();
+        let mut reserve: c_int = profile.reserve_size as c_int;
+        unsafe {
+            rusqlite::ffi::sqlite3_file_control(
+                conn.handle(),
+                c"main".as_ptr(),
+                rusqlite::ffi::SQLITE_FCNTL_RESERVE_BYTES,
+                &mut reserve as *mut c_int as *mut c_void,
+            );
+        }
+        conn.execute_batch(
+            "PRAGMA page_size=4096; VACUUM;
+             PRAGMA journal_mode=WAL; PRAGMA wal_autocheckpoint=0;
+             CREATE TABLE MSG (localId INTEGER PRIMARY KEY, StrContent TEXT);",
+        )
+        .unwrap();
+        for i in 0..70 {
+            if i == 50 {
+                conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))
+                    .unwrap();
+            }
+            conn.execute("INSERT INTO MSG (StrContent) VALUES (?1)", [format!("msg {}", i)])
+                .unwrap();
+        }
+
+        let snapshot = temp_dir.path().join("snapshot.db");
+        fs::copy(&plain_path, &snapshot).unwrap();
+        let plain_wal = fs::read(wal_path(&plain_path)).unwrap();
+        drop(conn);
+
+        let enc_path = temp_dir.path().join("MSG0.db");
+        encrypt_db(TEST_KEY, &snapshot, &enc_path, Some(&profile), None).unwrap();
+        let salt = fs::read(&enc_path).unwrap()[..16].to_vec();
+        fs::write(wal_path(&enc_path), encrypt_wal(&plain_wal, &salt, &profile)).unwrap();
+
+        let decrypt = |name: &str, wal: WalMode| {
+            let out_path = temp_dir.path().join(name);
+            let options = DecryptOptions {
+                wal,
+                ..Default::default()
+            };
+            let report = decrypt_db_with_options(TEST_KEY, &enc_path, &out_path, &options, None)
+                .unwrap();
+            (out_path, report)
+        };
+
+        let (ignored, report) = decrypt("ignored.db", WalMode::Ignore);
+        assert_eq!(report.wal_frames, 0);
+        assert!(!wal_path(&ignored).exists());
+        assert_eq!(count_rows(&ignored), 50);
+
+        // 以Ignore重新解密到同一输出时，上次留下的明文WAL被删除
+        let (stale, _) = decrypt("stale.db", WalMode::Decrypt);
+        assert!(wal_path(&stale).exists());
+        fs::write(shm_path(&stale), b"stale").unwrap();
+        let (stale, report) = decrypt("stale.db", WalMode::Ignore);
+        assert_eq!(report.wal_frames, 0);
+        assert!(!wal_path(&stale).exists());
+        assert!(!shm_path(&stale).exists());
+        assert_eq!(count_rows(&stale), 50);
+
+        let (with_wal, report) = decrypt("with_wal.db", WalMode::Decrypt);
+        assert!(report.wal_frames > 0);
+        assert!(!report.wal_checkpointed);
+        assert!(wal_path(&with_wal).exists());
+        assert_eq!(count_rows(&with_wal), 70);
+
+        let (merged, report) = decrypt("merged.db", WalMode::Checkpoint);
+        assert!(report.wal_checkpointed);
+        assert!(!wal_path(&merged).exists());
+        assert_eq!(count_rows(&merged), 70);
+    }
+}
@@ -0,0 +1,322 @@
+use crate::db::dbbase::DatabaseBase;
+use crate::db::protobuf_parser::ProtobufParser;
+use crate::utils::Result;
+use rusqlite::Row;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// UserNameList、DisplayNameList的分隔符
+const LIST_SEPARATOR: &str = "^G";
+
+const CHATROOM_COLUMNS: &str =
+    "ChatRoomName, UserNameList, DisplayNameList, Reserved2, SelfDisplayName, RoomData";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChatroomRole {
+    Owner,
+    Member,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatroomMember {
+    pub wxid: String,
+    /// 群昵称，未设置时为空
+    pub room_nickname: Option<String>,
+    pub role: ChatroomRole,
+    /// RoomData中的成员状态，取自列表时为0
+    pub state: i32,
+}
+
+/// 群公告，取自ChatRoomInfo表
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Announcement {
+    pub content: String,
+    pub editor: Option<String>,
+    pub publish_time: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Chatroom {
+    /// 群聊ID（`xxx@chatroom`）
+    pub wxid: String,
+    /// 群主wxid，取自Reserved2列
+    pub owner: Option<String>,
+    /// 自己在群里的群昵称
+    pub self_display_name: Option<String>,
+    pub members: Vec<ChatroomMember>,
+    pub announcement: Option<Announcement>,
+}
+
+impl Chatroom {
+    /// 群昵称映射，只包含设置了群昵称的成员
+    pub fn room_nicknames(&self) -> HashMap<String, String> {
+        self.members
+            .iter()
+            .filter_map(|m| Some((m.wxid.clone(), m.room_nickname.clone()?)))
+            .collect()
+    }
+}
+
+/// MicroMsg.db中的群聊信息（ChatRoom、ChatRoomInfo表）
+pub struct ChatroomHandler {
+    db: DatabaseBase,
+}
+
+impl ChatroomHandler {
+    pub fn new(db_path: &str) -> Result<Self> {
+        let db = DatabaseBase::new(db_path)?;
+        Ok(Self { db })
+    }
+
+    /// 获取群聊列表
+    pub fn get_chatrooms(&self) -> Result<Vec<Chatroom>> {
+        if !self.db.table_exists("ChatRoom") {
+            return Ok(Vec::new());
+        }
+
+        let sql = format!("SELECT {} FROM ChatRoom ORDER BY ChatRoomName", CHATROOM_COLUMNS);
+        let mut chatrooms = self.db.execute_query(&sql, &[], chatroom_from_row)?;
+
+        let mut announcements = self.get_announcements(None)?;
+        for chatroom in &mut chatrooms {
+            chatroom.announcement = announcements.remove(&chatroom.wxid);
+        }
+        Ok(chatrooms)
+    }
+
+    /// 获取群聊详情
+    pub fn get_chatroom(&self, wxid: &str) -> Result<Option<Chatroom>> {
+        if !self.db.table_exists("ChatRoom") {
+            return Ok(None);
+        }
+
+        let sql = format!("SELECT {} FROM ChatRoom WHERE ChatRoomName = ?", CHATROOM_COLUMNS);
+        let Some(mut chatroom) = self.db.execute_query(&sql, &[&wxid], chatroom_from_row)?.pop() else {
+            return Ok(None);
+        };
+        chatroom.announcement = self.get_announcements(Some(wxid))?.remove(wxid);
+        Ok(Some(chatroom))
+    }
+
+    /// 获取群成员，群聊不存在时为空
+    pub fn get_members(&self, wxid: &str) -> Result<Vec<ChatroomMember>> {
+        Ok(self.get_chatroom(wxid)?.map(|c| c.members).unwrap_or_default())
+    }
+
+    /// 群成员wxid到群昵称的映射（用于消息显示）
+    pub fn room_nicknames(&self, wxid: &str) -> Result<HashMap<String, String>> {
+        Ok(self
+            .get_chatroom(wxid)?
+            .map(|c| c.room_nicknames())
+            .unwrap_or_default())
+    }
+
+    /// 按群聊ID获取非空的群公告，未指定时获取全部
+    fn get_announcements(&self, wxid: Option<&str>) -> Result<HashMap<String, Announcement>> {
+        if !self.db.table_exists("ChatRoomInfo") {
+            return Ok(HashMap::new());
+        }
+
+        let mut sql = String::from(
+            "SELECT ChatRoomName, Announcement, AnnouncementEditor, AnnouncementPublishTime
+             FROM ChatRoomInfo
+             WHERE Announcement IS NOT NULL AND Announcement != ''",
+        );
+        let mut params: Vec<&dyn rusqlite::ToSql> = Vec::new();
+        if let Some(wxid) = &wxid {
+            sql.push_str(" AND ChatRoomName = ?");
+            params.push(wxid);
+        }
+
+        let rows = self.db.execute_query(&sql, &params, |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                Announcement {
+                    content: row.get(1)?,
+                    editor: non_empty(row.get(2)?),
+                    publish_time: row.get::<_, Option<i64>>(3)?.unwrap_or(0),
+                },
+            ))
+        })?;
+        Ok(rows.into_iter().collect())
+    }
+}
+
+fn chatroom_from_row(row: &Row) -> rusqlite::Result<Chatroom> {
+    let wxid: String = row.get(0)?;
+    let user_names: Option<String> = row.get(1)?;
+    let display_names: Option<String> = row.get(2)?;
+    let owner = non_empty(row.get(3)?);
+    let room_data: Option<Vec<u8>> = row.get(5)?;
+
+    // RoomData解析失败或为空时退回到两个列表，两者按位置对应
+    let mut members: Vec<ChatroomMember> = room_data
+        .and_then(|bytes| ProtobufParser::decode_room_data(&bytes).ok())
+        .map(|data| {
+            data.members
+                .into_iter()
+                .filter(|m| !m.wxid.is_empty())
+                .map(|m| ChatroomMember {
+                    wxid: m.wxid,
+                    room_nickname: non_empty(Some(m.display_name)),
+                    role: ChatroomRole::Member,
+                    state: m.state,
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    if members.is_empty() {
+        let user_names = user_names.unwrap_or_default();
+        let display_names = display_names.unwrap_or_default();
+        let mut display_names = display_names.split(LIST_SEPARATOR);
+        members = user_names
+            .split(LIST_SEPARATOR)
+            .map(|wxid| (wxid, display_names.next()))
+            .filter(|(wxid, _)| !wxid.is_empty())
+            .map(|(wxid, display_name)| ChatroomMember {
+                wxid: wxid.to_string(),
+                room_nickname: non_empty(display_name.map(str::to_string)),
+                role: ChatroomRole::Member,
+                state: 0,
+            })
+            .collect();
+    }
+    for member in &mut members {
+        if owner.as_deref() == Some(member.wxid.as_str()) {
+            member.role = ChatroomRole::Owner;
+        }
+    }
+
+    Ok(Chatroom {
+        wxid,
+        owner,
+        self_display_name: non_empty(row.get(4)?),
+        members,
+        announcement: None,
+    })
+}
+
+fn non_empty(value: Option<String>) -> Option<String> {
+    value.filter(|v| !v.trim().is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::contact::DisplayNames;
+    use crate::db::protobuf_parser::tests::build_room_data;
+    use rusqlite::Connection;
+    use tempfile::TempDir;
+
+    fn create_test_db() -> (TempDir, String) {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("MicroMsg.db");
+        let db_path_str = db_path.to_str().unwrap().to_string();
+
+        let conn = Connection::open(&db_path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE ChatRoom (
+                ChatRoomName TEXT PRIMARY KEY,
+                UserNameList TEXT,
+                DisplayNameList TEXT,
+                ChatRoomFlag INTEGER,
+                Owner INTEGER,
+                IsShowName INTEGER,
+                SelfDisplayName TEXT,
+                Reserved1 INTEGER,
+                Reserved2 TEXT,
+                RoomData BLOB
+            );
+            CREATE TABLE ChatRoomInfo (
+                ChatRoomName TEXT PRIMARY KEY,
+                Announcement TEXT,
+                InfoVersion INTEGER,
+                AnnouncementEditor TEXT,
+                AnnouncementPublishTime INTEGER
+            );",
+        )
+        .unwrap();
+
+        let room_data = build_room_data(&[("wxid_owner", "群主"), ("wxid_a", ""), ("wxid_me", "我的群昵称")]);
+        conn.execute(
+            "INSERT INTO ChatRoom (ChatRoomName, UserNameList, DisplayNameList, SelfDisplayName, Reserved2, RoomData)
+             VALUES (?, ?, ?, ?, ?, ?)",
+            rusqlite::params![
+                "111@chatroom",
+                "wxid_owner^Gwxid_a^Gwxid_me",
+                "^G^G",
+                "我的群昵称",
+                "wxid_owner",
+                room_data
+            ],
+        )
+        .unwrap();
+        // 没有RoomData的旧数据
+        conn.execute(
+            "INSERT INTO ChatRoom (ChatRoomName, UserNameList, DisplayNameList, Reserved2)
+             VALUES (?, ?, ?, ?)",
+            rusqlite::params!["222@chatroom", "wxid_b^Gwxid_c", "小B^G", "wxid_c"],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO ChatRoomInfo (ChatRoomName, Announcement, AnnouncementEditor, AnnouncementPublishTime)
+             VALUES (?, ?, ?, ?)",
+            rusqlite::params!["111@chatroom", "周末聚餐", "wxid_owner", 1_700_000_000],
+        )
+        .unwrap();
+
+        (temp_dir, db_path_str)
+    }
+
+    #[test]
+    fn test_get_chatroom() {
+        let (_temp_dir, db_path) = create_test_db();
+        let handler = ChatroomHandler::new(&db_path).unwrap();
+
+        let chatroom = handler.get_chatroom("111@chatroom").unwrap().unwrap();
+        assert_eq!(chatroom.owner.as_deref(), Some("wxid_owner"));
+        assert_eq!(chatroom.self_display_name.as_deref(), Some("我的群昵称"));
+        assert_eq!(chatroom.members.len(), 3);
+        assert_eq!(chatroom.members[0].role, ChatroomRole::Owner);
+        assert_eq!(chatroom.members[0].room_nickname.as_deref(), Some("群主"));
+        assert_eq!(chatroom.members[1].room_nickname, None);
+        let announcement = chatroom.announcement.unwrap();
+        assert_eq!(announcement.content, "周末聚餐");
+        assert_eq!(announcement.publish_time, 1_700_000_000);
+
+        assert!(handler.get_chatroom("missing@chatroom").unwrap().is_none());
+        assert!(handler.get_members("missing@chatroom").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_members_from_name_lists() {
+        let (_temp_dir, db_path) = create_test_db();
+        let handler = ChatroomHandler::new(&db_path).unwrap();
+
+        let members = handler.get_members("222@chatroom").unwrap();
+        assert_eq!(members.len(), 2);
+        assert_eq!(members[0].room_nickname.as_deref(), Some("小B"));
+        assert_eq!(members[1].role, ChatroomRole::Owner);
+
+        let nicknames = handler.room_nicknames("222@chatroom").unwrap();
+        assert_eq!(nicknames.len(), 1);
+        assert_eq!(nicknames["wxid_b"], "小B");
+
+        let chatrooms = handler.get_chatrooms().unwrap();
+        assert_eq!(chatrooms.len(), 2);
+        assert!(chatrooms[0].announcement.is_some());
+        assert!(chatrooms[1].announcement.is_none());
+    }
+
+    #[test]
+    fn test_display_names_room_nickname() {
+        let (_temp_dir, db_path) = create_test_db();
+        let handler = ChatroomHandler::new(&db_path).unwrap();
+        let mut names = DisplayNames::new(None).with_chatrooms(Some(&handler));
+
+        assert_eq!(names.name_in("111@chatroom", "wxid_owner"), "群主");
+        assert_eq!(names.name_in("111@chatroom", "wxid_a"), "wxid_a");
+        assert_eq!(names.name_in("wxid_owner", "wxid_owner"), "wxid_owner");
+    }
+}
@@ -1,104 +1,197 @@
+use crate::db::chatroom::ChatroomHandler;
 use crate::db::dbbase::DatabaseBase;
+use crate::db::utils::Message;
 use crate::utils::Result;
-use anyhow::Context;
-use rusqlite::params;
+use rusqlite::Row;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+/// 联系人分类
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContactKind {
+    Friend,
+    /// 公众号、服务号
+    Official,
+    Chatroom,
+    /// 企业微信联系人
+    Enterprise,
+    Deleted,
+    /// 群成员等不在通讯录中的联系人
+    Stranger,
+}
+
+impl ContactKind {
+    /// 按wxid后缀、VerifyFlag、DelFlag和Type分类
+    pub fn classify(wxid: &str, contact_type: i32, verify_flag: i32, del_flag: i32) -> Self {
+        if wxid.ends_with("@chatroom") {
+            ContactKind::Chatroom
+        } else if wxid.ends_with("@openim") {
+            ContactKind::Enterprise
+        } else if wxid.starts_with("gh_") || verify_flag & 8 != 0 {
+            // VerifyFlag为8、24、56等认证公众号
+            ContactKind::Official
+        } else if del_flag != 0 {
+            ContactKind::Deleted
+        } else if contact_type & 1 != 0 {
+            // Type的最低位表示在通讯录中
+            ContactKind::Friend
+        } else {
+            ContactKind::Stranger
+        }
+    }
+}
+
+/// 联系人标签（ContactLabel表）
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ContactLabel {
+    pub id: i64,
+    pub name: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Contact {
     pub wxid: String,
     pub nickname: Option<String>,
     pub remark: Option<String>,
+    /// 微信号：设置过时为Alias，否则为可作为微信号的旧式wxid
     pub account: Option<String>,
     pub alias: Option<String>,
+    /// 小头像URL，没有时为大头像
     pub head_img_url: Option<String>,
+    pub big_head_img_url: Option<String>,
     pub contact_type: i32,
+    pub verify_flag: i32,
+    pub kind: ContactKind,
+    /// 标签名称，按LabelIDList的顺序
+    pub labels: Vec<String>,
+}
+
+impl Contact {
+    /// 显示名称：备注优先，其次昵称，都为空时为None
+    pub fn display_name(&self) -> Option<&str> {
+        [self.remark.as_deref(), self.nickname.as_deref()]
+            .into_iter()
+            .flatten()
+            .find(|name| !name.is_empty())
+    }
 }
 
 pub struct ContactHandler {
     db: DatabaseBase,
+    /// 按实际存在的列生成的查询，列名统一为别名
+    select: String,
 }
 
 impl ContactHandler {
     pub fn new(db_path: &str) -> Result<Self> {
         let db = DatabaseBase::new(db_path)?;
-        Ok(Self { db })
+        let select = Self::build_select(&db)?;
+        Ok(Self { db, select })
     }
 
-    /// 获取联系人列表
-    pub fn get_contacts(&self) -> Result<Vec<Contact>> {
+    /// 不同版本的Contact表列不同，缺少的列以NULL代替
+    /// 头像URL优先取ContactHeadImgUrl表，其次取Contact表中的列
+    fn build_select(db: &DatabaseBase) -> Result<String> {
+        let columns = db.table_columns("Contact")?;
+        let has = |name: &str| columns.iter().any(|c| c.eq_ignore_ascii_case(name));
+        let column = |name: &str| {
+            if has(name) {
+                format!("C.{}", name)
+            } else {
+                "NULL".to_string()
+            }
+        };
+
+        let mut small = ["SmallHeadImgUrl", "HeadImgUrl"]
+            .into_iter()
+            .find(|name| has(name))
+            .map(column)
+            .unwrap_or_else(|| "NULL".to_string());
+        let mut big = column("BigHeadImgUrl");
+        let mut join = String::new();
+        if db.table_exists("ContactHeadImgUrl") {
+            small = format!("COALESCE(NULLIF(H.smallHeadImgUrl, ''), {})", small);
+            big = format!("COALESCE(NULLIF(H.bigHeadImgUrl, ''), {})", big);
+            join = " LEFT JOIN ContactHeadImgUrl H ON H.usrName = C.UserName".to_string();
+        }
+
+        Ok(format!(
+            "SELECT C.UserName AS UserName, {} AS NickName, {} AS Remark, {} AS Alias, {} AS Type,
+                    {} AS VerifyFlag, {} AS DelFlag, {} AS LabelIDList,
+                    {} AS SmallHeadImgUrl, {} AS BigHeadImgUrl
+             FROM Contact C{}",
+            column("NickName"),
+            column("Remark"),
+            column("Alias"),
+            column("Type"),
+            column("VerifyFlag"),
+            column("DelFlag"),
+            column("LabelIDList"),
+            small,
+            big,
+            join
+        ))
+    }
+
+    fn query_contacts(&self, filter: &str, params: &[&dyn rusqlite::ToSql]) -> Result<Vec<Contact>> {
         if !self.db.table_exists("Contact") {
             return Ok(Vec::new());
         }
 
-        let sql = "SELECT UserName, NickName, Remark, Alias, HeadImgUrl, Type 
-                   FROM Contact 
-                   WHERE UserName IS NOT NULL 
-                   ORDER BY NickName";
-
-        let contacts = self.db.execute_query(sql, &[], |row| {
-            Ok(Contact {
-                wxid: row.get(0)?,
-                nickname: row.get(1)?,
-                remark: row.get(2)?,
-                alias: row.get(3)?,
-                head_img_url: row.get(4)?,
-                contact_type: row.get(5)?,
-            })
-        })?;
+        let labels: HashMap<i64, String> = self
+            .get_labels()?
+            .into_iter()
+            .map(|label| (label.id, label.name))
+            .collect();
+        let sql = format!("SELECT * FROM ({}) WHERE {}", self.select, filter);
+        self.db
+            .execute_query(&sql, params, |row| contact_from_row(row, &labels))
+    }
 
-        Ok(contacts)
+    /// 获取联系人列表
+    pub fn get_contacts(&self) -> Result<Vec<Contact>> {
+        self.query_contacts("UserName IS NOT NULL ORDER BY NickName", &[])
     }
 
     /// 获取联系人详情
     pub fn get_contact(&self, wxid: &str) -> Result<Option<Contact>> {
-        if !self.db.table_exists("Contact") {
-            return Ok(None);
-        }
-
-        let sql = "SELECT UserName, NickName, Remark, Alias, HeadImgUrl, Type 
-                   FROM Contact 
-                   WHERE UserName = ?";
-
-        let contacts = self.db.execute_query(sql, &[&wxid], |row| {
-            Ok(Contact {
-                wxid: row.get(0)?,
-                nickname: row.get(1)?,
-                remark: row.get(2)?,
-                alias: row.get(3)?,
-                head_img_url: row.get(4)?,
-                contact_type: row.get(5)?,
-            })
-        })?;
-
+        let contacts = self.query_contacts("UserName = ?", &[&wxid])?;
         Ok(contacts.first().cloned())
     }
 
     /// 搜索联系人
     pub fn search_contacts(&self, keyword: &str) -> Result<Vec<Contact>> {
-        if !self.db.table_exists("Contact") {
+        let search_pattern = format!("%{}%", keyword);
+        self.query_contacts(
+            "UserName LIKE ?1 OR NickName LIKE ?1 OR Remark LIKE ?1 OR Alias LIKE ?1 ORDER BY NickName",
+            &[&search_pattern],
+        )
+    }
+
+    /// 按分类和标签名称筛选联系人，条件为空时不筛选
+    pub fn filter_contacts(&self, kind: Option<ContactKind>, label: Option<&str>) -> Result<Vec<Contact>> {
+        let mut contacts = self.get_contacts()?;
+        contacts.retain(|c| {
+            kind.is_none_or(|kind| c.kind == kind)
+                && label.is_none_or(|label| c.labels.iter().any(|l| l == label))
+        });
+        Ok(contacts)
+    }
+
+    /// 获取联系人标签
+    pub fn get_labels(&self) -> Result<Vec<ContactLabel>> {
+        if !self.db.table_exists("ContactLabel") {
             return Ok(Vec::new());
         }
 
-        let search_pattern = format!("%{}%", keyword);
-        let sql = "SELECT UserName, NickName, Remark, Alias, HeadImgUrl, Type 
-                   FROM Contact 
-                   WHERE UserName LIKE ? OR NickName LIKE ? OR Remark LIKE ? OR Alias LIKE ?
-                   ORDER BY NickName";
-
-        let contacts = self.db.execute_query(sql, &[&search_pattern, &search_pattern, &search_pattern, &search_pattern], |row| {
-            Ok(Contact {
-                wxid: row.get(0)?,
-                nickname: row.get(1)?,
-                remark: row.get(2)?,
-                alias: row.get(3)?,
-                head_img_url: row.get(4)?,
-                contact_type: row.get(5)?,
+        let sql = "SELECT LabelId, LabelName FROM ContactLabel ORDER BY LabelId";
+        self.db.execute_query(sql, &[], |row| {
+            Ok(ContactLabel {
+                id: row.get(0)?,
+                name: row.get::<_, Option<String>>(1)?.unwrap_or_default(),
             })
-        })?;
-
-        Ok(contacts)
+        })
     }
 
     /// 获取联系人映射（用于消息显示）
@@ -112,6 +205,112 @@ impl ContactHandler {
     }
 }
 
+/// LabelIDList形如`1,3,`，未知的标签ID忽略
+fn parse_label_ids(list: &str) -> impl Iterator<Item = i64> + '_ {
+    list.split(',').filter_map(|id| id.trim().parse().ok())
+}
+
+fn contact_from_row(row: &Row, labels: &HashMap<i64, String>) -> rusqlite::Result<Contact> {
+    let wxid: String = row.get("UserName")?;
+    let alias: Option<String> = row.get::<_, Option<String>>("Alias")?.filter(|a| !a.is_empty());
+    let contact_type = row.get::<_, Option<i32>>("Type")?.unwrap_or(0);
+    let verify_flag = row.get::<_, Option<i32>>("VerifyFlag")?.unwrap_or(0);
+    let del_flag = row.get::<_, Option<i32>>("DelFlag")?.unwrap_or(0);
+    let label_list = row.get::<_, Option<String>>("LabelIDList")?.unwrap_or_default();
+    let small: Option<String> = row.get::<_, Option<String>>("SmallHeadImgUrl")?.filter(|u| !u.is_empty());
+    let big: Option<String> = row.get::<_, Option<String>>("BigHeadImgUrl")?.filter(|u| !u.is_empty());
+
+    // 新注册的wxid_开头的账号不能作为微信号使用
+    let account = alias.clone().or_else(|| {
+        (!wxid.starts_with("wxid_") && !wxid.contains('@')).then(|| wxid.clone())
+    });
+
+    Ok(Contact {
+        kind: ContactKind::classify(&wxid, contact_type, verify_flag, del_flag),
+        labels: parse_label_ids(&label_list)
+            .filter_map(|id| labels.get(&id).cloned())
+            .collect(),
+        nickname: row.get("NickName")?,
+        remark: row.get("Remark")?,
+        account,
+        alias,
+        head_img_url: small.or_else(|| big.clone()),
+        big_head_img_url: big,
+        contact_type,
+        verify_flag,
+        wxid,
+    })
+}
+
+/// 按wxid解析显示名称（备注优先，其次昵称），群聊发送者优先使用群昵称
+/// 查询结果在导出等批量处理中缓存
+pub struct DisplayNames<'a> {
+    contacts: Option<&'a ContactHandler>,
+    chatrooms: Option<&'a ChatroomHandler>,
+    cache: HashMap<String, String>,
+    room_cache: HashMap<String, HashMap<String, String>>,
+}
+
+impl<'a> DisplayNames<'a> {
+    pub fn new(contacts: Option<&'a ContactHandler>) -> Self {
+        Self {
+            contacts,
+            chatrooms: None,
+            cache: HashMap::new(),
+            room_cache: HashMap::new(),
+        }
+    }
+
+    /// 使用群聊信息解析群昵称
+    pub fn with_chatrooms(mut self, chatrooms: Option<&'a ChatroomHandler>) -> Self {
+        self.chatrooms = chatrooms;
+        self
+    }
+
+    /// 联系人不存在或没有名称时返回wxid本身
+    pub fn name_of(&mut self, wxid: &str) -> String {
+        if let Some(name) = self.cache.get(wxid) {
+            return name.clone();
+        }
+
+        let name = self
+            .contacts
+            .and_then(|c| c.get_contact(wxid).ok().flatten())
+            .and_then(|c| c.display_name().map(str::to_string))
+            .unwrap_or_else(|| wxid.to_string());
+        self.cache.insert(wxid.to_string(), name.clone());
+        name
+    }
+
+    /// 群成员的显示名称，有群昵称时使用群昵称；`room`不是群聊时同`name_of`
+    pub fn name_in(&mut self, room: &str, wxid: &str) -> String {
+        if let Some(name) = self.room_nickname(room, wxid) {
+            return name;
+        }
+        self.name_of(wxid)
+    }
+
+    /// 消息发送者的显示名称，自己发送的消息显示为“我”
+    pub fn sender_of(&mut self, msg: &Message) -> String {
+        match msg.display_wxid() {
+            Some(wxid) => self.name_in(&msg.str_talker, wxid),
+            None => "我".to_string(),
+        }
+    }
+
+    fn room_nickname(&mut self, room: &str, wxid: &str) -> Option<String> {
+        if !room.ends_with("@chatroom") || room == wxid {
+            return None;
+        }
+        let chatrooms = self.chatrooms?;
+        self.room_cache
+            .entry(room.to_string())
+            .or_insert_with(|| chatrooms.room_nicknames(room).unwrap_or_default())
+            .get(wxid)
+            .cloned()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -177,5 +376,62 @@ mod tests {
         let contacts = handler.search_contacts("Test").unwrap();
         assert!(!contacts.is_empty());
     }
-}
 
+    /// 3.x的MicroMsg.db：Contact表没有HeadImgUrl列，头像URL在ContactHeadImgUrl表
+    fn create_micromsg_db() -> (TempDir, String) {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("MicroMsg.db");
+        let conn = Connection::open(&db_path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE Contact (
+                UserName TEXT PRIMARY KEY, Alias TEXT, DelFlag INTEGER, Type INTEGER,
+                VerifyFlag INTEGER, Remark TEXT, NickName TEXT, LabelIDList TEXT,
+                BigHeadImgUrl TEXT, SmallHeadImgUrl TEXT
+            );
+            CREATE TABLE ContactLabel (LabelId INTEGER PRIMARY KEY, LabelName TEXT);
+            CREATE TABLE ContactHeadImgUrl (usrName TEXT PRIMARY KEY, smallHeadImgUrl TEXT, bigHeadImgUrl TEXT);
+            INSERT INTO ContactLabel VALUES (1, '同事'), (2, '家人');
+            INSERT INTO Contact VALUES ('wxid_friend', 'friend01', 0, 3, 0, '', '好友', '1,2,', '', 'http://small/contact');
+            INSERT INTO Contact VALUES ('oldname', '', 0, 3, 0, '', '老好友', '2', '', '');
+            INSERT INTO Contact VALUES ('gh_news', '', 0, 3, 24, '', '新闻', '', '', '');
+            INSERT INTO Contact VALUES ('123@chatroom', '', 0, 2, 0, '', '群聊', '', '', '');
+            INSERT INTO Contact VALUES ('wxid_member', '', 0, 4, 0, '', '群成员', '', '', '');
+            INSERT INTO Contact VALUES ('wxid_deleted', '', 1, 3, 0, '', '已删除', '', '', '');
+            INSERT INTO Contact VALUES ('abc@openim', '', 0, 3, 0, '', '企业', '', '', '');
+            INSERT INTO ContactHeadImgUrl VALUES ('wxid_friend', 'http://small', 'http://big');",
+        )
+        .unwrap();
+        (temp_dir, db_path.to_str().unwrap().to_string())
+    }
+
+    #[test]
+    fn test_contact_kinds_and_labels() {
+        let (_temp_dir, db_path) = create_micromsg_db();
+        let handler = ContactHandler::new(&db_path).unwrap();
+
+        let friend = handler.get_contact("wxid_friend").unwrap().unwrap();
+        assert_eq!(friend.kind, ContactKind::Friend);
+        assert_eq!(friend.labels, vec!["同事", "家人"]);
+        assert_eq!(friend.account.as_deref(), Some("friend01"));
+        assert_eq!(friend.head_img_url.as_deref(), Some("http://small"));
+        assert_eq!(friend.big_head_img_url.as_deref(), Some("http://big"));
+
+        let old = handler.get_contact("oldname").unwrap().unwrap();
+        assert_eq!(old.account.as_deref(), Some("oldname"));
+        assert_eq!(old.head_img_url, None);
+
+        let kind_of = |wxid: &str| handler.get_contact(wxid).unwrap().unwrap().kind;
+        assert_eq!(kind_of("gh_news"), ContactKind::Official);
+        assert_eq!(kind_of("123@chatroom"), ContactKind::Chatroom);
+        assert_eq!(kind_of("wxid_member"), ContactKind::Stranger);
+        assert_eq!(kind_of("wxid_deleted"), ContactKind::Deleted);
+        assert_eq!(kind_of("abc@openim"), ContactKind::Enterprise);
+
+        assert_eq!(handler.get_labels().unwrap().len(), 2);
+        let family = handler.filter_contacts(None, Some("家人")).unwrap();
+        assert_eq!(family.len(), 2);
+        let friends = handler.filter_contacts(Some(ContactKind::Friend), Some("同事")).unwrap();
+        assert_eq!(friends.len(), 1);
+        assert_eq!(handler.filter_contacts(None, None).unwrap().len(), 7);
+    }
+}
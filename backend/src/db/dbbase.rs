@@ -1,97 +1,370 @@
-use crate::utils::Result;
+use crate::db::protobuf_parser::{BytesExtraType, ProtobufParser};
+use crate::utils::{AppError, Result};
 use anyhow::Context;
-use rusqlite::{Connection, Row};
+use rusqlite::functions::FunctionFlags;
+use rusqlite::types::ValueRef;
+use rusqlite::{Connection, OpenFlags, Row};
+use std::ops::Deref;
 use std::path::Path;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::Duration;
 
+/// 连接池默认大小
+const DEFAULT_POOL_SIZE: usize = 4;
+/// 等待空闲连接的超时时间
+const ACQUIRE_TIMEOUT: Duration = Duration::from_secs(30);
+/// 数据库被写连接锁定时的重试时间
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+/// 内存映射大小（256MB）
+const MMAP_SIZE: i64 = 256 * 1024 * 1024;
+/// 每个连接的页缓存大小（负数表示KiB，即64MB）
+const CACHE_SIZE_KIB: i64 = -64 * 1024;
+
+fn db_error(e: rusqlite::Error) -> AppError {
+    AppError::Database(e.to_string())
+}
+
+/// 注册查询中使用的自定义函数
+/// `bytes_extra_sender(BytesExtra)`：群聊消息的发送者wxid，不是BytesExtra时为NULL
+fn register_functions(conn: &Connection) -> rusqlite::Result<()> {
+    conn.create_scalar_function(
+        "bytes_extra_sender",
+        1,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        |ctx| {
+            let ValueRef::Blob(bytes) = ctx.get_raw(0) else {
+                return Ok(None);
+            };
+            Ok(ProtobufParser::decode_bytes_extra(bytes)
+                .ok()
+                .and_then(|extra| extra.value_of(BytesExtraType::Sender)))
+        },
+    )
+}
+
+/// 连接池参数
+#[derive(Debug, Clone)]
+pub struct PoolConfig {
+    /// 同时打开的读连接上限
+    pub max_size: usize,
+    /// 读连接是否以只读方式打开
+    pub read_only: bool,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            max_size: DEFAULT_POOL_SIZE,
+            read_only: true,
+        }
+    }
+}
+
+struct PoolState {
+    idle: Vec<Connection>,
+    /// 已打开（含借出）的读连接数
+    open: usize,
+}
+
+/// 有上限的SQLite连接池
+/// 读连接按需打开并复用；写操作（建索引、合并、清理）通过单独的写连接串行执行
 pub struct DatabasePool {
     db_path: String,
+    config: PoolConfig,
+    state: Mutex<PoolState>,
+    available: Condvar,
+    writer: Mutex<Option<Connection>>,
+}
+
+/// 从连接池借出的连接，离开作用域时归还
+pub struct PooledConnection<'a> {
+    pool: &'a DatabasePool,
+    conn: Option<Connection>,
+}
+
+impl Deref for PooledConnection<'_> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.conn.as_ref().expect("connection already returned")
+    }
+}
+
+impl Drop for PooledConnection<'_> {
+    fn drop(&mut self) {
+        if let Some(conn) = self.conn.take() {
+            let mut state = self.pool.lock_state();
+            state.idle.push(conn);
+            self.pool.available.notify_one();
+        }
+    }
 }
 
 impl DatabasePool {
     pub fn new(db_path: &str) -> Result<Self> {
+        Self::with_config(db_path, PoolConfig::default())
+    }
+
+    pub fn with_config(db_path: &str, config: PoolConfig) -> Result<Self> {
         if !Path::new(db_path).exists() {
             return Err(anyhow::anyhow!("Database file not found: {}", db_path).into());
         }
+        if config.max_size == 0 {
+            return Err(AppError::ValidationFailed(
+                "Pool size must be at least 1".to_string(),
+            ));
+        }
 
         Ok(Self {
             db_path: db_path.to_string(),
+            config,
+            state: Mutex::new(PoolState {
+                idle: Vec::new(),
+                open: 0,
+            }),
+            available: Condvar::new(),
+            writer: Mutex::new(None),
         })
     }
 
-    pub fn get_connection(&self) -> Result<Connection> {
-        Connection::open(&self.db_path)
-            .with_context(|| format!("Failed to open database: {}", self.db_path))
+    fn lock_state(&self) -> MutexGuard<'_, PoolState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// 打开新连接并设置缓存、内存映射等参数
+    fn open(&self, read_only: bool) -> Result<Connection> {
+        let flags = if read_only {
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX
+        } else {
+            OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_NO_MUTEX
+        };
+        let conn = Connection::open_with_flags(&self.db_path, flags)
+            .with_context(|| format!("Failed to open database: {}", self.db_path))?;
+
+        conn.busy_timeout(BUSY_TIMEOUT).map_err(db_error)?;
+        register_functions(&conn).map_err(db_error)?;
+        conn.pragma_update(None, "mmap_size", MMAP_SIZE)
+            .map_err(db_error)?;
+        conn.pragma_update(None, "cache_size", CACHE_SIZE_KIB)
+            .map_err(db_error)?;
+        conn.pragma_update(None, "temp_store", "MEMORY")
+            .map_err(db_error)?;
+        // 不修改journal_mode：该设置会持久写入用户的数据库文件
+        Ok(conn)
+    }
+
+    /// 借出一个读连接，连接数已达上限时等待其他请求归还
+    pub fn get_connection(&self) -> Result<PooledConnection<'_>> {
+        let mut state = self.lock_state();
+        loop {
+            if let Some(conn) = state.idle.pop() {
+                return Ok(PooledConnection {
+                    pool: self,
+                    conn: Some(conn),
+                });
+            }
+
+            if state.open < self.config.max_size {
+                state.open += 1;
+                drop(state);
+                return match self.open(self.config.read_only) {
+                    Ok(conn) => Ok(PooledConnection {
+                        pool: self,
+                        conn: Some(conn),
+                    }),
+                    Err(e) => {
+                        self.lock_state().open -= 1;
+                        self.available.notify_one();
+                        Err(e)
+                    }
+                };
+            }
+
+            let (guard, timeout) = self
+                .available
+                .wait_timeout(state, ACQUIRE_TIMEOUT)
+                .unwrap_or_else(|e| e.into_inner());
+            state = guard;
+            if timeout.timed_out() && state.idle.is_empty() {
+                return Err(AppError::Timeout(format!(
+                    "No database connection available for {}",
+                    self.db_path
+                )));
+            }
+        }
+    }
+
+    /// 使用写连接执行操作，首次调用时打开
+    pub fn with_writer<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&Connection) -> Result<T>,
+    {
+        let mut writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        if writer.is_none() {
+            *writer = Some(self.open(false)?);
+        }
+        f(writer.as_ref().expect("writer opened above"))
+    }
+
+    /// 当前已打开的读连接数
+    pub fn open_connections(&self) -> usize {
+        self.lock_state().open
     }
 }
 
+/// 数据库访问基础类，克隆后共享同一个连接池
+#[derive(Clone)]
 pub struct DatabaseBase {
-    pool: DatabasePool,
-    existed_tables: Vec<String>,
+    pool: Arc<DatabasePool>,
+    existed_tables: Arc<Vec<String>>,
 }
 
 impl DatabaseBase {
     pub fn new(db_path: &str) -> Result<Self> {
-        let pool = DatabasePool::new(db_path)?;
-        let mut db = Self {
-            pool,
-            existed_tables: Vec::new(),
-        };
-        db.load_tables()?;
-        Ok(db)
+        Self::with_config(db_path, PoolConfig::default())
     }
 
-    fn load_tables(&mut self) -> Result<()> {
-        let conn = self.pool.get_connection()?;
-        let mut stmt = conn.prepare(
-            "SELECT name FROM sqlite_master WHERE type='table' AND name!='sqlite_sequence'"
-        )?;
-        
-        let tables = stmt.query_map([], |row| {
-            Ok(row.get::<_, String>(0)?)
-        })?;
-
-        self.existed_tables.clear();
-        for table in tables {
-            self.existed_tables.push(table?);
-        }
+    pub fn with_config(db_path: &str, config: PoolConfig) -> Result<Self> {
+        let pool = DatabasePool::with_config(db_path, config)?;
+        let existed_tables = Self::load_tables(&pool)?;
+        Ok(Self {
+            pool: Arc::new(pool),
+            existed_tables: Arc::new(existed_tables),
+        })
+    }
 
-        Ok(())
+    fn load_tables(pool: &DatabasePool) -> Result<Vec<String>> {
+        let conn = pool.get_connection()?;
+        let mut stmt = conn
+            .prepare("SELECT name FROM sqlite_master WHERE type='table' AND name!='sqlite_sequence'")
+            .map_err(db_error)?;
+
+        let tables = stmt
+            .query_map([], |row| row.get::<_, String>(0))
+            .map_err(db_error)?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error)?;
+        Ok(tables)
     }
 
     pub fn table_exists(&self, table_name: &str) -> bool {
         self.existed_tables.iter().any(|t| t.eq_ignore_ascii_case(table_name))
     }
 
+    /// 表的列名，表不存在时为空
+    pub fn table_columns(&self, table_name: &str) -> Result<Vec<String>> {
+        if !self.table_exists(table_name) {
+            return Ok(Vec::new());
+        }
+        let sql = format!("PRAGMA table_info(\"{}\")", table_name.replace('"', "\"\""));
+        self.execute_query(&sql, &[], |row| row.get::<_, String>(1))
+    }
+
     pub fn execute_query<T, F>(&self, sql: &str, params: &[&dyn rusqlite::ToSql], mapper: F) -> Result<Vec<T>>
     where
         F: FnMut(&Row) -> rusqlite::Result<T>,
     {
         let conn = self.pool.get_connection()?;
-        let mut stmt = conn.prepare(sql)?;
-        let rows = stmt.query_map(params, mapper)?;
-        
+        let mut stmt = conn.prepare_cached(sql).map_err(db_error)?;
+        let rows = stmt.query_map(params, mapper).map_err(db_error)?;
+
         let mut results = Vec::new();
         for row in rows {
-            results.push(row?);
+            results.push(row.map_err(db_error)?);
         }
         Ok(results)
     }
 
     pub fn execute(&self, sql: &str, params: &[&dyn rusqlite::ToSql]) -> Result<usize> {
-        let conn = self.pool.get_connection()?;
-        let count = conn.execute(sql, params)?;
-        Ok(count)
+        self.pool
+            .with_writer(|conn| conn.execute(sql, params).map_err(db_error))
     }
 
     pub fn execute_batch(&self, sql: &str) -> Result<()> {
-        let conn = self.pool.get_connection()?;
-        conn.execute_batch(sql)?;
-        Ok(())
+        self.pool
+            .with_writer(|conn| conn.execute_batch(sql).map_err(db_error))
     }
 
     /// 获取数据库路径
     pub fn get_db_path(&self) -> &str {
         &self.pool.db_path
     }
+
+    /// 底层连接池
+    pub fn pool(&self) -> &DatabasePool {
+        &self.pool
+    }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn create_test_db() -> (TempDir, String) {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let conn = Connection::open(&db_path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE MSG (localId INTEGER PRIMARY KEY, StrTalker TEXT);
+             INSERT INTO MSG (StrTalker) VALUES ('a'), ('b'), ('c');",
+        )
+        .unwrap();
+        (temp_dir, db_path.to_str().unwrap().to_string())
+    }
+
+    #[test]
+    fn test_pool_reuses_connections() {
+        let (_temp_dir, db_path) = create_test_db();
+        let config = PoolConfig {
+            max_size: 2,
+            ..Default::default()
+        };
+        let db = DatabaseBase::with_config(&db_path, config).unwrap();
+        assert!(db.table_exists("msg"));
+
+        for _ in 0..10 {
+            let rows = db
+                .execute_query("SELECT StrTalker FROM MSG", &[], |row| row.get::<_, String>(0))
+                .unwrap();
+            assert_eq!(rows.len(), 3);
+        }
+        assert_eq!(db.pool().open_connections(), 1);
+
+        // 多个线程共享连接池时，打开的连接数不超过上限
+        std::thread::scope(|s| {
+            for _ in 0..8 {
+                let db = db.clone();
+                s.spawn(move || {
+                    let conn = db.pool().get_connection().unwrap();
+                    let count: i64 = conn
+                        .query_row("SELECT COUNT(*) FROM MSG", [], |row| row.get(0))
+                        .unwrap();
+                    assert_eq!(count, 3);
+                });
+            }
+        });
+        assert!(db.pool().open_connections() <= 2);
+    }
+
+    #[test]
+    fn test_read_connections_are_read_only() {
+        let (_temp_dir, db_path) = create_test_db();
+        let db = DatabaseBase::new(&db_path).unwrap();
+
+        let conn = db.pool().get_connection().unwrap();
+        assert!(conn.execute("DELETE FROM MSG", []).is_err());
+        drop(conn);
+
+        // 写操作走单独的写连接
+        db.execute_batch("CREATE INDEX IF NOT EXISTS idx_MSG_StrTalker ON MSG(StrTalker);")
+            .unwrap();
+        let deleted = db.execute("DELETE FROM MSG WHERE StrTalker = ?", &[&"a"]).unwrap();
+        assert_eq!(deleted, 1);
+
+        let rows = db
+            .execute_query("SELECT COUNT(*) FROM MSG", &[], |row| row.get::<_, i64>(0))
+            .unwrap();
+        assert_eq!(rows, vec![2]);
+    }
+}
@@ -0,0 +1,71 @@
+use crate::db::dbbase::DatabaseBase;
+use crate::utils::Result;
+
+/// 本地缓存的联系人头像
+/// 3.x在Misc.db的ContactHeadImg1表，4.x在head_image.db的head_image表
+pub struct HeadImageHandler {
+    db: DatabaseBase,
+}
+
+impl HeadImageHandler {
+    pub fn new(db_path: &str) -> Result<Self> {
+        let db = DatabaseBase::new(db_path)?;
+        Ok(Self { db })
+    }
+
+    /// 获取联系人的头像图片，同一联系人有多条时取最新的
+    pub fn get_head_image(&self, wxid: &str) -> Result<Option<Vec<u8>>> {
+        let sql = if self.db.table_exists("ContactHeadImg1") {
+            "SELECT smallHeadBuf FROM ContactHeadImg1 WHERE usrName = ? ORDER BY createTime DESC"
+        } else if self.db.table_exists("head_image") {
+            "SELECT image_buffer FROM head_image WHERE username = ? ORDER BY update_time DESC"
+        } else {
+            return Ok(None);
+        };
+
+        let images = self.db.execute_query(sql, &[&wxid], |row| row.get::<_, Option<Vec<u8>>>(0))?;
+        Ok(images.into_iter().flatten().find(|image| !image.is_empty()))
+    }
+}
+
+/// 按文件头识别头像的图片格式
+pub fn image_mime(data: &[u8]) -> &'static str {
+    match data {
+        [0xFF, 0xD8, 0xFF, ..] => "image/jpeg",
+        [0x89, b'P', b'N', b'G', ..] => "image/png",
+        [b'G', b'I', b'F', b'8', ..] => "image/gif",
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => "image/webp",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::Connection;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_get_head_image() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("Misc.db");
+        let conn = Connection::open(&db_path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE ContactHeadImg1 (usrName TEXT, createTime INTEGER, smallHeadBuf BLOB, m_headImgMD5 TEXT);",
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO ContactHeadImg1 VALUES (?, ?, ?, ?), (?, ?, ?, ?)",
+            rusqlite::params![
+                "wxid_a", 1, vec![0xFFu8, 0xD8, 0xFF, 0x01], "old",
+                "wxid_a", 2, vec![0x89u8, b'P', b'N', b'G'], "new"
+            ],
+        )
+        .unwrap();
+
+        let handler = HeadImageHandler::new(db_path.to_str().unwrap()).unwrap();
+        let image = handler.get_head_image("wxid_a").unwrap().unwrap();
+        assert_eq!(image_mime(&image), "image/png");
+        assert!(handler.get_head_image("wxid_b").unwrap().is_none());
+    }
+}
@@ -2,24 +2,36 @@ pub mod dbbase;
 pub mod msg;
 pub mod msg_query;
 pub mod msg_list;
+pub mod msg_index;
+pub mod search_query;
 pub mod contact;
+pub mod chatroom;
+pub mod head_image;
 pub mod media;
 pub mod favorite;
 pub mod sns;
 pub mod merge;
 pub mod msg_parser;
+pub mod msg_content;
+pub mod money;
 pub mod bytes_extra;
 pub mod protobuf_parser;
 pub mod lz4_utils;
 pub mod utils;
+pub mod workspace;
 
 pub use dbbase::DatabaseBase;
 pub use msg::MsgHandler;
 pub use msg_query::MsgQuery;
 pub use msg_list::MsgList;
-pub use contact::{ContactHandler, Contact};
+pub use contact::{ContactHandler, Contact, ContactKind};
+pub use chatroom::{ChatroomHandler, Chatroom};
+pub use head_image::HeadImageHandler;
 pub use media::{MediaHandler, MediaInfo};
 pub use favorite::{FavoriteHandler, FavoriteItem};
 pub use sns::{SnsHandler, MomentItem};
 pub use msg_parser::MessageParser;
+pub use msg_content::MessageContent;
+pub use money::MoneyLedger;
+pub use workspace::{Workspace, WorkspaceInfo};
 
@@ -0,0 +1,306 @@
+use crate::db::msg_content::PayStatus;
+use crate::db::utils::Message;
+use chrono::{Local, TimeZone};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// 转账消息类型
+pub const TRANSFER_TYPE: (i32, i32) = (49, 2000);
+/// 红包消息类型
+pub const RED_PACKET_TYPE: (i32, i32) = (49, 2001);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MoneyKind {
+    Transfer,
+    RedPacket,
+}
+
+/// 资金方向，相对于自己
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MoneyDirection {
+    Outgoing,
+    Incoming,
+}
+
+/// 一笔转账或一个红包
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MoneyRecord {
+    pub kind: MoneyKind,
+    pub transfer_id: String,
+    pub direction: MoneyDirection,
+    /// 金额（元），红包为0
+    pub amount: f64,
+    pub memo: String,
+    /// 按transferid合并发起、收款、退还消息后的最终状态
+    pub status: PayStatus,
+    /// 发起时间
+    pub create_time: i64,
+    /// 收款或退还的时间
+    pub settle_time: Option<i64>,
+    /// 发起消息的localId，只有收款消息时为收款消息的localId
+    pub local_id: i64,
+}
+
+/// 按月汇总的已收款金额
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MonthlyMoney {
+    /// `YYYY-MM`
+    pub month: String,
+    pub sent: f64,
+    pub received: f64,
+    pub count: i64,
+}
+
+/// 与某个联系人的资金往来
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MoneyLedger {
+    pub wxid: String,
+    /// 对方已收款的转账合计
+    pub total_sent: f64,
+    /// 自己已收款的转账合计
+    pub total_received: f64,
+    /// 尚未收款的转账合计
+    pub pending: f64,
+    pub red_packets_sent: i64,
+    pub red_packets_received: i64,
+    pub months: Vec<MonthlyMoney>,
+    pub records: Vec<MoneyRecord>,
+}
+
+impl MoneyLedger {
+    /// 由转账和红包消息（按时间升序）生成账本
+    pub fn build(wxid: &str, messages: &[Message]) -> Self {
+        let mut records: Vec<MoneyRecord> = Vec::new();
+        let mut by_transfer_id: HashMap<String, usize> = HashMap::new();
+
+        for msg in messages {
+            let Some(pay) = msg.pay_info() else {
+                continue;
+            };
+            let is_sender = msg.is_sender == 1;
+
+            if (msg.msg_type, msg.sub_type) == RED_PACKET_TYPE {
+                let direction = if is_sender {
+                    MoneyDirection::Outgoing
+                } else {
+                    MoneyDirection::Incoming
+                };
+                records.push(MoneyRecord {
+                    kind: MoneyKind::RedPacket,
+                    transfer_id: pay.transfer_id,
+                    direction,
+                    amount: 0.0,
+                    memo: pay.receiver_title,
+                    status: pay.status,
+                    create_time: msg.create_time,
+                    settle_time: None,
+                    local_id: msg.local_id,
+                });
+                continue;
+            }
+
+            // 发起消息由付款方发出，收款和退还消息由收款方发出
+            let direction = if pay.payer_username == wxid {
+                MoneyDirection::Incoming
+            } else if pay.receiver_username == wxid {
+                MoneyDirection::Outgoing
+            } else {
+                match (pay.status, is_sender) {
+                    (PayStatus::Received | PayStatus::Refunded, true) => MoneyDirection::Incoming,
+                    (PayStatus::Received | PayStatus::Refunded, false) => MoneyDirection::Outgoing,
+                    (_, true) => MoneyDirection::Outgoing,
+                    (_, false) => MoneyDirection::Incoming,
+                }
+            };
+            let settled = matches!(pay.status, PayStatus::Received | PayStatus::Refunded);
+
+            let existing = if pay.transfer_id.is_empty() {
+                None
+            } else {
+                by_transfer_id.get(&pay.transfer_id).copied()
+            };
+            match existing {
+                Some(idx) => {
+                    let record = &mut records[idx];
+                    if record.amount == 0.0 {
+                        record.amount = pay.amount;
+                    }
+                    if record.memo.is_empty() {
+                        record.memo = pay.pay_memo;
+                    }
+                    if settled {
+                        record.status = pay.status;
+                        record.settle_time = Some(msg.create_time);
+                    }
+                }
+                None => {
+                    if !pay.transfer_id.is_empty() {
+                        by_transfer_id.insert(pay.transfer_id.clone(), records.len());
+                    }
+                    let create_time = if settled && pay.begin_transfer_time > 0 {
+                        pay.begin_transfer_time
+                    } else {
+                        msg.create_time
+                    };
+                    records.push(MoneyRecord {
+                        kind: MoneyKind::Transfer,
+                        transfer_id: pay.transfer_id,
+                        direction,
+                        amount: pay.amount,
+                        memo: pay.pay_memo,
+                        status: pay.status,
+                        create_time,
+                        settle_time: settled.then_some(msg.create_time),
+                        local_id: msg.local_id,
+                    });
+                }
+            }
+        }
+
+        let mut ledger = MoneyLedger {
+            wxid: wxid.to_string(),
+            ..Default::default()
+        };
+        // 按分累加，避免浮点误差
+        let (mut sent, mut received, mut pending) = (0i64, 0i64, 0i64);
+        let mut months: BTreeMap<String, (i64, i64, i64)> = BTreeMap::new();
+        for record in &records {
+            let cents = to_cents(record.amount);
+            match (record.kind, record.status) {
+                (MoneyKind::RedPacket, _) => match record.direction {
+                    MoneyDirection::Outgoing => ledger.red_packets_sent += 1,
+                    MoneyDirection::Incoming => ledger.red_packets_received += 1,
+                },
+                (MoneyKind::Transfer, PayStatus::Received) => {
+                    let time = record.settle_time.unwrap_or(record.create_time);
+                    let month = months.entry(month_of(time)).or_default();
+                    month.2 += 1;
+                    match record.direction {
+                        MoneyDirection::Outgoing => {
+                            sent += cents;
+                            month.0 += cents;
+                        }
+                        MoneyDirection::Incoming => {
+                            received += cents;
+                            month.1 += cents;
+                        }
+                    }
+                }
+                (MoneyKind::Transfer, PayStatus::Sent) => pending += cents,
+                _ => {}
+            }
+        }
+
+        ledger.total_sent = from_cents(sent);
+        ledger.total_received = from_cents(received);
+        ledger.pending = from_cents(pending);
+        ledger.months = months
+            .into_iter()
+            .map(|(month, (sent, received, count))| MonthlyMoney {
+                month,
+                sent: from_cents(sent),
+                received: from_cents(received),
+                count,
+            })
+            .collect();
+        ledger.records = records;
+        ledger
+    }
+}
+
+fn to_cents(amount: f64) -> i64 {
+    (amount * 100.0).round() as i64
+}
+
+fn from_cents(cents: i64) -> f64 {
+    cents as f64 / 100.0
+}
+
+fn month_of(timestamp: i64) -> String {
+    Local
+        .timestamp_opt(timestamp, 0)
+        .single()
+        .map(|dt| dt.format("%Y-%m").to_string())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::msg_content::{AppMsg, MessageContent, WcPayInfo};
+
+    fn pay_message(local_id: i64, time: i64, is_sender: i32, sub_type: i32, pay: WcPayInfo) -> Message {
+        let app = AppMsg {
+            app_type: sub_type,
+            pay: Some(pay),
+            ..Default::default()
+        };
+        Message {
+            id: 0,
+            local_id,
+            msg_svr_id: local_id,
+            msg_type: 49,
+            sub_type,
+            type_name: String::new(),
+            create_time: time,
+            create_time_str: String::new(),
+            is_sender,
+            talker: "alice".to_string(),
+            str_talker: "alice".to_string(),
+            sender_wxid: String::new(),
+            content: String::new(),
+            display_content: String::new(),
+            src: String::new(),
+            extra: MessageContent::App(Box::new(app)).to_extra(),
+            reply_to: None,
+            event: None,
+        }
+    }
+
+    fn transfer(pay_subtype: i32, transfer_id: &str, amount: f64) -> WcPayInfo {
+        WcPayInfo {
+            pay_subtype,
+            status: PayStatus::from_subtype(pay_subtype),
+            amount,
+            transfer_id: transfer_id.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_money_ledger() {
+        let messages = vec![
+            // 自己转出100，对方已收款
+            pay_message(1, 1_700_000_000, 1, 2000, transfer(1, "t1", 100.0)),
+            pay_message(2, 1_700_000_100, 0, 2000, transfer(3, "t1", 100.0)),
+            // 对方转入20.1，自己已收款
+            pay_message(3, 1_700_000_200, 0, 2000, transfer(1, "t2", 20.1)),
+            pay_message(4, 1_700_000_300, 1, 2000, transfer(3, "t2", 20.1)),
+            // 对方转入50，已退还
+            pay_message(5, 1_700_000_400, 0, 2000, transfer(1, "t3", 50.0)),
+            pay_message(6, 1_700_000_500, 1, 2000, transfer(4, "t3", 50.0)),
+            // 自己转出8，对方尚未收款
+            pay_message(7, 1_700_000_600, 1, 2000, transfer(1, "t4", 8.0)),
+            pay_message(8, 1_700_000_700, 0, 2001, WcPayInfo::default()),
+        ];
+
+        let ledger = MoneyLedger::build("alice", &messages);
+        assert_eq!(ledger.records.len(), 5);
+        assert_eq!(ledger.total_sent, 100.0);
+        assert_eq!(ledger.total_received, 20.1);
+        assert_eq!(ledger.pending, 8.0);
+        assert_eq!(ledger.red_packets_received, 1);
+
+        let t1 = &ledger.records[0];
+        assert_eq!(t1.direction, MoneyDirection::Outgoing);
+        assert_eq!(t1.status, PayStatus::Received);
+        assert_eq!(t1.settle_time, Some(1_700_000_100));
+        assert_eq!(ledger.records[1].direction, MoneyDirection::Incoming);
+        assert_eq!(ledger.records[2].status, PayStatus::Refunded);
+
+        assert_eq!(ledger.months.len(), 1);
+        assert_eq!(ledger.months[0].count, 2);
+    }
+}
@@ -1,48 +1,80 @@
 use crate::db::dbbase::DatabaseBase;
+use crate::db::msg_index::{highlight, FtsQuery, MsgIndex, SearchHit};
+use crate::db::money::{MoneyLedger, RED_PACKET_TYPE, TRANSFER_TYPE};
 use crate::db::msg_query::MsgQuery;
-use crate::db::msg_list::MsgList;
+use crate::db::search_query::SearchQuery;
+use crate::db::msg_list::{MsgCursor, MsgList, MsgPage, PageDirection};
 use crate::db::utils::Message;
-use crate::utils::Result;
+use crate::utils::{AppError, Result};
 use std::collections::HashMap;
+use std::path::Path;
 
-/// MSG数据库处理器
+/// 系统消息的(Type, SubType)：普通系统通知和撤回等带XML的通知
+const SYSTEM_MSG_TYPES: [(i32, Option<i32>); 2] = [(10000, None), (10002, None)];
+/// 逐页读取全部消息时的每页条数
+const EXPORT_PAGE_SIZE: i64 = 1000;
+/// 读取系统事件时每批的消息数上限
+const SYSTEM_EVENT_BATCH: usize = 500;
+
+/// MSG数据库处理器，可同时查询多个分片（Multi/MSG0.db、MSG1.db……）
 pub struct MsgHandler {
-    db_path: String,
-    db: DatabaseBase,
+    db_paths: Vec<String>,
+    shards: Vec<DatabaseBase>,
     query: MsgQuery,
     list: MsgList,
+    index: Option<MsgIndex>,
 }
 
 impl MsgHandler {
     pub fn new(db_path: &str) -> Result<Self> {
-        let db = DatabaseBase::new(db_path)?;
-        let db_path_str = db_path.to_string();
-        let query = MsgQuery::new(DatabaseBase::new(&db_path_str)?);
-        let list = MsgList::new(DatabaseBase::new(&db_path_str)?);
+        Self::with_shards(&[db_path])
+    }
+
+    /// 打开多个MSG分片，查询结果按时间统一排序，无需先合并数据库
+    pub fn with_shards<S: AsRef<str>>(db_paths: &[S]) -> Result<Self> {
+        if db_paths.is_empty() {
+            return Err(AppError::BadRequest("No MSG database given".to_string()));
+        }
+
+        let shards = db_paths
+            .iter()
+            .map(|path| DatabaseBase::new(path.as_ref()))
+            .collect::<Result<Vec<_>>>()?;
+        // 查询和列表共享同一组连接池
+        let query = MsgQuery::new(shards.clone());
+        let list = MsgList::new(shards.clone());
         Ok(Self {
-            db_path: db_path_str,
-            db,
+            db_paths: db_paths.iter().map(|p| p.as_ref().to_string()).collect(),
+            shards,
             query,
             list,
+            index: None,
         })
     }
 
+    /// 各分片的数据库路径
+    pub fn db_paths(&self) -> &[String] {
+        &self.db_paths
+    }
+
     /// 添加索引以加快查询速度
     pub fn add_indexes(&self) -> Result<()> {
-        if !self.db.table_exists("MSG") {
-            return Ok(());
-        }
+        for db in &self.shards {
+            if !db.table_exists("MSG") {
+                continue;
+            }
 
-        // 添加更多索引以优化查询性能
-        self.db.execute_batch(
-            "CREATE INDEX IF NOT EXISTS idx_MSG_StrTalker ON MSG(StrTalker);
-             CREATE INDEX IF NOT EXISTS idx_MSG_CreateTime ON MSG(CreateTime);
-             CREATE INDEX IF NOT EXISTS idx_MSG_StrTalker_CreateTime ON MSG(StrTalker, CreateTime);
-             CREATE INDEX IF NOT EXISTS idx_MSG_MsgSvrID ON MSG(MsgSvrID);
-             CREATE INDEX IF NOT EXISTS idx_MSG_IsSender ON MSG(IsSender);
-             CREATE INDEX IF NOT EXISTS idx_MSG_Type ON MSG(Type);
-             CREATE INDEX IF NOT EXISTS idx_MSG_StrTalker_IsSender ON MSG(StrTalker, IsSender);"
-        )?;
+            // 添加更多索引以优化查询性能
+            db.execute_batch(
+                "CREATE INDEX IF NOT EXISTS idx_MSG_StrTalker ON MSG(StrTalker);
+                 CREATE INDEX IF NOT EXISTS idx_MSG_CreateTime ON MSG(CreateTime);
+                 CREATE INDEX IF NOT EXISTS idx_MSG_StrTalker_CreateTime ON MSG(StrTalker, CreateTime);
+                 CREATE INDEX IF NOT EXISTS idx_MSG_MsgSvrID ON MSG(MsgSvrID);
+                 CREATE INDEX IF NOT EXISTS idx_MSG_IsSender ON MSG(IsSender);
+                 CREATE INDEX IF NOT EXISTS idx_MSG_Type ON MSG(Type);
+                 CREATE INDEX IF NOT EXISTS idx_MSG_StrTalker_IsSender ON MSG(StrTalker, IsSender);"
+            )?;
+        }
 
         Ok(())
     }
@@ -75,6 +107,264 @@ impl MsgHandler {
     ) -> Result<Vec<Message>> {
         self.list.get_msg_list(wxid, start_index, page_size, start_time, end_time)
     }
+
+    /// 按游标获取一页消息
+    pub fn get_msg_page(
+        &self,
+        wxid: Option<&str>,
+        cursor: Option<&MsgCursor>,
+        direction: PageDirection,
+        page_size: i64,
+        start_time: Option<i64>,
+        end_time: Option<i64>,
+    ) -> Result<MsgPage> {
+        self.list.get_msg_page(wxid, cursor, direction, page_size, start_time, end_time)
+    }
+
+    /// 从最早的消息开始逐页读取所有消息，每页交给`f`处理，用于导出
+    /// 按游标翻页，导出大表时每页的查询开销不随位置增长
+    pub fn for_each_page<F>(
+        &self,
+        wxid: Option<&str>,
+        start_time: Option<i64>,
+        end_time: Option<i64>,
+        mut f: F,
+    ) -> Result<()>
+    where
+        F: FnMut(Vec<Message>) -> Result<()>,
+    {
+        let mut cursor = None;
+        loop {
+            let page = self.get_msg_page(
+                wxid,
+                cursor.as_ref(),
+                PageDirection::Forward,
+                EXPORT_PAGE_SIZE,
+                start_time,
+                end_time,
+            )?;
+            f(page.messages)?;
+            match page.next_cursor {
+                Some(next) => cursor = Some(next),
+                None => return Ok(()),
+            }
+        }
+    }
+
+    /// 获取指定时间附近的一页消息
+    pub fn get_msg_page_at(
+        &self,
+        wxid: Option<&str>,
+        timestamp: i64,
+        page_size: i64,
+        start_time: Option<i64>,
+        end_time: Option<i64>,
+    ) -> Result<MsgPage> {
+        self.list.get_msg_page_at(wxid, timestamp, page_size, start_time, end_time)
+    }
+
+    /// 搜索消息
+    pub fn search_messages(
+        &self,
+        wxid: Option<&str>,
+        keyword: &str,
+        start_time: Option<i64>,
+        end_time: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<Message>> {
+        self.list.search_messages(wxid, keyword, start_time, end_time, limit)
+    }
+
+    /// 启用全文索引，索引保存在单独的文件中
+    pub fn enable_index(&mut self, index_path: &Path) -> Result<()> {
+        self.index = Some(MsgIndex::open(index_path)?);
+        Ok(())
+    }
+
+    /// 全文索引，未启用时返回None
+    pub fn index(&self) -> Option<&MsgIndex> {
+        self.index.as_ref()
+    }
+
+    /// 将各分片中新增的消息加入全文索引，返回新增条数
+    pub fn update_index(&self) -> Result<usize> {
+        let index = self
+            .index
+            .as_ref()
+            .ok_or_else(|| AppError::BadRequest("Full-text index is not enabled".to_string()))?;
+
+        let mut total = 0;
+        for (path, db) in self.db_paths.iter().zip(&self.shards) {
+            total += index.update_shard(path, db)?;
+        }
+        Ok(total)
+    }
+
+    /// 是否有分片的消息尚未加入全文索引
+    pub fn index_stale(&self) -> Result<bool> {
+        let Some(index) = &self.index else {
+            return Ok(false);
+        };
+        for (path, db) in self.db_paths.iter().zip(&self.shards) {
+            if index.is_stale(path, db)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// 搜索消息，查询语法见`SearchQuery`，结果带摘要和高亮区间
+    /// 只含关键词且已启用全文索引时按相关度排序，否则在MSG表上按时间倒序查询
+    pub fn search(
+        &self,
+        query: &str,
+        wxid: Option<&str>,
+        start_time: Option<i64>,
+        end_time: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<SearchHit>> {
+        let query = SearchQuery::parse(query)?;
+        let fts = self.index.as_ref().zip(FtsQuery::from_query(&query));
+        let Some((index, fts)) = fts else {
+            let terms = query.highlight_terms();
+            let messages = self.list.search_query(&query.to_sql(), wxid, start_time, end_time, limit)?;
+            return Ok(messages
+                .into_iter()
+                .map(|message| Self::search_hit(message, &terms, 0.0))
+                .collect());
+        };
+
+        // 注册工作区时已在后台建立索引，这里只补充新增的消息
+        if self.index_stale()? {
+            self.update_index()?;
+        }
+        let hits = index.search(&fts, wxid, start_time, end_time, limit)?;
+
+        // 按分片批量读取命中的消息，再按相关度顺序输出
+        let mut by_shard: HashMap<usize, Vec<i64>> = HashMap::new();
+        for hit in &hits {
+            if let Some(shard) = self.db_paths.iter().position(|p| *p == hit.shard_path) {
+                by_shard.entry(shard).or_default().push(hit.local_id);
+            }
+        }
+        let mut messages = HashMap::new();
+        for (shard, local_ids) in by_shard {
+            for msg in self.list.get_by_local_ids(shard, &local_ids)? {
+                messages.insert((self.db_paths[shard].as_str(), msg.local_id), msg);
+            }
+        }
+
+        Ok(hits
+            .iter()
+            .filter_map(|hit| {
+                let message = messages.remove(&(hit.shard_path.as_str(), hit.local_id))?;
+                Some(Self::search_hit(message, &fts.terms, hit.score))
+            })
+            .enumerate()
+            .map(|(idx, mut hit)| {
+                hit.message.id = idx as i64;
+                hit
+            })
+            .collect())
+    }
+
+    fn search_hit(message: Message, terms: &[String], score: f64) -> SearchHit {
+        let (snippet, highlights) = highlight(&message.content, terms);
+        SearchHit {
+            message,
+            snippet,
+            highlights,
+            score,
+        }
+    }
+
+    /// 获取日期聊天统计
+    pub fn get_date_count(
+        &self,
+        wxid: Option<&str>,
+        start_time: Option<i64>,
+        end_time: Option<i64>,
+    ) -> Result<HashMap<String, serde_json::Value>> {
+        self.query.get_date_count(wxid, start_time, end_time)
+    }
+
+    /// 与联系人的转账和红包往来，按transferid合并同一笔转账的发起和收款消息
+    pub fn money_ledger(
+        &self,
+        wxid: &str,
+        start_time: Option<i64>,
+        end_time: Option<i64>,
+    ) -> Result<MoneyLedger> {
+        let types = [TRANSFER_TYPE, RED_PACKET_TYPE].map(|(msg_type, sub_type)| (msg_type, Some(sub_type)));
+        let messages = self.list.get_msgs_by_type(Some(wxid), &types, start_time, end_time)?;
+        Ok(MoneyLedger::build(wxid, &messages))
+    }
+
+    /// 会话中的系统事件（撤回、拍一拍、进退群），从游标之后按时间升序；`kinds`为空时返回所有类型
+    /// 事件类型需解析内容后才能确定，按批读取系统消息直到凑满`limit`条；只能向后翻页，`prev_cursor`总为空
+    pub fn system_events(
+        &self,
+        wxid: &str,
+        kinds: &[String],
+        cursor: Option<&MsgCursor>,
+        start_time: Option<i64>,
+        end_time: Option<i64>,
+        limit: i64,
+    ) -> Result<MsgPage> {
+        let limit = limit.max(0) as usize;
+        let batch = limit.clamp(1, SYSTEM_EVENT_BATCH) as i64;
+        let mut cursor = cursor.copied();
+        let mut events = Vec::new();
+        let mut exhausted = false;
+
+        while events.len() < limit {
+            let rows = self.list.get_msgs_by_type_after(
+                Some(wxid),
+                &SYSTEM_MSG_TYPES,
+                cursor.as_ref(),
+                batch,
+                start_time,
+                end_time,
+            )?;
+            let short = (rows.len() as i64) < batch;
+            let mut rows = rows.into_iter();
+            for (position, msg) in rows.by_ref() {
+                cursor = Some(position);
+                let matched = msg
+                    .event
+                    .as_ref()
+                    .is_some_and(|e| kinds.is_empty() || kinds.iter().any(|k| k == e.kind()));
+                if matched {
+                    events.push(msg);
+                    if events.len() == limit {
+                        break;
+                    }
+                }
+            }
+            // 凑满后提前停下时，本批剩余的行还没有看过，不能视为已读完
+            exhausted = short && rows.next().is_none();
+            if exhausted {
+                break;
+            }
+        }
+
+        self.list.link_related(&mut events)?;
+        Ok(MsgPage {
+            messages: events,
+            prev_cursor: None,
+            next_cursor: cursor.filter(|_| !exhausted),
+        })
+    }
+
+    /// 获取聊天最多的联系人
+    pub fn get_top_talkers(
+        &self,
+        top: i64,
+        start_time: Option<i64>,
+        end_time: Option<i64>,
+    ) -> Result<HashMap<String, serde_json::Value>> {
+        self.query.get_top_talkers(top, start_time, end_time)
+    }
 }
 
 #[cfg(test)]
@@ -82,6 +372,7 @@ mod tests {
     use super::*;
     use tempfile::TempDir;
     use rusqlite::Connection;
+    use crate::db::msg_content::SystemEvent;
 
     fn create_test_db() -> (TempDir, String) {
         let temp_dir = TempDir::new().unwrap();
@@ -143,4 +434,374 @@ mod tests {
         assert_eq!(messages.len(), 1);
         assert_eq!(messages[0].str_talker, "test_wxid");
     }
+
+    /// 创建包含完整MSG表结构的分片
+    fn create_shard(dir: &std::path::Path, name: &str, rows: &[(&str, i64, &str)]) -> String {
+        let db_path = dir.join(name);
+        let conn = Connection::open(&db_path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE MSG (
+                localId INTEGER PRIMARY KEY, MsgSvrID INTEGER, Type INTEGER, SubType INTEGER,
+                CreateTime INTEGER, IsSender INTEGER, TalkerId INTEGER, StrTalker TEXT,
+                StrContent TEXT, DisplayContent TEXT, BytesExtra BLOB, CompressContent BLOB
+            )",
+        ).unwrap();
+        for (talker, time, content) in rows {
+            conn.execute(
+                "INSERT INTO MSG (MsgSvrID, Type, SubType, CreateTime, IsSender, TalkerId, StrTalker, StrContent, DisplayContent)
+                 VALUES (?, 1, 0, ?, 0, ?, ?, ?, '')",
+                rusqlite::params![time, time, talker, talker, content],
+            ).unwrap();
+        }
+        db_path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_multi_shard_view() {
+        let temp_dir = TempDir::new().unwrap();
+        let shard0 = create_shard(temp_dir.path(), "MSG0.db", &[
+            ("alice", 100, "hello"),
+            ("alice", 300, "third"),
+            ("bob", 500, "hello bob"),
+        ]);
+        let shard1 = create_shard(temp_dir.path(), "MSG1.db", &[
+            ("alice", 200, "second"),
+            ("alice", 400, "hello again"),
+        ]);
+        let handler = MsgHandler::with_shards(&[shard0, shard1]).unwrap();
+        handler.add_indexes().unwrap();
+
+        let counts = handler.get_msg_count(None).unwrap();
+        assert_eq!(counts["alice"], 4);
+        assert_eq!(counts["total"], 5);
+
+        // 跨分片按时间统一排序后分页
+        let page = handler.get_msg_list(Some("alice"), 1, 2, None, None).unwrap();
+        let times: Vec<i64> = page.iter().map(|m| m.create_time).collect();
+        assert_eq!(times, vec![200, 300]);
+        assert_eq!(page[0].id, 1);
+
+        let all = handler.get_msg_list(None, 0, 10, None, None).unwrap();
+        let times: Vec<i64> = all.iter().map(|m| m.create_time).collect();
+        assert_eq!(times, vec![100, 200, 300, 400, 500]);
+
+        let found = handler.search_messages(None, "hello", None, None, 2).unwrap();
+        let times: Vec<i64> = found.iter().map(|m| m.create_time).collect();
+        assert_eq!(times, vec![500, 400]);
+
+        let top = handler.get_top_talkers(1, None, None).unwrap();
+        assert_eq!(top["alice"]["total_count"], 4);
+    }
+
+    #[test]
+    fn test_cursor_paging() {
+        let temp_dir = TempDir::new().unwrap();
+        let shard0 = create_shard(temp_dir.path(), "MSG0.db", &[
+            ("alice", 100, "a"),
+            ("alice", 200, "b"),
+            ("alice", 200, "c"),
+            ("alice", 500, "f"),
+        ]);
+        let shard1 = create_shard(temp_dir.path(), "MSG1.db", &[
+            ("alice", 200, "d"),
+            ("alice", 300, "e"),
+        ]);
+        let handler = MsgHandler::with_shards(&[shard0, shard1]).unwrap();
+        let contents = |page: &MsgPage| -> Vec<String> {
+            page.messages.iter().map(|m| m.content.clone()).collect()
+        };
+
+        // 同一时间的消息跨分片时也不重复、不遗漏
+        let first = handler.get_msg_page(Some("alice"), None, PageDirection::Forward, 2, None, None).unwrap();
+        assert_eq!(contents(&first), vec!["a", "b"]);
+        assert!(first.prev_cursor.is_none());
+
+        let cursor = MsgCursor::decode(&first.next_cursor.unwrap().encode()).unwrap();
+        let second = handler.get_msg_page(Some("alice"), Some(&cursor), PageDirection::Forward, 2, None, None).unwrap();
+        assert_eq!(contents(&second), vec!["c", "d"]);
+
+        let third = handler.get_msg_page(Some("alice"), second.next_cursor.as_ref(), PageDirection::Forward, 2, None, None).unwrap();
+        assert_eq!(contents(&third), vec!["e", "f"]);
+        assert!(third.next_cursor.is_none());
+
+        // 向前翻页回到上一页
+        let back = handler.get_msg_page(Some("alice"), third.prev_cursor.as_ref(), PageDirection::Backward, 2, None, None).unwrap();
+        assert_eq!(contents(&back), vec!["c", "d"]);
+        assert!(back.prev_cursor.is_some() && back.next_cursor.is_some());
+
+        let latest = handler.get_msg_page(Some("alice"), None, PageDirection::Backward, 3, None, None).unwrap();
+        assert_eq!(contents(&latest), vec!["d", "e", "f"]);
+
+        // 跳转到日期
+        let jumped = handler.get_msg_page_at(Some("alice"), 250, 2, None, None).unwrap();
+        assert_eq!(contents(&jumped), vec!["e", "f"]);
+        let jumped = handler.get_msg_page_at(Some("alice"), 900, 2, None, None).unwrap();
+        assert_eq!(contents(&jumped), vec!["e", "f"]);
+
+        assert!(MsgCursor::decode("not-a-cursor").is_err());
+    }
+
+    #[test]
+    fn test_full_text_index() {
+        let temp_dir = TempDir::new().unwrap();
+        let shard0 = create_shard(temp_dir.path(), "MSG0.db", &[
+            ("alice", 100, "今天晚饭吃什么"),
+            ("alice", 200, "Lunch meeting moved"),
+        ]);
+        let shard1 = create_shard(temp_dir.path(), "MSG1.db", &[
+            ("bob", 300, "晚饭取消了"),
+        ]);
+        let mut handler = MsgHandler::with_shards(&[shard0.clone(), shard1]).unwrap();
+        handler.enable_index(&temp_dir.path().join("msg_index.db")).unwrap();
+
+        let hits = handler.search("晚饭", None, None, None, 10).unwrap();
+        assert_eq!(hits.len(), 2);
+        let alice = hits.iter().find(|h| h.message.str_talker == "alice").unwrap();
+        assert_eq!(alice.highlights, vec![(2, 4)]);
+
+        let hits = handler.search("晚饭 -取消", None, None, None, 10).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].message.create_time, 100);
+
+        let hits = handler.search("lunch OR 取消", None, None, None, 10).unwrap();
+        assert_eq!(hits.len(), 2);
+        let hits = handler.search("\"meeting moved\"", Some("alice"), None, None, 10).unwrap();
+        assert_eq!(hits[0].snippet, "Lunch meeting moved");
+        assert_eq!(hits[0].highlights, vec![(6, 19)]);
+
+        // 新消息增量加入索引
+        Connection::open(&shard0).unwrap().execute(
+            "INSERT INTO MSG (MsgSvrID, Type, SubType, CreateTime, IsSender, TalkerId, StrTalker, StrContent, DisplayContent)
+             VALUES (400, 1, 0, 400, 1, 'alice', 'alice', '晚饭改到明天', '')",
+            [],
+        ).unwrap();
+        let hits = handler.search("晚饭", Some("alice"), None, None, 10).unwrap();
+        assert_eq!(hits.len(), 2);
+        assert_eq!(handler.update_index().unwrap(), 0);
+        assert_eq!(handler.index().unwrap().indexed_count().unwrap(), 4);
+        assert!(!handler.index_stale().unwrap());
+
+        // 并发更新读到旧进度时不会重复写入
+        Connection::open(temp_dir.path().join("msg_index.db")).unwrap()
+            .execute("UPDATE msg_shard SET last_local_id = 0", [])
+            .unwrap();
+        assert!(handler.index_stale().unwrap());
+        std::thread::scope(|scope| {
+            let updates: Vec<_> = (0..2).map(|_| scope.spawn(|| handler.update_index().unwrap())).collect();
+            assert_eq!(updates.into_iter().map(|u| u.join().unwrap()).sum::<usize>(), 0);
+        });
+        assert_eq!(handler.index().unwrap().indexed_count().unwrap(), 4);
+        assert_eq!(handler.search("晚饭", None, None, None, 10).unwrap().len(), 3);
+
+        // 带过滤条件的查询在MSG表上执行
+        let hits = handler.search("晚饭 is:sent", None, None, None, 10).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].message.create_time, 400);
+        assert_eq!(hits[0].highlights, vec![(0, 2)]);
+        let hits = handler.search("in:bob (取消 OR lunch)", None, None, None, 10).unwrap();
+        assert_eq!(hits.len(), 1);
+
+        assert!(handler.search("\"晚饭", None, None, None, 10).is_err());
+    }
+
+    #[test]
+    fn test_chatroom_sender() {
+        let temp_dir = TempDir::new().unwrap();
+        let shard = create_shard(temp_dir.path(), "MSG0.db", &[
+            ("123@chatroom", 100, "hello from bytes extra"),
+            ("123@chatroom", 200, "wxid_legacy:\nhello from prefix"),
+            ("alice", 300, "hi"),
+        ]);
+        let bytes_extra = crate::db::protobuf_parser::tests::build_bytes_extra(&[(1, "wxid_member")]);
+        Connection::open(&shard).unwrap().execute(
+            "UPDATE MSG SET BytesExtra = ? WHERE CreateTime = 100",
+            [bytes_extra],
+        ).unwrap();
+
+        let handler = MsgHandler::new(&shard).unwrap();
+        let messages = handler.get_msg_list(None, 0, 10, None, None).unwrap();
+        assert_eq!(messages[0].sender_wxid, "wxid_member");
+        assert_eq!(messages[1].sender_wxid, "wxid_legacy");
+        assert_eq!(messages[1].content, "hello from prefix");
+        assert_eq!(messages[2].sender_wxid, "alice");
+        assert_eq!(messages[2].display_wxid(), Some("alice"));
+    }
+
+    #[test]
+    fn test_search_filters() {
+        let temp_dir = TempDir::new().unwrap();
+        let shard = create_shard(temp_dir.path(), "MSG0.db", &[
+            ("123@chatroom", 100, "from a"),
+            ("123@chatroom", 200, "from ab"),
+            ("123@chatroom", 300, "@a hello"),
+            ("alice", 400, "no display content"),
+            ("123@chatroom", 500, ""),
+        ]);
+        let build = crate::db::protobuf_parser::tests::build_bytes_extra;
+        let conn = Connection::open(&shard).unwrap();
+        for (time, bytes_extra) in [
+            (100, build(&[(1, "wxid_a")])),
+            (200, build(&[(1, "wxid_ab")])),
+            // 发送者是wxid_b，msgsource中@了wxid_a，附件路径中也含有wxid_a
+            (300, build(&[
+                (1, "wxid_b"),
+                (3, "wxid_a\\FileStorage\\Image\\a.dat"),
+                (7, "<msgsource><atuserlist>wxid_a</atuserlist></msgsource>"),
+            ])),
+        ] {
+            conn.execute("UPDATE MSG SET BytesExtra = ? WHERE CreateTime = ?", rusqlite::params![bytes_extra, time]).unwrap();
+        }
+        conn.execute("UPDATE MSG SET DisplayContent = NULL WHERE CreateTime = 400", []).unwrap();
+        conn.execute("UPDATE MSG SET StrContent = NULL WHERE CreateTime = 500", []).unwrap();
+
+        let handler = MsgHandler::new(&shard).unwrap();
+        let hits = handler.search("from:wxid_a", None, None, None, 10).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].message.create_time, 100);
+
+        // DisplayContent为NULL的消息不会被排除词漏掉
+        let hits = handler.search("in:alice -missing", None, None, None, 10).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].message.create_time, 400);
+
+        // StrContent和BytesExtra为NULL的消息不会被取反的from:和has:漏掉
+        let hits = handler.search("in:123@chatroom -from:wxid_a -has:link", None, None, None, 10).unwrap();
+        let mut times: Vec<i64> = hits.iter().map(|h| h.message.create_time).collect();
+        times.sort();
+        assert_eq!(times, vec![200, 300, 500]);
+    }
+
+    #[test]
+    fn test_reply_linking() {
+        let temp_dir = TempDir::new().unwrap();
+        let shard0 = create_shard(temp_dir.path(), "MSG0.db", &[
+            ("alice", 100, "明天开会"),
+            ("bob", 150, "other chat"),
+        ]);
+        let shard1 = create_shard(temp_dir.path(), "MSG1.db", &[
+            ("alice", 200, ""),
+            ("alice", 300, ""),
+        ]);
+        let reply = |svrid: i64| format!(
+            "<msg><appmsg><title>收到</title><type>57</type><refermsg><type>1</type><svrid>{}</svrid>\
+             <fromusr>alice</fromusr><displayname>Alice</displayname><content>原文</content></refermsg></appmsg></msg>",
+            svrid
+        );
+        let conn = Connection::open(&shard1).unwrap();
+        for (time, svrid) in [(200, 100), (300, 150)] {
+            conn.execute(
+                "UPDATE MSG SET Type = 49, SubType = 57, StrContent = ? WHERE CreateTime = ?",
+                rusqlite::params![reply(svrid), time],
+            ).unwrap();
+        }
+
+        let handler = MsgHandler::with_shards(&[shard0, shard1]).unwrap();
+        let messages = handler.get_msg_list(Some("alice"), 0, 10, None, None).unwrap();
+        assert_eq!(messages[0].reply_to, None);
+        assert_eq!(messages[1].content, "收到");
+
+        // 被引用消息在另一个分片中，按MsgSvrID关联
+        let linked = messages[1].reply_to.as_ref().unwrap();
+        assert_eq!(linked.sender_wxid, "alice");
+        assert_eq!(linked.local_id, Some(1));
+        assert_eq!(linked.create_time, 100);
+        assert_eq!(linked.content, "明天开会");
+
+        // 其他会话中的同ID消息不关联，保留refermsg中的摘要
+        let unlinked = messages[2].reply_to.as_ref().unwrap();
+        assert_eq!(unlinked.msg_svr_id, 150);
+        assert_eq!(unlinked.local_id, None);
+        assert_eq!(unlinked.content, "原文");
+    }
+
+    #[test]
+    fn test_for_each_page() {
+        let temp_dir = TempDir::new().unwrap();
+        let shard0 = create_shard(temp_dir.path(), "MSG0.db", &[("alice", 100, "a"), ("alice", 300, "c")]);
+        let shard1 = create_shard(temp_dir.path(), "MSG1.db", &[("alice", 200, "b"), ("bob", 250, "x")]);
+        let handler = MsgHandler::with_shards(&[shard0, shard1]).unwrap();
+
+        let mut contents = Vec::new();
+        handler
+            .for_each_page(Some("alice"), None, None, |messages| {
+                contents.extend(messages.into_iter().map(|m| m.content));
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(contents, vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_system_events_limit_in_short_batch() {
+        let temp_dir = TempDir::new().unwrap();
+        let shard = create_shard(temp_dir.path(), "MSG0.db", &[
+            ("123@chatroom", 100, "以下为新消息"),
+            ("123@chatroom", 200, "\"张三\" 拍了拍 \"李四\""),
+            ("123@chatroom", 300, "\"李四\" 拍了拍 \"张三\""),
+            ("123@chatroom", 400, "\"张三\"邀请\"王五\"加入了群聊"),
+            ("123@chatroom", 500, "\"王五\" 拍了拍 \"张三\""),
+        ]);
+        let conn = Connection::open(&shard).unwrap();
+        conn.execute("UPDATE MSG SET Type = 10000", []).unwrap();
+        let handler = MsgHandler::new(&shard).unwrap();
+
+        // 第二批只有两行（不足一批），读到第一行就凑满，第二行仍需能通过游标取到
+        let first = handler.system_events("123@chatroom", &[], None, None, None, 3).unwrap();
+        let times: Vec<i64> = first.messages.iter().map(|m| m.create_time).collect();
+        assert_eq!(times, vec![200, 300, 400]);
+        let cursor = first.next_cursor.expect("events left in the short batch");
+        let rest = handler.system_events("123@chatroom", &[], Some(&cursor), None, None, 3).unwrap();
+        let times: Vec<i64> = rest.messages.iter().map(|m| m.create_time).collect();
+        assert_eq!(times, vec![500]);
+        assert!(rest.next_cursor.is_none());
+    }
+
+    #[test]
+    fn test_system_events() {
+        let temp_dir = TempDir::new().unwrap();
+        let shard = create_shard(temp_dir.path(), "MSG0.db", &[
+            ("123@chatroom", 100, "马上撤回"),
+            ("123@chatroom", 200, r#"<sysmsg type="revokemsg"><revokemsg><newmsgid>100</newmsgid><replacemsg><![CDATA["张三" 撤回了一条消息]]></replacemsg></revokemsg></sysmsg>"#),
+            ("123@chatroom", 300, "\"张三\" 拍了拍 \"李四\""),
+            ("123@chatroom", 400, "\"张三\"邀请\"王五\"加入了群聊"),
+            ("123@chatroom", 500, "以下为新消息"),
+        ]);
+        let conn = Connection::open(&shard).unwrap();
+        conn.execute("UPDATE MSG SET Type = 10002 WHERE CreateTime = 200", []).unwrap();
+        conn.execute("UPDATE MSG SET Type = 10000 WHERE CreateTime >= 300", []).unwrap();
+
+        let handler = MsgHandler::new(&shard).unwrap();
+        let page = handler.system_events("123@chatroom", &[], None, None, None, 100).unwrap();
+        let kinds: Vec<&str> = page.messages.iter().map(|m| m.event.as_ref().unwrap().kind()).collect();
+        assert_eq!(kinds, vec!["revoke", "pat", "join"]);
+        assert!(page.next_cursor.is_none());
+
+        // 按游标翻页，每页只读取所需的消息
+        let first = handler.system_events("123@chatroom", &[], None, None, None, 2).unwrap();
+        assert_eq!(first.messages.len(), 2);
+        let cursor = first.next_cursor.expect("more events after the first page");
+        let rest = handler.system_events("123@chatroom", &[], Some(&cursor), None, None, 2).unwrap();
+        let kinds: Vec<&str> = rest.messages.iter().map(|m| m.event.as_ref().unwrap().kind()).collect();
+        assert_eq!(kinds, vec!["join"]);
+        assert!(rest.next_cursor.is_none());
+
+        // 时间范围在SQL中筛选
+        let ranged = handler.system_events("123@chatroom", &[], None, Some(250), Some(350), 100).unwrap();
+        assert_eq!(ranged.messages.len(), 1);
+
+        // 撤回事件按newmsgid关联到被撤回的消息
+        let revokes = handler
+            .system_events("123@chatroom", &["revoke".to_string()], None, None, None, 100)
+            .unwrap()
+            .messages;
+        assert_eq!(revokes.len(), 1);
+        assert_eq!(revokes[0].content, "\"张三\" 撤回了一条消息");
+        match &revokes[0].event {
+            Some(SystemEvent::Revoke { operator, revoked_local_id, .. }) => {
+                assert_eq!(operator.name, "张三");
+                assert_eq!(*revoked_local_id, Some(1));
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+    }
 }
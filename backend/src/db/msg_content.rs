@@ -0,0 +1,1099 @@
+use crate::db::utils::get_message_type_name;
+use regex::Regex;
+use roxmltree::{Document, Node};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::LazyLock;
+
+/// 语音消息 `<voicemsg>`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct VoiceMsg {
+    /// 语音时长（秒）
+    pub voicelength: f64,
+    /// 语音文件大小（字节）
+    pub length: i64,
+    pub voice_format: i32,
+    /// 语音转文字结果
+    pub transtext: String,
+    pub from_username: String,
+}
+
+/// 图片消息 `<img>`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ImgMsg {
+    pub md5: String,
+    pub length: i64,
+    pub hd_length: i64,
+    pub cdn_thumb_url: String,
+    pub cdn_mid_img_url: String,
+    pub cdn_big_img_url: String,
+    pub thumb_width: i32,
+    pub thumb_height: i32,
+}
+
+/// 视频消息 `<videomsg>`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct VideoMsg {
+    /// 播放时长（秒）
+    pub play_length: i64,
+    pub length: i64,
+    pub md5: String,
+    pub cdn_video_url: String,
+    pub cdn_thumb_url: String,
+    pub thumb_width: i32,
+    pub thumb_height: i32,
+    pub from_username: String,
+}
+
+/// 动画表情 `<emoji>`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EmojiMsg {
+    pub md5: String,
+    pub len: i64,
+    pub cdnurl: String,
+    pub thumburl: String,
+    pub encrypturl: String,
+    pub externurl: String,
+    pub width: i32,
+    pub height: i32,
+    pub product_id: String,
+    pub desc: String,
+}
+
+/// 位置消息 `<location>`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LocationMsg {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub scale: i32,
+    pub label: String,
+    pub poiname: String,
+    pub poiid: String,
+}
+
+/// 小程序信息 `<weappinfo>`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct WeAppInfo {
+    pub username: String,
+    pub appid: String,
+    pub pagepath: String,
+    pub icon_url: String,
+}
+
+/// 视频号内容 `<finderFeed>`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FinderFeed {
+    pub object_id: String,
+    pub nickname: String,
+    pub desc: String,
+}
+
+/// 引用回复中被引用的消息 `<refermsg>`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ReferMsg {
+    /// 被引用消息的MsgSvrID
+    pub svrid: i64,
+    pub msg_type: i32,
+    /// 单聊为发送者wxid，群聊为群聊ID
+    pub from_usr: String,
+    /// 群聊中被引用消息的发送者wxid
+    pub chat_usr: String,
+    pub display_name: String,
+    pub content: String,
+    pub create_time: i64,
+}
+
+impl ReferMsg {
+    /// 被引用消息的发送者wxid
+    pub fn sender_wxid(&self) -> &str {
+        if !self.chat_usr.is_empty() {
+            &self.chat_usr
+        } else {
+            &self.from_usr
+        }
+    }
+
+    /// 被引用消息的文本摘要，非文本消息显示为`[类型]`或应用消息标题
+    pub fn summary(&self) -> String {
+        match self.msg_type {
+            1 => self.content.clone(),
+            49 => AppMsg::parse(&self.content)
+                .map(|app| app.title)
+                .filter(|title| !title.is_empty())
+                .unwrap_or_else(|| "[链接]".to_string()),
+            msg_type => format!("[{}]", get_message_type_name(msg_type, 0)),
+        }
+    }
+}
+
+/// 合并转发的聊天记录中的一条消息 `<dataitem>`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ForwardedItem {
+    /// 1文本 2图片 3语音 4视频 5链接 6位置 8文件 17聊天记录 19小程序
+    pub data_type: i32,
+    pub data_id: String,
+    pub source_name: String,
+    pub source_time: String,
+    pub source_head_url: String,
+    /// 原消息的发送时间（Unix时间戳，秒），旧版本没有该字段
+    pub create_time: i64,
+    pub desc: String,
+    pub title: String,
+    pub link: String,
+    pub file_ext: String,
+    pub file_size: i64,
+    pub md5: String,
+    pub cdn_data_url: String,
+    pub cdn_thumb_url: String,
+    /// 嵌套转发的聊天记录（17）
+    pub forwarded: Vec<ForwardedItem>,
+}
+
+impl ForwardedItem {
+    /// 转发消息的文本摘要
+    pub fn summary(&self) -> String {
+        let or_title = |fallback: &str| {
+            if self.title.is_empty() {
+                fallback.to_string()
+            } else {
+                format!("{} {}", fallback, self.title)
+            }
+        };
+        match self.data_type {
+            1 => self.desc.clone(),
+            2 => "[图片]".to_string(),
+            3 => "[语音]".to_string(),
+            4 => "[视频]".to_string(),
+            5 if !self.link.is_empty() => format!("{} {}", or_title("[链接]"), self.link),
+            5 => or_title("[链接]"),
+            6 => or_title("[位置]"),
+            8 => or_title("[文件]"),
+            17 => or_title("[聊天记录]"),
+            19 => or_title("[小程序]"),
+            _ if !self.desc.is_empty() => self.desc.clone(),
+            _ => self.title.clone(),
+        }
+    }
+}
+
+/// 转账状态，对应`<paysubtype>`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PayStatus {
+    /// 1：发起转账，等待收款
+    Sent,
+    /// 3：已收款
+    Received,
+    /// 4：已退还
+    Refunded,
+    #[default]
+    Unknown,
+}
+
+impl PayStatus {
+    pub fn from_subtype(pay_subtype: i32) -> Self {
+        match pay_subtype {
+            1 => Self::Sent,
+            3 => Self::Received,
+            4 => Self::Refunded,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// 转账/红包信息 `<wcpayinfo>`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct WcPayInfo {
+    pub pay_subtype: i32,
+    pub status: PayStatus,
+    /// 金额（元），红包消息中没有金额
+    pub amount: f64,
+    /// 原始金额文本，如`￥100.00`
+    pub fee_desc: String,
+    pub pay_memo: String,
+    /// 同一笔转账的发起、收款、退还消息共用同一个transferid
+    pub transfer_id: String,
+    pub transaction_id: String,
+    pub invalid_time: i64,
+    pub begin_transfer_time: i64,
+    pub payer_username: String,
+    pub receiver_username: String,
+    /// 红包祝福语
+    pub receiver_title: String,
+}
+
+impl WcPayInfo {
+    fn parse(node: Node) -> Self {
+        let pay_subtype = child_num(node, "paysubtype");
+        let fee_desc = child_text(node, "feedesc");
+        WcPayInfo {
+            pay_subtype,
+            status: PayStatus::from_subtype(pay_subtype),
+            amount: parse_amount(&fee_desc),
+            fee_desc,
+            pay_memo: child_text(node, "pay_memo"),
+            transfer_id: child_text(node, "transferid"),
+            // 微信XML中的拼写即为transcationid
+            transaction_id: child_text(node, "transcationid"),
+            invalid_time: child_num(node, "invalidtime"),
+            begin_transfer_time: child_num(node, "begintransfertime"),
+            payer_username: child_text(node, "payer_username"),
+            receiver_username: child_text(node, "receiver_username"),
+            receiver_title: child_text(node, "receivertitle"),
+        }
+    }
+}
+
+/// 解析`￥1,234.50`形式的金额
+fn parse_amount(fee_desc: &str) -> f64 {
+    fee_desc
+        .chars()
+        .filter(|c| c.is_ascii_digit() || *c == '.')
+        .collect::<String>()
+        .parse()
+        .unwrap_or_default()
+}
+
+/// 应用消息 `<appmsg>`，覆盖49类型的所有子类型
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AppMsg {
+    /// `<type>`，与消息的SubType一致
+    pub app_type: i32,
+    pub title: String,
+    pub des: String,
+    pub url: String,
+    pub thumb_url: String,
+    pub data_url: String,
+    pub app_id: String,
+    pub app_name: String,
+    pub source_username: String,
+    pub source_display_name: String,
+    /// 附件大小（字节）
+    pub file_size: i64,
+    pub file_ext: String,
+    pub attach_id: String,
+    pub cdn_attach_url: String,
+    pub md5: String,
+    pub weapp: Option<WeAppInfo>,
+    pub finder_feed: Option<FinderFeed>,
+    /// 引用回复（57）
+    pub refer: Option<ReferMsg>,
+    /// 合并转发的聊天记录（19）
+    pub forwarded: Vec<ForwardedItem>,
+    /// 转账（2000）和红包（2001）
+    pub pay: Option<WcPayInfo>,
+}
+
+/// 系统事件涉及的用户，XML中带wxid，纯文本中只有显示名
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EventUser {
+    pub wxid: String,
+    pub name: String,
+    /// 自己（文本中的“你”“我”）
+    pub is_self: bool,
+}
+
+impl EventUser {
+    fn me() -> Self {
+        Self {
+            is_self: true,
+            ..Default::default()
+        }
+    }
+
+    fn named(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn with_wxid(wxid: &str) -> Self {
+        Self {
+            wxid: wxid.to_string(),
+            ..Default::default()
+        }
+    }
+}
+
+/// 系统消息中的事件
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum SystemEvent {
+    /// 撤回消息
+    Revoke {
+        operator: EventUser,
+        /// 被撤回消息的MsgSvrID，只有10002消息中有
+        new_msg_id: i64,
+        /// 被撤回消息仍在库中时为其localId
+        revoked_local_id: Option<i64>,
+    },
+    /// 拍一拍
+    Pat { from: EventUser, target: EventUser },
+    /// 加入群聊，扫码加入时`inviter`为分享二维码的人
+    Join {
+        inviter: Option<EventUser>,
+        members: Vec<EventUser>,
+    },
+    /// 被移出或主动退出群聊，主动退出时`operator`为空
+    Leave {
+        operator: Option<EventUser>,
+        members: Vec<EventUser>,
+    },
+}
+
+impl SystemEvent {
+    /// 所有事件类型名
+    pub const KINDS: &'static [&'static str] = &["revoke", "pat", "join", "leave"];
+
+    /// 事件类型名，用于按类型筛选
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Revoke { .. } => "revoke",
+            Self::Pat { .. } => "pat",
+            Self::Join { .. } => "join",
+            Self::Leave { .. } => "leave",
+        }
+    }
+}
+
+/// 系统消息，`<sysmsg>`或纯文本
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SysMsg {
+    /// `<sysmsg type="...">`，纯文本时为空
+    pub sys_type: String,
+    /// 展示给用户的文本
+    pub text: String,
+    pub event: Option<SystemEvent>,
+}
+
+/// 解析后的消息内容，序列化后写入`Message.extra`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum MessageContent {
+    Voice(VoiceMsg),
+    Image(ImgMsg),
+    Video(VideoMsg),
+    Emoji(EmojiMsg),
+    Location(LocationMsg),
+    App(Box<AppMsg>),
+    System(SysMsg),
+}
+
+impl MessageContent {
+    /// 按消息类型解析XML内容
+    pub fn parse(msg_type: i32, xml: &str) -> Option<Self> {
+        match msg_type {
+            3 => ImgMsg::parse(xml).map(Self::Image),
+            34 => VoiceMsg::parse(xml).map(Self::Voice),
+            43 | 62 => VideoMsg::parse(xml).map(Self::Video),
+            47 => EmojiMsg::parse(xml).map(Self::Emoji),
+            48 => LocationMsg::parse(xml).map(Self::Location),
+            49 => AppMsg::parse(xml).map(|app| Self::App(Box::new(app))),
+            10000 | 10002 => Some(Self::System(SysMsg::parse(xml))),
+            _ => None,
+        }
+    }
+
+    /// 转换为`Message.extra`
+    pub fn to_extra(&self) -> serde_json::Value {
+        serde_json::to_value(self).unwrap_or_else(|_| serde_json::json!({}))
+    }
+}
+
+impl VoiceMsg {
+    pub fn parse(xml: &str) -> Option<Self> {
+        with_element(xml, "voicemsg", |node| {
+            let doc_root = root_of(node);
+            let transtext = doc_root
+                .descendants()
+                .find_map(|n| n.attribute("transtext").map(str::to_string))
+                .or_else(|| find_descendant(doc_root, "transtext").map(text_of))
+                .unwrap_or_default();
+            VoiceMsg {
+                voicelength: attr_num::<f64>(node, "voicelength") / 1000.0,
+                length: attr_num(node, "length"),
+                voice_format: attr_num(node, "voiceformat"),
+                transtext,
+                from_username: attr(node, "fromusername"),
+            }
+        })
+    }
+}
+
+impl ImgMsg {
+    pub fn parse(xml: &str) -> Option<Self> {
+        with_element(xml, "img", |node| ImgMsg {
+            md5: attr(node, "md5"),
+            length: attr_num(node, "length"),
+            hd_length: attr_num(node, "hdlength"),
+            cdn_thumb_url: attr(node, "cdnthumburl"),
+            cdn_mid_img_url: attr(node, "cdnmidimgurl"),
+            cdn_big_img_url: attr(node, "cdnbigimgurl"),
+            thumb_width: attr_num(node, "cdnthumbwidth"),
+            thumb_height: attr_num(node, "cdnthumbheight"),
+        })
+    }
+}
+
+impl VideoMsg {
+    pub fn parse(xml: &str) -> Option<Self> {
+        with_element(xml, "videomsg", |node| VideoMsg {
+            play_length: attr_num(node, "playlength"),
+            length: attr_num(node, "length"),
+            md5: attr(node, "md5"),
+            cdn_video_url: attr(node, "cdnvideourl"),
+            cdn_thumb_url: attr(node, "cdnthumburl"),
+            thumb_width: attr_num(node, "cdnthumbwidth"),
+            thumb_height: attr_num(node, "cdnthumbheight"),
+            from_username: attr(node, "fromusername"),
+        })
+    }
+}
+
+impl EmojiMsg {
+    pub fn parse(xml: &str) -> Option<Self> {
+        with_element(xml, "emoji", |node| EmojiMsg {
+            md5: attr(node, "md5"),
+            len: attr_num(node, "len"),
+            cdnurl: attr(node, "cdnurl"),
+            thumburl: attr(node, "thumburl"),
+            encrypturl: attr(node, "encrypturl"),
+            externurl: attr(node, "externurl"),
+            width: attr_num(node, "width"),
+            height: attr_num(node, "height"),
+            product_id: attr(node, "productid"),
+            desc: attr(node, "desc"),
+        })
+    }
+}
+
+impl LocationMsg {
+    pub fn parse(xml: &str) -> Option<Self> {
+        with_element(xml, "location", |node| LocationMsg {
+            latitude: attr_num(node, "x"),
+            longitude: attr_num(node, "y"),
+            scale: attr_num(node, "scale"),
+            label: attr(node, "label"),
+            poiname: attr(node, "poiname"),
+            poiid: attr(node, "poiid"),
+        })
+    }
+}
+
+impl AppMsg {
+    pub fn parse(xml: &str) -> Option<Self> {
+        with_element(xml, "appmsg", |node| {
+            let attach = find_child(node, "appattach");
+            let app_info = node.parent().and_then(|p| find_child(p, "appinfo"));
+            let weapp = find_child(node, "weappinfo").map(|w| WeAppInfo {
+                username: child_text(w, "username"),
+                appid: child_text(w, "appid"),
+                pagepath: child_text(w, "pagepath"),
+                icon_url: child_text(w, "weappiconurl"),
+            });
+            let finder_feed = find_child(node, "finderFeed").map(|f| FinderFeed {
+                object_id: child_text(f, "objectId"),
+                nickname: child_text(f, "nickname"),
+                desc: child_text(f, "desc"),
+            });
+            let refer = find_child(node, "refermsg").map(|r| ReferMsg {
+                svrid: child_num(r, "svrid"),
+                msg_type: child_num(r, "type"),
+                from_usr: child_text(r, "fromusr"),
+                chat_usr: child_text(r, "chatusr"),
+                display_name: child_text(r, "displayname"),
+                content: child_text(r, "content"),
+                create_time: child_num(r, "createtime"),
+            });
+
+            AppMsg {
+                app_type: child_num(node, "type"),
+                title: child_text(node, "title"),
+                des: child_text(node, "des"),
+                url: child_text(node, "url"),
+                thumb_url: child_text(node, "thumburl"),
+                data_url: child_text(node, "dataurl"),
+                app_id: attr(node, "appid"),
+                app_name: app_info.map(|a| child_text(a, "appname")).unwrap_or_default(),
+                source_username: child_text(node, "sourceusername"),
+                source_display_name: child_text(node, "sourcedisplayname"),
+                file_size: attach.map(|a| child_num(a, "totallen")).unwrap_or_default(),
+                file_ext: attach.map(|a| child_text(a, "fileext")).unwrap_or_default(),
+                attach_id: attach.map(|a| child_text(a, "attachid")).unwrap_or_default(),
+                cdn_attach_url: attach
+                    .map(|a| child_text(a, "cdnattachurl"))
+                    .unwrap_or_default(),
+                md5: child_text(node, "md5"),
+                weapp,
+                finder_feed,
+                refer,
+                forwarded: find_child(node, "recorditem")
+                    .map(|r| parse_record_item(r, 0))
+                    .unwrap_or_default(),
+                pay: find_child(node, "wcpayinfo").map(WcPayInfo::parse),
+            }
+        })
+    }
+}
+
+impl SysMsg {
+    /// 解析系统消息，非XML内容按纯文本处理
+    pub fn parse(content: &str) -> Self {
+        let parsed = with_element(content, "sysmsg", |node| {
+            let sys_type = attr(node, "type");
+            match sys_type.as_str() {
+                "revokemsg" => Self::parse_revoke(node, sys_type),
+                "pat" => Self::parse_pat(node, sys_type),
+                "sysmsgtemplate" => Self::parse_template(node, sys_type),
+                _ => {
+                    let text = ["replacemsg", "template", "content"]
+                        .iter()
+                        .find_map(|tag| find_descendant(node, tag).map(text_of))
+                        .unwrap_or_default();
+                    let event = classify_event(&text, &split_names);
+                    SysMsg { sys_type, text, event }
+                }
+            }
+        });
+        parsed.unwrap_or_else(|| {
+            let text = content.trim().to_string();
+            let event = classify_event(&text, &split_names);
+            SysMsg {
+                sys_type: String::new(),
+                text,
+                event,
+            }
+        })
+    }
+
+    /// `<revokemsg>`：`newmsgid`为被撤回消息的MsgSvrID
+    fn parse_revoke(node: Node, sys_type: String) -> Self {
+        let revoke = find_descendant(node, "revokemsg");
+        let text = revoke.map(|r| child_text(r, "replacemsg")).unwrap_or_default();
+        let operator = match classify_event(&text, &split_names) {
+            Some(SystemEvent::Revoke { operator, .. }) => operator,
+            _ => EventUser::default(),
+        };
+        let event = SystemEvent::Revoke {
+            operator,
+            new_msg_id: revoke.map(|r| child_num(r, "newmsgid")).unwrap_or_default(),
+            revoked_local_id: None,
+        };
+        SysMsg {
+            sys_type,
+            text,
+            event: Some(event),
+        }
+    }
+
+    /// `<pat>`：模板中的`${wxid}`替换为wxid
+    fn parse_pat(node: Node, sys_type: String) -> Self {
+        let pat = find_descendant(node, "pat");
+        let field = |tag: &str| pat.map(|p| child_text(p, tag)).unwrap_or_default();
+        let text = PAT_PLACEHOLDER.replace_all(&field("template"), "$1").into_owned();
+        let event = SystemEvent::Pat {
+            from: EventUser::with_wxid(&field("fromusername")),
+            target: EventUser::with_wxid(&field("pattedusername")),
+        };
+        SysMsg {
+            sys_type,
+            text,
+            event: Some(event),
+        }
+    }
+
+    /// `<sysmsgtemplate>`：模板中的`$name$`由`<link_list>`中同名链接的成员替换
+    fn parse_template(node: Node, sys_type: String) -> Self {
+        let Some(content) = find_descendant(node, "content_template") else {
+            return SysMsg {
+                sys_type,
+                ..Default::default()
+            };
+        };
+        let template = child_text(content, "template");
+
+        let mut links: HashMap<String, (Vec<EventUser>, String)> = HashMap::new();
+        if let Some(link_list) = find_child(content, "link_list") {
+            for link in link_list.children().filter(|n| n.has_tag_name("link")) {
+                let members = link
+                    .descendants()
+                    .filter(|n| n.has_tag_name("member"))
+                    .map(|m| EventUser {
+                        wxid: child_text(m, "username"),
+                        name: child_text(m, "nickname"),
+                        is_self: false,
+                    })
+                    .collect();
+                let separator = find_child(link, "separator")
+                    .map(text_of)
+                    .filter(|s| !s.is_empty())
+                    .unwrap_or_else(|| "、".to_string());
+                links.insert(attr(link, "name"), (members, separator));
+            }
+        }
+
+        let text = TEMPLATE_PLACEHOLDER
+            .replace_all(&template, |caps: &regex::Captures| match links.get(&caps[1]) {
+                Some((members, separator)) => members
+                    .iter()
+                    .map(|m| m.name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(separator),
+                None => caps[0].to_string(),
+            })
+            .into_owned();
+        let resolve = |value: &str| {
+            TEMPLATE_PLACEHOLDER
+                .captures(value)
+                .and_then(|caps| links.get(&caps[1]))
+                .map(|(members, _)| members.clone())
+                .unwrap_or_else(|| split_names(value))
+        };
+        let event = classify_event(&template, &resolve);
+        SysMsg { sys_type, text, event }
+    }
+}
+
+/// 文本中的用户：带引号的名称，或表示自己的“你”“我”
+const PERSON: &str = r#"(?:["“](.+?)["”]|你|我)"#;
+
+static REVOKE_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(&format!(r"^{}\s*撤回了一条消息", PERSON)).unwrap());
+static PAT_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(&format!(r#"^{}\s*拍了拍\s*(?:["“](.+?)["”]|(你|我|自己))"#, PERSON)).unwrap()
+});
+static INVITE_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(&format!(r"^{}\s*邀请\s*{}\s*加入了?群聊", PERSON, PERSON)).unwrap()
+});
+static QRCODE_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(&format!(r"^{}\s*通过扫描\s*{}\s*分享的二维码加入群聊", PERSON, PERSON)).unwrap()
+});
+static KICK_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(&format!(r"^{}\s*将\s*{}\s*移出了群聊", PERSON, PERSON)).unwrap()
+});
+static QUIT_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(&format!(r"^{}\s*已?退出了群聊", PERSON)).unwrap());
+static PAT_PLACEHOLDER: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\$\{([^}]*)\}").unwrap());
+static TEMPLATE_PLACEHOLDER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\$([A-Za-z_]+)\$").unwrap());
+
+/// 纯文本中以“、”分隔的多个名称
+fn split_names(value: &str) -> Vec<EventUser> {
+    value.split('、').map(EventUser::named).collect()
+}
+
+/// 从系统提示文本识别事件，`resolve`把引号中的内容解析为用户
+fn classify_event(text: &str, resolve: &dyn Fn(&str) -> Vec<EventUser>) -> Option<SystemEvent> {
+    let users = |m: Option<regex::Match>| match m {
+        Some(m) => resolve(m.as_str()),
+        None => vec![EventUser::me()],
+    };
+    let user = |m: Option<regex::Match>| users(m).into_iter().next().unwrap_or_default();
+
+    if let Some(caps) = REVOKE_RE.captures(text) {
+        return Some(SystemEvent::Revoke {
+            operator: user(caps.get(1)),
+            new_msg_id: 0,
+            revoked_local_id: None,
+        });
+    }
+    if let Some(caps) = PAT_RE.captures(text) {
+        let from = user(caps.get(1));
+        let target = match caps.get(3).map(|m| m.as_str()) {
+            Some("自己") => from.clone(),
+            _ => user(caps.get(2)),
+        };
+        return Some(SystemEvent::Pat { from, target });
+    }
+    if let Some(caps) = INVITE_RE.captures(text) {
+        return Some(SystemEvent::Join {
+            inviter: Some(user(caps.get(1))),
+            members: users(caps.get(2)),
+        });
+    }
+    if let Some(caps) = QRCODE_RE.captures(text) {
+        return Some(SystemEvent::Join {
+            inviter: Some(user(caps.get(2))),
+            members: users(caps.get(1)),
+        });
+    }
+    if let Some(caps) = KICK_RE.captures(text) {
+        return Some(SystemEvent::Leave {
+            operator: Some(user(caps.get(1))),
+            members: users(caps.get(2)),
+        });
+    }
+    if let Some(caps) = QUIT_RE.captures(text) {
+        return Some(SystemEvent::Leave {
+            operator: None,
+            members: users(caps.get(1)),
+        });
+    }
+    None
+}
+
+/// 聊天记录嵌套的最大层数
+const MAX_RECORD_DEPTH: usize = 8;
+
+/// 解析`<recorditem>`/`<recordxml>`，其中的`<recordinfo>`可能是子元素，也可能是转义或CDATA中的XML文本
+fn parse_record_item(node: Node, depth: usize) -> Vec<ForwardedItem> {
+    if depth >= MAX_RECORD_DEPTH {
+        return Vec::new();
+    }
+    if let Some(info) = find_child(node, "recordinfo") {
+        return parse_record_info(info, depth);
+    }
+    let text = text_of(node);
+    with_element(&text, "recordinfo", |info| parse_record_info(info, depth)).unwrap_or_default()
+}
+
+fn parse_record_info(info: Node, depth: usize) -> Vec<ForwardedItem> {
+    let Some(datalist) = find_child(info, "datalist") else {
+        return Vec::new();
+    };
+    datalist
+        .children()
+        .filter(|n| n.is_element() && n.has_tag_name("dataitem"))
+        .map(|item| ForwardedItem {
+            data_type: attr_num(item, "datatype"),
+            data_id: attr(item, "dataid"),
+            source_name: child_text(item, "sourcename"),
+            source_time: child_text(item, "sourcetime"),
+            source_head_url: child_text(item, "sourceheadurl"),
+            create_time: child_num(item, "srcMsgCreateTime"),
+            desc: child_text(item, "datadesc"),
+            title: child_text(item, "datatitle"),
+            link: child_text(item, "link"),
+            file_ext: child_text(item, "datafmt"),
+            file_size: child_num(item, "datasize"),
+            md5: child_text(item, "fullmd5"),
+            cdn_data_url: child_text(item, "cdndataurl"),
+            cdn_thumb_url: child_text(item, "cdnthumburl"),
+            forwarded: find_child(item, "recordxml")
+                .map(|r| parse_record_item(r, depth + 1))
+                .unwrap_or_default(),
+        })
+        .collect()
+}
+
+/// 去掉群聊发送者前缀、XML声明前的杂质以及结尾的`\0`
+fn sanitize(xml: &str) -> Option<&str> {
+    let start = xml.find('<')?;
+    Some(xml[start..].trim_end_matches(['\0', ' ', '\r', '\n', '\t']))
+}
+
+/// 解析XML并在第一个名为`tag`的元素上执行`f`
+fn with_element<T>(xml: &str, tag: &str, f: impl FnOnce(Node) -> T) -> Option<T> {
+    let doc = Document::parse(sanitize(xml)?).ok()?;
+    let node = find_descendant(doc.root(), tag)?;
+    Some(f(node))
+}
+
+fn root_of<'a, 'input>(node: Node<'a, 'input>) -> Node<'a, 'input> {
+    node.document().root()
+}
+
+fn find_descendant<'a, 'input>(node: Node<'a, 'input>, tag: &str) -> Option<Node<'a, 'input>> {
+    node.descendants().find(|n| n.is_element() && n.has_tag_name(tag))
+}
+
+/// 只查找直接子元素，避免误取嵌套结构（如引用消息）中的同名标签
+fn find_child<'a, 'input>(node: Node<'a, 'input>, tag: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|n| n.is_element() && n.has_tag_name(tag))
+}
+
+/// 元素的文本内容（包括CDATA）
+fn text_of(node: Node) -> String {
+    node.descendants()
+        .filter(|n| n.is_text())
+        .filter_map(|n| n.text())
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+fn child_text(node: Node, tag: &str) -> String {
+    find_child(node, tag).map(text_of).unwrap_or_default()
+}
+
+fn child_num<T: FromStr + Default>(node: Node, tag: &str) -> T {
+    child_text(node, tag).parse().unwrap_or_default()
+}
+
+fn attr(node: Node, name: &str) -> String {
+    node.attribute(name).unwrap_or_default().to_string()
+}
+
+fn attr_num<T: FromStr + Default>(node: Node, name: &str) -> T {
+    node.attribute(name)
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_media_messages() {
+        let voice = r#"<msg><voicemsg endflag="1" voiceformat="4" voicelength="2610" length="4224" fromusername="wxid_a" /><voicetrans transtext="你好" /></msg>"#;
+        let voice = VoiceMsg::parse(voice).unwrap();
+        assert_eq!(voice.voicelength, 2.61);
+        assert_eq!(voice.length, 4224);
+        assert_eq!(voice.transtext, "你好");
+
+        let img = "wxid_a:\n<?xml version=\"1.0\"?>\n<msg><img md5=\"abc\" length=\"1024\" cdnthumbwidth=\"120\" cdnthumbheight=\"90\" /></msg>\0";
+        let img = ImgMsg::parse(img).unwrap();
+        assert_eq!(img.md5, "abc");
+        assert_eq!(img.length, 1024);
+        assert_eq!((img.thumb_width, img.thumb_height), (120, 90));
+
+        let video = r#"<msg><videomsg playlength="15" length="2048" md5="v" /></msg>"#;
+        assert_eq!(VideoMsg::parse(video).unwrap().play_length, 15);
+
+        let emoji = r#"<msg><emoji md5="e" cdnurl="http://emoji/a?x=1&amp;y=2" width="240" height="240" /></msg>"#;
+        let emoji = EmojiMsg::parse(emoji).unwrap();
+        assert_eq!(emoji.cdnurl, "http://emoji/a?x=1&y=2");
+        assert_eq!(emoji.width, 240);
+
+        let location = r#"<msg><location x="39.9" y="116.4" scale="16" label="北京市" poiname="天安门" /></msg>"#;
+        let location = LocationMsg::parse(location).unwrap();
+        assert_eq!((location.latitude, location.longitude), (39.9, 116.4));
+        assert_eq!(location.poiname, "天安门");
+
+        assert_eq!(VoiceMsg::parse("not xml"), None);
+        assert_eq!(ImgMsg::parse("<msg><broken></msg>"), None);
+    }
+
+    #[test]
+    fn test_parse_app_message() {
+        let file = r#"<?xml version="1.0"?>
+<msg>
+    <appmsg appid="" sdkver="0">
+        <title><![CDATA[报告.pdf]]></title>
+        <des></des>
+        <type>6</type>
+        <appattach>
+            <totallen>20480</totallen>
+            <attachid>@cdn_1</attachid>
+            <fileext><![CDATA[pdf]]></fileext>
+        </appattach>
+        <md5>f00</md5>
+    </appmsg>
+    <appinfo><version>1</version><appname>文件传输</appname></appinfo>
+</msg>"#;
+        let app = AppMsg::parse(file).unwrap();
+        assert_eq!(app.app_type, 6);
+        assert_eq!(app.title, "报告.pdf");
+        assert_eq!(app.file_size, 20480);
+        assert_eq!(app.file_ext, "pdf");
+        assert_eq!(app.app_name, "文件传输");
+
+        let weapp = r#"<msg><appmsg><title>小程序</title><type>33</type><url>https://mp</url>
+            <weappinfo><username><![CDATA[gh_1@app]]></username><appid>wx1</appid><pagepath>pages/index</pagepath></weappinfo>
+            <refermsg><title>不应该取到</title></refermsg></appmsg></msg>"#;
+        let app = AppMsg::parse(weapp).unwrap();
+        assert_eq!(app.title, "小程序");
+        assert_eq!(app.url, "https://mp");
+        let info = app.weapp.unwrap();
+        assert_eq!(info.username, "gh_1@app");
+        assert_eq!(info.pagepath, "pages/index");
+
+        let extra = MessageContent::parse(49, file).unwrap().to_extra();
+        assert_eq!(extra["kind"], "app");
+        assert_eq!(extra["file_size"], 20480);
+        assert_eq!(extra["title"], "报告.pdf");
+    }
+
+    #[test]
+    fn test_parse_refer_message() {
+        let reply = r#"<msg><appmsg appid="" sdkver="0"><title>收到</title><type>57</type>
+            <refermsg>
+                <type>1</type>
+                <svrid>7000000000000000001</svrid>
+                <fromusr>123@chatroom</fromusr>
+                <chatusr>wxid_a</chatusr>
+                <displayname>张三</displayname>
+                <content>明天开会</content>
+                <createtime>1700000000</createtime>
+            </refermsg></appmsg></msg>"#;
+        let app = AppMsg::parse(reply).unwrap();
+        assert_eq!(app.title, "收到");
+        let refer = app.refer.unwrap();
+        assert_eq!(refer.svrid, 7000000000000000001);
+        assert_eq!(refer.sender_wxid(), "wxid_a");
+        assert_eq!(refer.summary(), "明天开会");
+
+        let quoted_link = ReferMsg {
+            msg_type: 49,
+            content: "<msg><appmsg><title>一篇文章</title><type>5</type></appmsg></msg>".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(quoted_link.summary(), "一篇文章");
+        let quoted_image = ReferMsg { msg_type: 3, ..Default::default() };
+        assert_eq!(quoted_image.summary(), "[图片]");
+    }
+
+    #[test]
+    fn test_parse_forwarded_record() {
+        let record = r#"<msg><appmsg><title>群聊的聊天记录</title><type>19</type>
+            <recorditem><![CDATA[<recordinfo><title>群聊的聊天记录</title><datalist count="3">
+                <dataitem datatype="1" dataid="a1"><datadesc>第一条</datadesc><sourcename>张三</sourcename><sourcetime>2023-01-01 12:00</sourcetime><srcMsgCreateTime>1672545600</srcMsgCreateTime></dataitem>
+                <dataitem datatype="8" dataid="a2"><datatitle>方案.docx</datatitle><datafmt>docx</datafmt><datasize>4096</datasize><fullmd5>m</fullmd5><sourcename>李四</sourcename></dataitem>
+                <dataitem datatype="17" dataid="a3"><datatitle>聊天记录</datatitle><sourcename>王五</sourcename>
+                    <recordxml><recordinfo><datalist count="1">
+                        <dataitem datatype="2" dataid="b1"><cdndataurl>http://cdn/img</cdndataurl><sourcename>赵六</sourcename></dataitem>
+                    </datalist></recordinfo></recordxml>
+                </dataitem>
+            </datalist></recordinfo>]]></recorditem></appmsg></msg>"#;
+        let app = AppMsg::parse(record).unwrap();
+        assert_eq!(app.app_type, 19);
+        assert_eq!(app.forwarded.len(), 3);
+
+        let text = &app.forwarded[0];
+        assert_eq!(text.source_name, "张三");
+        assert_eq!(text.create_time, 1672545600);
+        assert_eq!(text.summary(), "第一条");
+
+        let file = &app.forwarded[1];
+        assert_eq!((file.file_ext.as_str(), file.file_size), ("docx", 4096));
+        assert_eq!(file.summary(), "[文件] 方案.docx");
+
+        let nested = &app.forwarded[2].forwarded;
+        assert_eq!(nested.len(), 1);
+        assert_eq!(nested[0].data_type, 2);
+        assert_eq!(nested[0].cdn_data_url, "http://cdn/img");
+
+        let extra = MessageContent::parse(49, record).unwrap().to_extra();
+        assert_eq!(extra["forwarded"][2]["forwarded"][0]["source_name"], "赵六");
+    }
+
+    #[test]
+    fn test_parse_pay_info() {
+        let transfer = r#"<msg><appmsg><title>微信转账</title><type>2000</type>
+            <wcpayinfo>
+                <paysubtype>3</paysubtype>
+                <feedesc><![CDATA[￥1,234.50]]></feedesc>
+                <transcationid>t1</transcationid>
+                <transferid>1000050001</transferid>
+                <invalidtime>1700086400</invalidtime>
+                <pay_memo><![CDATA[房租]]></pay_memo>
+                <payer_username>wxid_a</payer_username>
+                <receiver_username>wxid_b</receiver_username>
+            </wcpayinfo></appmsg></msg>"#;
+        let pay = AppMsg::parse(transfer).unwrap().pay.unwrap();
+        assert_eq!(pay.status, PayStatus::Received);
+        assert_eq!(pay.amount, 1234.5);
+        assert_eq!(pay.pay_memo, "房租");
+        assert_eq!(pay.transfer_id, "1000050001");
+        assert_eq!(pay.transaction_id, "t1");
+        assert_eq!(pay.invalid_time, 1700086400);
+
+        let red_packet = r#"<msg><appmsg><title>恭喜发财</title><type>2001</type>
+            <wcpayinfo><receivertitle>恭喜发财，大吉大利</receivertitle></wcpayinfo></appmsg></msg>"#;
+        let pay = AppMsg::parse(red_packet).unwrap().pay.unwrap();
+        assert_eq!(pay.status, PayStatus::Unknown);
+        assert_eq!(pay.amount, 0.0);
+        assert_eq!(pay.receiver_title, "恭喜发财，大吉大利");
+    }
+
+    #[test]
+    fn test_parse_sys_message() {
+        let revoke = r#"<sysmsg type="revokemsg"><revokemsg><session>123@chatroom</session><msgid>1</msgid><newmsgid>99</newmsgid><replacemsg><![CDATA["张三" 撤回了一条消息]]></replacemsg></revokemsg></sysmsg>"#;
+        let sys = SysMsg::parse(revoke);
+        assert_eq!(sys.sys_type, "revokemsg");
+        assert_eq!(sys.text, "\"张三\" 撤回了一条消息");
+
+        let plain = SysMsg::parse("你已添加了张三，现在可以开始聊天了。");
+        assert_eq!(plain.sys_type, "");
+        assert_eq!(plain.text, "你已添加了张三，现在可以开始聊天了。");
+
+        let extra = MessageContent::parse(10000, "以上是打招呼的内容").unwrap().to_extra();
+        assert_eq!(extra["kind"], "system");
+        assert_eq!(extra["text"], "以上是打招呼的内容");
+        assert_eq!(extra["event"], serde_json::Value::Null);
+    }
+
+    #[test]
+    fn test_parse_system_events() {
+        let revoke = r#"<sysmsg type="revokemsg"><revokemsg><newmsgid>99</newmsgid><replacemsg><![CDATA["张三" 撤回了一条消息]]></replacemsg></revokemsg></sysmsg>"#;
+        assert_eq!(
+            SysMsg::parse(revoke).event,
+            Some(SystemEvent::Revoke {
+                operator: EventUser::named("张三"),
+                new_msg_id: 99,
+                revoked_local_id: None,
+            })
+        );
+        match SysMsg::parse("你撤回了一条消息").event {
+            Some(SystemEvent::Revoke { operator, new_msg_id, .. }) => {
+                assert!(operator.is_self);
+                assert_eq!(new_msg_id, 0);
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+
+        let pat = r#"<sysmsg type="pat"><pat><fromusername>wxid_a</fromusername><chatusername>1@chatroom</chatusername><pattedusername>wxid_b</pattedusername><template><![CDATA["${wxid_a}" 拍了拍 "${wxid_b}"]]></template></pat></sysmsg>"#;
+        let pat = SysMsg::parse(pat);
+        assert_eq!(pat.text, "\"wxid_a\" 拍了拍 \"wxid_b\"");
+        assert_eq!(
+            pat.event,
+            Some(SystemEvent::Pat {
+                from: EventUser::with_wxid("wxid_a"),
+                target: EventUser::with_wxid("wxid_b"),
+            })
+        );
+        match SysMsg::parse("\"张三\" 拍了拍自己").event {
+            Some(SystemEvent::Pat { from, target }) => {
+                assert_eq!(from.name, "张三");
+                assert_eq!(target, from);
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+
+        assert_eq!(
+            SysMsg::parse("\"张三\"邀请\"李四、王五\"加入了群聊").event,
+            Some(SystemEvent::Join {
+                inviter: Some(EventUser::named("张三")),
+                members: vec![EventUser::named("李四"), EventUser::named("王五")],
+            })
+        );
+        assert_eq!(
+            SysMsg::parse("\"李四\"通过扫描\"张三\"分享的二维码加入群聊").event,
+            Some(SystemEvent::Join {
+                inviter: Some(EventUser::named("张三")),
+                members: vec![EventUser::named("李四")],
+            })
+        );
+        assert_eq!(
+            SysMsg::parse("你将\"李四\"移出了群聊").event,
+            Some(SystemEvent::Leave {
+                operator: Some(EventUser::me()),
+                members: vec![EventUser::named("李四")],
+            })
+        );
+
+        let template = r#"<sysmsg type="sysmsgtemplate"><sysmsgtemplate><content_template type="tmpl_type_profile">
+            <plain><![CDATA[]]></plain>
+            <template><![CDATA["$username$"邀请"$names$"加入了群聊]]></template>
+            <link_list>
+                <link name="username" type="link_profile"><memberlist><member><username><![CDATA[wxid_a]]></username><nickname><![CDATA[张三]]></nickname></member></memberlist></link>
+                <link name="names" type="link_profile"><memberlist>
+                    <member><username><![CDATA[wxid_b]]></username><nickname><![CDATA[李四]]></nickname></member>
+                    <member><username><![CDATA[wxid_c]]></username><nickname><![CDATA[王五]]></nickname></member>
+                </memberlist><separator><![CDATA[、]]></separator></link>
+            </link_list></content_template></sysmsgtemplate></sysmsg>"#;
+        let sys = SysMsg::parse(template);
+        assert_eq!(sys.text, "\"张三\"邀请\"李四、王五\"加入了群聊");
+        match sys.event {
+            Some(SystemEvent::Join { inviter, members }) => {
+                assert_eq!(inviter.unwrap().wxid, "wxid_a");
+                let wxids: Vec<_> = members.iter().map(|m| m.wxid.as_str()).collect();
+                assert_eq!(wxids, vec!["wxid_b", "wxid_c"]);
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,497 @@
+use crate::db::dbbase::DatabaseBase;
+use crate::db::msg_list::MsgList;
+use crate::db::search_query::{QueryNode, SearchQuery, Term};
+use crate::db::utils::Message;
+use crate::utils::{AppError, Result};
+use anyhow::Context;
+use rusqlite::Connection;
+use std::path::Path;
+
+/// 索引结构版本，结构变化时重建索引
+const SCHEMA_VERSION: i64 = 2;
+/// 增量建立索引时每批读取的消息数
+const INDEX_BATCH: i64 = 2000;
+/// 摘要中第一个命中词前保留的字符数
+const SNIPPET_BEFORE: usize = 16;
+/// 摘要的最大字符数（不含省略号）
+const SNIPPET_LENGTH: usize = 80;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS msg_shard (
+        id INTEGER PRIMARY KEY,
+        path TEXT NOT NULL UNIQUE,
+        last_local_id INTEGER NOT NULL DEFAULT 0
+    );
+    CREATE TABLE IF NOT EXISTS msg_doc (
+        id INTEGER PRIMARY KEY,
+        shard_id INTEGER NOT NULL,
+        local_id INTEGER NOT NULL,
+        create_time INTEGER NOT NULL,
+        talker TEXT NOT NULL
+    );
+    CREATE UNIQUE INDEX IF NOT EXISTS idx_msg_doc_shard ON msg_doc(shard_id, local_id);
+    CREATE INDEX IF NOT EXISTS idx_msg_doc_talker ON msg_doc(talker, create_time);
+    CREATE VIRTUAL TABLE IF NOT EXISTS msg_fts USING fts5(
+        body,
+        tokenize = 'unicode61 remove_diacritics 2'
+    );";
+
+fn db_error(e: rusqlite::Error) -> AppError {
+    AppError::Database(e.to_string())
+}
+
+/// 中日韩文字之间没有空格，unicode61会把整句当作一个词
+fn is_cjk(c: char) -> bool {
+    matches!(c as u32,
+        0x3040..=0x30FF         // 平假名、片假名
+        | 0x3400..=0x4DBF       // CJK扩展A
+        | 0x4E00..=0x9FFF       // CJK统一汉字
+        | 0xAC00..=0xD7AF       // 韩文音节
+        | 0xF900..=0xFAFF       // CJK兼容汉字
+        | 0x20000..=0x2FA1F     // CJK扩展B及以后
+    )
+}
+
+/// 将文本转换为索引形式：中日韩文字逐字切分，查询时以短语匹配连续的字
+/// 其余文字交给unicode61按空格和标点分词
+pub fn segment(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() * 2);
+    for c in text.chars() {
+        if is_cjk(c) {
+            out.push(' ');
+            out.push(c);
+            out.push(' ');
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// 解析后的全文检索查询
+#[derive(Debug, Clone, PartialEq)]
+pub struct FtsQuery {
+    /// FTS5的MATCH表达式
+    pub expression: String,
+    /// 用于高亮的关键词原文（不含排除词）
+    pub terms: Vec<String>,
+}
+
+/// 将关键词转换为FTS5短语，未加引号的关键词按前缀匹配
+fn fts_phrase(term: &str, prefix: bool) -> String {
+    let phrase = format!("\"{}\"", segment(term).replace('"', "\"\""));
+    if prefix {
+        format!("{}*", phrase)
+    } else {
+        phrase
+    }
+}
+
+/// 将语法树转换为FTS5表达式；含过滤条件、单独的排除词或只有标点的词时返回None
+fn fts_expression(node: &QueryNode) -> Option<String> {
+    match node {
+        QueryNode::Term(Term::Text { text, quoted }) => {
+            if !text.chars().any(char::is_alphanumeric) {
+                return None;
+            }
+            Some(fts_phrase(text, !quoted))
+        }
+        QueryNode::Term(_) | QueryNode::Not(_) => None,
+        QueryNode::Or(nodes) => {
+            let parts = nodes.iter().map(fts_expression).collect::<Option<Vec<_>>>()?;
+            Some(format!("({})", parts.join(" OR ")))
+        }
+        QueryNode::And(nodes) => {
+            // FTS5的NOT是二元运算，排除词放在所有包含词之后
+            let mut include = Vec::new();
+            let mut exclude = Vec::new();
+            for node in nodes {
+                match node {
+                    QueryNode::Not(inner) => exclude.push(fts_expression(inner)?),
+                    _ => include.push(fts_expression(node)?),
+                }
+            }
+            if include.is_empty() {
+                return None;
+            }
+            let mut expr = format!("({})", include.join(" AND "));
+            for part in exclude {
+                expr = format!("({} NOT {})", expr, part);
+            }
+            Some(expr)
+        }
+    }
+}
+
+impl FtsQuery {
+    /// 由搜索查询生成全文检索表达式，查询不能完全由索引处理时返回None
+    pub fn from_query(query: &SearchQuery) -> Option<Self> {
+        Some(Self {
+            expression: fts_expression(&query.root)?,
+            terms: query.highlight_terms(),
+        })
+    }
+}
+
+/// 按关键词生成摘要和高亮区间，区间为摘要内的字符下标`[start, end)`
+pub fn highlight(content: &str, terms: &[String]) -> (String, Vec<(usize, usize)>) {
+    let chars: Vec<char> = content.chars().collect();
+    let folded: Vec<char> = chars.iter().map(|c| c.to_lowercase().next().unwrap_or(*c)).collect();
+
+    let mut ranges = Vec::new();
+    for term in terms {
+        let needle: Vec<char> = term.chars().flat_map(char::to_lowercase).collect();
+        if needle.is_empty() || needle.len() > folded.len() {
+            continue;
+        }
+        let mut i = 0;
+        while i + needle.len() <= folded.len() {
+            if folded[i..i + needle.len()] == needle[..] {
+                ranges.push((i, i + needle.len()));
+                i += needle.len();
+            } else {
+                i += 1;
+            }
+        }
+    }
+    ranges.sort_unstable();
+
+    // 合并重叠的区间
+    let mut merged: Vec<(usize, usize)> = Vec::new();
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+
+    if chars.len() <= SNIPPET_LENGTH {
+        return (content.to_string(), merged);
+    }
+
+    let from = merged
+        .first()
+        .map(|(start, _)| start.saturating_sub(SNIPPET_BEFORE))
+        .unwrap_or(0)
+        .min(chars.len() - SNIPPET_LENGTH);
+    let to = from + SNIPPET_LENGTH;
+
+    let mut snippet = String::new();
+    let mut offset = 0;
+    if from > 0 {
+        snippet.push('…');
+        offset = 1;
+    }
+    snippet.extend(&chars[from..to]);
+    if to < chars.len() {
+        snippet.push('…');
+    }
+
+    let highlights = merged
+        .into_iter()
+        .filter(|(start, end)| *end > from && *start < to)
+        .map(|(start, end)| (start.max(from) - from + offset, end.min(to) - from + offset))
+        .collect();
+    (snippet, highlights)
+}
+
+/// 索引中的命中结果
+#[derive(Debug, Clone)]
+pub struct IndexHit {
+    /// 消息所在分片的数据库路径
+    pub shard_path: String,
+    pub local_id: i64,
+    /// 相关度，越大越相关
+    pub score: f64,
+}
+
+/// 带摘要和相关度的搜索结果
+#[derive(Debug, Clone)]
+pub struct SearchHit {
+    pub message: Message,
+    pub snippet: String,
+    /// 摘要中命中词的字符区间`[start, end)`
+    pub highlights: Vec<(usize, usize)>,
+    pub score: f64,
+}
+
+/// 消息全文索引，保存在独立的数据库文件中，不修改原始MSG库
+/// 以解析后的消息内容建立索引，按各分片的localId增量更新
+pub struct MsgIndex {
+    db: DatabaseBase,
+}
+
+impl MsgIndex {
+    /// 打开索引文件，不存在时创建
+    pub fn open(path: &Path) -> Result<Self> {
+        let conn = Connection::open(path)
+            .with_context(|| format!("Failed to open message index: {:?}", path))?;
+        let version: i64 = conn
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .map_err(db_error)?;
+        if version != SCHEMA_VERSION {
+            conn.execute_batch(
+                "DROP TABLE IF EXISTS msg_fts;
+                 DROP TABLE IF EXISTS msg_doc;
+                 DROP TABLE IF EXISTS msg_shard;",
+            )
+            .map_err(db_error)?;
+        }
+        conn.execute_batch(SCHEMA).map_err(db_error)?;
+        conn.pragma_update(None, "user_version", SCHEMA_VERSION)
+            .map_err(db_error)?;
+        drop(conn);
+
+        Ok(Self {
+            db: DatabaseBase::new(&path.to_string_lossy())?,
+        })
+    }
+
+    /// 索引文件路径
+    pub fn path(&self) -> &str {
+        self.db.get_db_path()
+    }
+
+    /// 已建立索引的消息数
+    pub fn indexed_count(&self) -> Result<i64> {
+        let rows = self
+            .db
+            .execute_query("SELECT COUNT(*) FROM msg_doc", &[], |row| row.get(0))?;
+        Ok(rows.into_iter().next().unwrap_or(0))
+    }
+
+    /// 获取分片在索引中的ID和已索引到的localId
+    fn shard_state(&self, shard_path: &str) -> Result<(i64, i64)> {
+        self.db.pool().with_writer(|conn| {
+            conn.execute(
+                "INSERT OR IGNORE INTO msg_shard (path) VALUES (?)",
+                [shard_path],
+            )
+            .map_err(db_error)?;
+            conn.query_row(
+                "SELECT id, last_local_id FROM msg_shard WHERE path = ?",
+                [shard_path],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .map_err(db_error)
+        })
+    }
+
+    /// 删除分片的全部索引
+    fn clear_shard(&self, shard_id: i64) -> Result<()> {
+        self.db.pool().with_writer(|conn| {
+            let tx = conn.unchecked_transaction().map_err(db_error)?;
+            tx.execute(
+                "DELETE FROM msg_fts WHERE rowid IN (SELECT id FROM msg_doc WHERE shard_id = ?)",
+                [shard_id],
+            )
+            .map_err(db_error)?;
+            tx.execute("DELETE FROM msg_doc WHERE shard_id = ?", [shard_id])
+                .map_err(db_error)?;
+            tx.execute("UPDATE msg_shard SET last_local_id = 0 WHERE id = ?", [shard_id])
+                .map_err(db_error)?;
+            tx.commit().map_err(db_error)
+        })
+    }
+
+    /// 写入一批消息并记录进度，返回写入索引的消息数
+    /// 并发更新时进度在事务内重新读取，已被其他更新写入的消息跳过
+    fn insert_batch(&self, shard_id: i64, messages: &[Message], last_local_id: i64) -> Result<usize> {
+        self.db.pool().with_writer(|conn| {
+            let tx = conn.unchecked_transaction().map_err(db_error)?;
+            let indexed: i64 = tx
+                .query_row(
+                    "SELECT last_local_id FROM msg_shard WHERE id = ?",
+                    [shard_id],
+                    |row| row.get(0),
+                )
+                .map_err(db_error)?;
+            if indexed >= last_local_id {
+                return Ok(0);
+            }
+
+            let mut inserted = 0;
+            {
+                let mut insert_doc = tx
+                    .prepare_cached(
+                        "INSERT OR IGNORE INTO msg_doc (shard_id, local_id, create_time, talker) VALUES (?, ?, ?, ?)",
+                    )
+                    .map_err(db_error)?;
+                let mut insert_fts = tx
+                    .prepare_cached("INSERT INTO msg_fts (rowid, body) VALUES (?, ?)")
+                    .map_err(db_error)?;
+
+                for msg in messages {
+                    if msg.local_id <= indexed || msg.content.trim().is_empty() {
+                        continue;
+                    }
+                    let changed = insert_doc
+                        .execute(rusqlite::params![shard_id, msg.local_id, msg.create_time, msg.str_talker])
+                        .map_err(db_error)?;
+                    if changed == 0 {
+                        continue;
+                    }
+                    let doc_id = tx.last_insert_rowid();
+                    insert_fts
+                        .execute(rusqlite::params![doc_id, segment(&msg.content)])
+                        .map_err(db_error)?;
+                    inserted += 1;
+                }
+            }
+            tx.execute(
+                "UPDATE msg_shard SET last_local_id = MAX(last_local_id, ?) WHERE id = ?",
+                [last_local_id, shard_id],
+            )
+            .map_err(db_error)?;
+            tx.commit().map_err(db_error)?;
+            Ok(inserted)
+        })
+    }
+
+    /// 分片中是否有尚未索引的消息，或分片已变化需要重建
+    pub fn is_stale(&self, shard_path: &str, shard: &DatabaseBase) -> Result<bool> {
+        if !shard.table_exists("MSG") {
+            return Ok(false);
+        }
+        let indexed: Option<i64> = self
+            .db
+            .execute_query(
+                "SELECT last_local_id FROM msg_shard WHERE path = ?",
+                &[&shard_path],
+                |row| row.get(0),
+            )?
+            .into_iter()
+            .next();
+        Ok(indexed != Some(Self::max_local_id(shard)?))
+    }
+
+    fn max_local_id(shard: &DatabaseBase) -> Result<i64> {
+        Ok(shard
+            .execute_query("SELECT IFNULL(MAX(localId), 0) FROM MSG", &[], |row| row.get(0))?
+            .into_iter()
+            .next()
+            .unwrap_or(0))
+    }
+
+    /// 将分片中新增的消息加入索引，返回新增的索引条数
+    /// 分片被重新解密或合并导致localId变小时，重建该分片的索引
+    pub fn update_shard(&self, shard_path: &str, shard: &DatabaseBase) -> Result<usize> {
+        if !shard.table_exists("MSG") {
+            return Ok(0);
+        }
+
+        let (shard_id, mut last_local_id) = self.shard_state(shard_path)?;
+        let max_local_id = Self::max_local_id(shard)?;
+        if max_local_id < last_local_id {
+            tracing::info!("MSG shard {} changed, rebuilding its index", shard_path);
+            self.clear_shard(shard_id)?;
+            last_local_id = 0;
+        }
+
+        let mut total = 0;
+        while last_local_id < max_local_id {
+            let batch = MsgList::messages_after(shard, last_local_id, INDEX_BATCH)?;
+            let Some(last) = batch.last() else {
+                break;
+            };
+            last_local_id = last.local_id;
+            total += self.insert_batch(shard_id, &batch, last_local_id)?;
+        }
+        Ok(total)
+    }
+
+    /// 按相关度搜索，可按聊天对象和时间范围过滤
+    pub fn search(
+        &self,
+        query: &FtsQuery,
+        wxid: Option<&str>,
+        start_time: Option<i64>,
+        end_time: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<IndexHit>> {
+        let mut sql = String::from(
+            "SELECT s.path, d.local_id, -bm25(msg_fts) AS score
+             FROM msg_fts
+             JOIN msg_doc d ON d.id = msg_fts.rowid
+             JOIN msg_shard s ON s.id = d.shard_id
+             WHERE msg_fts MATCH ?",
+        );
+        let mut params: Vec<&dyn rusqlite::ToSql> = vec![&query.expression];
+
+        if let Some(wxid) = &wxid {
+            sql.push_str(" AND d.talker = ?");
+            params.push(wxid);
+        }
+        if let Some(start) = &start_time {
+            sql.push_str(" AND d.create_time >= ?");
+            params.push(start);
+        }
+        if let Some(end) = &end_time {
+            sql.push_str(" AND d.create_time <= ?");
+            params.push(end);
+        }
+
+        sql.push_str(" ORDER BY score DESC, d.create_time DESC LIMIT ?");
+        params.push(&limit);
+
+        self.db.execute_query(&sql, &params, |row| {
+            Ok(IndexHit {
+                shard_path: row.get(0)?,
+                local_id: row.get(1)?,
+                score: row.get(2)?,
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_segment_cjk() {
+        assert_eq!(segment("hi你好").split_whitespace().collect::<Vec<_>>(), vec!["hi", "你", "好"]);
+        assert_eq!(segment("hello world"), "hello world");
+    }
+
+    #[test]
+    fn test_fts_expression() {
+        let fts = |input: &str| FtsQuery::from_query(&SearchQuery::parse(input).unwrap());
+
+        let query = fts("晚饭 \"hello world\" OR lunch -cancel").unwrap();
+        assert_eq!(query.terms, vec!["晚饭", "hello world", "lunch"]);
+        assert_eq!(
+            query.expression,
+            "((\" 晚  饭 \"* AND (\"hello world\" OR \"lunch\"*)) NOT \"cancel\"*)"
+        );
+
+        // OR分组与排除词同在一个AND中时，OR整体加括号后再接NOT
+        assert_eq!(
+            fts("lunch OR dinner -cancel").unwrap().expression,
+            "(((\"lunch\"* OR \"dinner\"*)) NOT \"cancel\"*)"
+        );
+        assert_eq!(
+            fts("(lunch -cancel) OR dinner").unwrap().expression,
+            "(((\"lunch\"*) NOT \"cancel\"*) OR \"dinner\"*)"
+        );
+
+        // 过滤条件和单独的排除词需要在MSG表上查询
+        assert!(fts("lunch type:text").is_none());
+        assert!(fts("-lunch").is_none());
+        assert!(fts("???").is_none());
+    }
+
+    #[test]
+    fn test_highlight() {
+        let (snippet, highlights) = highlight("Hello 世界, hello", &["hello".to_string(), "世界".to_string()]);
+        assert_eq!(snippet, "Hello 世界, hello");
+        assert_eq!(highlights, vec![(0, 5), (6, 8), (10, 15)]);
+
+        let long = format!("{}目标{}", "前".repeat(100), "后".repeat(100));
+        let (snippet, highlights) = highlight(&long, &["目标".to_string()]);
+        assert!(snippet.starts_with('…') && snippet.ends_with('…'));
+        let (start, end) = highlights[0];
+        let chars: Vec<char> = snippet.chars().collect();
+        assert_eq!(chars[start..end].iter().collect::<String>(), "目标");
+    }
+}
@@ -1,16 +1,338 @@
 use crate::db::dbbase::DatabaseBase;
-use crate::db::utils::{Message, timestamp_to_string};
+use crate::db::protobuf_parser::{BytesExtraInfo, ProtobufParser};
+use crate::db::utils::{is_chatroom, Message, ReplyTo, timestamp_to_string};
+use crate::db::msg_content::{ImgMsg, MessageContent, ReferMsg, SystemEvent, VideoMsg};
 use crate::db::msg_parser::MessageParser;
-use crate::utils::Result;
+use crate::db::search_query::{CompiledQuery, QueryNode, SearchQuery, Term};
+use crate::utils::{AppError, Result};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::HashMap;
 
-/// 消息列表查询功能
+/// 每次按MsgSvrID查找关联消息的数量，低于SQLite的参数上限
+const RELATED_BATCH: usize = 500;
+
+/// 查询消息时选取的列，与`map_message`中的下标对应
+const MESSAGE_COLUMNS: &str = "localId, MsgSvrID, Type, SubType, CreateTime, IsSender,
+                    TalkerId, StrTalker, StrContent, DisplayContent, BytesExtra, CompressContent";
+
+/// 编码后游标的字节长度（CreateTime + 分片序号 + localId）
+const CURSOR_SIZE: usize = 20;
+
+/// 消息在所有分片中的位置，按(CreateTime, 分片序号, localId)全局排序
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MsgCursor {
+    pub create_time: i64,
+    pub shard: u32,
+    pub local_id: i64,
+}
+
+impl MsgCursor {
+    fn of(shard: usize, msg: &Message) -> Self {
+        Self {
+            create_time: msg.create_time,
+            shard: shard as u32,
+            local_id: msg.local_id,
+        }
+    }
+
+    /// 指定时间之前的位置，向后翻页时从该时间的第一条消息开始
+    pub fn before_time(timestamp: i64) -> Self {
+        Self {
+            create_time: timestamp,
+            shard: 0,
+            local_id: i64::MIN,
+        }
+    }
+
+    /// 编码为不透明的十六进制字符串
+    pub fn encode(&self) -> String {
+        let mut bytes = Vec::with_capacity(CURSOR_SIZE);
+        bytes.extend_from_slice(&self.create_time.to_be_bytes());
+        bytes.extend_from_slice(&self.shard.to_be_bytes());
+        bytes.extend_from_slice(&self.local_id.to_be_bytes());
+        hex::encode(bytes)
+    }
+
+    pub fn decode(cursor: &str) -> Result<Self> {
+        let invalid = || AppError::BadRequest(format!("Invalid cursor: {}", cursor));
+        let bytes = hex::decode(cursor).map_err(|_| invalid())?;
+        if bytes.len() != CURSOR_SIZE {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            create_time: i64::from_be_bytes(bytes[0..8].try_into().unwrap()),
+            shard: u32::from_be_bytes(bytes[8..12].try_into().unwrap()),
+            local_id: i64::from_be_bytes(bytes[12..20].try_into().unwrap()),
+        })
+    }
+}
+
+/// 翻页方向
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PageDirection {
+    /// 游标之后（更新）的消息
+    #[default]
+    Forward,
+    /// 游标之前（更早）的消息
+    Backward,
+}
+
+impl PageDirection {
+    fn reverse(self) -> Self {
+        match self {
+            PageDirection::Forward => PageDirection::Backward,
+            PageDirection::Backward => PageDirection::Forward,
+        }
+    }
+}
+
+/// 一页消息（按时间正序）及前后翻页的游标，没有更多消息时游标为空
+#[derive(Debug, Clone)]
+pub struct MsgPage {
+    pub messages: Vec<Message>,
+    pub prev_cursor: Option<MsgCursor>,
+    pub next_cursor: Option<MsgCursor>,
+}
+
+/// 消息筛选条件
+struct MsgFilter<'a> {
+    wxid: Option<&'a str>,
+    /// (Type, SubType)，为空时不按类型筛选
+    types: &'a [(i32, Option<i32>)],
+    start_time: Option<i64>,
+    end_time: Option<i64>,
+}
+
+/// 生成按(Type, SubType)筛选的条件，参数追加到`params`
+fn type_clause<'a>(types: &'a [(i32, Option<i32>)], params: &mut Vec<&'a dyn rusqlite::ToSql>) -> String {
+    let clauses: Vec<&str> = types
+        .iter()
+        .map(|(msg_type, sub_type)| {
+            params.push(msg_type);
+            match sub_type {
+                Some(sub_type) => {
+                    params.push(sub_type);
+                    "(Type = ? AND SubType = ?)"
+                }
+                None => "(Type = ?)",
+            }
+        })
+        .collect();
+    format!("({})", clauses.join(" OR "))
+}
+
+/// 消息列表查询功能，跨所有MSG分片按时间统一排序和分页
 pub struct MsgList {
-    db: DatabaseBase,
+    shards: Vec<DatabaseBase>,
 }
 
 impl MsgList {
-    pub fn new(db: DatabaseBase) -> Self {
-        Self { db }
+    pub fn new(shards: Vec<DatabaseBase>) -> Self {
+        Self { shards }
+    }
+
+    /// 在每个含MSG表的分片上执行查询，结果附带分片序号
+    fn query_shards(
+        &self,
+        sql: &str,
+        params: &[&dyn rusqlite::ToSql],
+    ) -> Result<Vec<(usize, Message)>> {
+        let mut messages = Vec::new();
+        for (shard, db) in self.shards.iter().enumerate() {
+            if !db.table_exists("MSG") {
+                continue;
+            }
+            let rows = db.execute_query(sql, params, Self::map_message)?;
+            messages.extend(rows.into_iter().map(|msg| (shard, msg)));
+        }
+        Ok(messages)
+    }
+
+    /// 读取单个分片中localId大于指定值的消息，按localId升序，用于增量建立索引
+    pub(crate) fn messages_after(db: &DatabaseBase, local_id: i64, limit: i64) -> Result<Vec<Message>> {
+        if !db.table_exists("MSG") {
+            return Ok(Vec::new());
+        }
+        let sql = format!(
+            "SELECT {} FROM MSG WHERE localId > ? ORDER BY localId LIMIT ?",
+            MESSAGE_COLUMNS
+        );
+        db.execute_query(&sql, &[&local_id, &limit], Self::map_message)
+    }
+
+    /// 按localId读取指定分片中的消息
+    pub fn get_by_local_ids(&self, shard: usize, local_ids: &[i64]) -> Result<Vec<Message>> {
+        let Some(db) = self.shards.get(shard) else {
+            return Err(AppError::NotFound(format!("MSG shard {} not found", shard)));
+        };
+        if local_ids.is_empty() || !db.table_exists("MSG") {
+            return Ok(Vec::new());
+        }
+
+        let placeholders = vec!["?"; local_ids.len()].join(", ");
+        let sql = format!(
+            "SELECT {} FROM MSG WHERE localId IN ({})",
+            MESSAGE_COLUMNS, placeholders
+        );
+        let params: Vec<&dyn rusqlite::ToSql> =
+            local_ids.iter().map(|id| id as &dyn rusqlite::ToSql).collect();
+        let mut messages = db.execute_query(&sql, &params, Self::map_message)?;
+        self.link_related(&mut messages)?;
+        Ok(messages)
+    }
+
+    /// 将查询结果行转换为消息
+    fn map_message(row: &rusqlite::Row) -> rusqlite::Result<Message> {
+        let msg_type: i32 = row.get(2)?;
+        let sub_type: i32 = row.get(3)?;
+        let create_time: i64 = row.get(4)?;
+        let is_sender: i32 = row.get(5)?;
+        let str_talker: String = row.get(7)?;
+        let content: String = row.get::<_, Option<String>>(8)?.unwrap_or_default();
+        let bytes_extra: Option<Vec<u8>> = row.get(10).ok();
+        let compress_content: Option<Vec<u8>> = row.get(11).ok();
+
+        let extra_info = bytes_extra
+            .as_deref()
+            .and_then(|b| ProtobufParser::parse_bytes_extra_info(b).ok())
+            .unwrap_or_default();
+        let (sender_wxid, content) = Self::resolve_sender(&str_talker, is_sender, &content, &extra_info);
+
+        // 解析消息内容
+        let (parsed_content, src, extra) = Self::parse_message_content(
+            msg_type,
+            sub_type,
+            content,
+            bytes_extra.as_deref(),
+            compress_content.as_deref(),
+        );
+        let reply_to = if (msg_type, sub_type) == (49, 57) {
+            Self::reply_of(&extra)
+        } else {
+            None
+        };
+        let event = if matches!(msg_type, 10000 | 10002) {
+            extra.get("event").and_then(|v| serde_json::from_value(v.clone()).ok())
+        } else {
+            None
+        };
+
+        Ok(Message {
+            id: 0,
+            local_id: row.get(0)?,
+            msg_svr_id: row.get(1)?,
+            msg_type,
+            sub_type,
+            type_name: crate::db::utils::get_message_type_name(msg_type, sub_type).to_string(),
+            create_time,
+            create_time_str: timestamp_to_string(create_time),
+            is_sender,
+            talker: row.get(6)?,
+            str_talker,
+            sender_wxid,
+            content: parsed_content,
+            display_content: row.get::<_, Option<String>>(9)?.unwrap_or_default(),
+            src,
+            extra,
+            reply_to,
+            event,
+        })
+    }
+
+    /// 从引用回复的`extra.refer`中取出被引用消息
+    fn reply_of(extra: &serde_json::Value) -> Option<ReplyTo> {
+        let refer: ReferMsg = serde_json::from_value(extra.get("refer")?.clone()).ok()?;
+        Some(ReplyTo::from(&refer))
+    }
+
+    /// 按MsgSvrID在所有分片中查找引用回复所引用的消息和撤回事件所撤回的消息
+    pub fn link_related(&self, messages: &mut [Message]) -> Result<()> {
+        let mut svr_ids: Vec<i64> = messages
+            .iter()
+            .filter_map(Self::related_svr_id)
+            .filter(|id| *id != 0)
+            .collect();
+        svr_ids.sort_unstable();
+        svr_ids.dedup();
+        if svr_ids.is_empty() {
+            return Ok(());
+        }
+
+        let mut related: HashMap<i64, Message> = HashMap::new();
+        for chunk in svr_ids.chunks(RELATED_BATCH) {
+            let placeholders = vec!["?"; chunk.len()].join(", ");
+            let sql = format!(
+                "SELECT {} FROM MSG WHERE MsgSvrID IN ({})",
+                MESSAGE_COLUMNS, placeholders
+            );
+            let params: Vec<&dyn rusqlite::ToSql> =
+                chunk.iter().map(|id| id as &dyn rusqlite::ToSql).collect();
+            for (_, msg) in self.query_shards(&sql, &params)? {
+                related.insert(msg.msg_svr_id, msg);
+            }
+        }
+
+        for msg in messages.iter_mut() {
+            // 只关联同一会话中的消息
+            let Some(target) = Self::related_svr_id(msg)
+                .and_then(|id| related.get(&id))
+                .filter(|target| target.str_talker == msg.str_talker)
+            else {
+                continue;
+            };
+            if let Some(reply) = msg.reply_to.as_mut() {
+                reply.local_id = Some(target.local_id);
+                reply.create_time = target.create_time;
+                reply.msg_type = target.msg_type;
+                reply.content = target.content.clone();
+            }
+            if let Some(SystemEvent::Revoke { revoked_local_id, .. }) = msg.event.as_mut() {
+                *revoked_local_id = Some(target.local_id);
+            }
+        }
+        Ok(())
+    }
+
+    /// 消息关联的另一条消息的MsgSvrID：被引用或被撤回的消息
+    fn related_svr_id(msg: &Message) -> Option<i64> {
+        if let Some(reply) = &msg.reply_to {
+            return Some(reply.msg_svr_id);
+        }
+        match &msg.event {
+            Some(SystemEvent::Revoke { new_msg_id, .. }) => Some(*new_msg_id),
+            _ => None,
+        }
+    }
+
+    /// 确定消息的实际发送者，返回发送者wxid和去掉发送者前缀后的内容
+    /// 群聊的发送者保存在BytesExtra中；旧版本则以`wxid:\n`为前缀写在内容里
+    fn resolve_sender<'a>(
+        str_talker: &str,
+        is_sender: i32,
+        content: &'a str,
+        extra_info: &BytesExtraInfo,
+    ) -> (String, &'a str) {
+        if !is_chatroom(str_talker) {
+            let sender = if is_sender == 0 { str_talker.to_string() } else { String::new() };
+            return (sender, content);
+        }
+
+        if let Some((prefix, rest)) = content.split_once(":\n") {
+            let is_sender_prefix = match &extra_info.sender_wxid {
+                Some(sender) => prefix == sender,
+                None => {
+                    !prefix.is_empty()
+                        && prefix.chars().all(|c| c.is_ascii_alphanumeric() || "_-@.".contains(c))
+                }
+            };
+            if is_sender_prefix {
+                return (prefix.to_string(), rest);
+            }
+        }
+        (extra_info.sender_wxid.clone().unwrap_or_default(), content)
     }
 
     /// 获取消息列表（带用户信息）
@@ -36,6 +358,7 @@ impl MsgList {
     }
 
     /// 获取消息列表
+    /// 多个分片时每个分片取前`start_index + page_size`条，归并后再分页
     pub fn get_msg_list(
         &self,
         wxid: Option<&str>,
@@ -44,83 +367,204 @@ impl MsgList {
         start_time: Option<i64>,
         end_time: Option<i64>,
     ) -> Result<Vec<Message>> {
-        if !self.db.table_exists("MSG") {
-            return Ok(Vec::new());
-        }
-
-        let mut sql = String::from(
-            "SELECT localId, MsgSvrID, Type, SubType, CreateTime, IsSender, 
-                    TalkerId, StrTalker, StrContent, DisplayContent, BytesExtra, CompressContent
-             FROM MSG WHERE 1=1"
-        );
+        let mut sql = format!("SELECT {} FROM MSG WHERE 1=1", MESSAGE_COLUMNS);
 
         let mut params: Vec<&dyn rusqlite::ToSql> = Vec::new();
 
-        if let Some(wxid) = wxid {
+        if let Some(wxid) = &wxid {
             sql.push_str(" AND StrTalker = ?");
-            params.push(&wxid);
+            params.push(wxid);
         }
 
-        if let Some(start) = start_time {
+        if let Some(start) = &start_time {
             sql.push_str(" AND CreateTime >= ?");
-            params.push(&start);
+            params.push(start);
         }
 
-        if let Some(end) = end_time {
+        if let Some(end) = &end_time {
             sql.push_str(" AND CreateTime <= ?");
-            params.push(&end);
+            params.push(end);
         }
 
-        sql.push_str(" ORDER BY CreateTime ASC LIMIT ? OFFSET ?");
-        params.push(&page_size);
-        params.push(&start_index);
-
-        let messages = self.db.execute_query(&sql, &params, |row| {
-            let msg_type: i32 = row.get(2)?;
-            let sub_type: i32 = row.get(3)?;
-            let create_time: i64 = row.get(4)?;
-            let content: String = row.get(8)?;
-            let bytes_extra: Option<Vec<u8>> = row.get(10).ok();
-            let compress_content: Option<Vec<u8>> = row.get(11).ok();
-
-            // 解析消息内容
-            let (parsed_content, src, extra) = Self::parse_message_content(
-                msg_type,
-                sub_type,
-                &content,
-                bytes_extra.as_deref(),
-                compress_content.as_deref(),
-            );
+        let single_shard = self.shards.len() == 1;
+        let (limit, offset) = if single_shard {
+            (page_size, start_index)
+        } else {
+            (start_index + page_size, 0)
+        };
+        sql.push_str(" ORDER BY CreateTime ASC, localId ASC LIMIT ? OFFSET ?");
+        params.push(&limit);
+        params.push(&offset);
 
-            Ok(Message {
-                id: 0, // 将在后续计算
-                local_id: row.get(0)?,
-                msg_svr_id: row.get(1)?,
-                msg_type,
-                sub_type,
-                type_name: crate::db::utils::get_message_type_name(msg_type, sub_type).to_string(),
-                create_time,
-                create_time_str: timestamp_to_string(create_time),
-                is_sender: row.get(5)?,
-                talker: row.get(6)?,
-                str_talker: row.get(7)?,
-                content: parsed_content,
-                display_content: row.get(9)?,
-                src,
-                extra,
-            })
-        })?;
+        let mut messages = self.query_shards(&sql, &params)?;
+        // 时间相同时按分片和localId排序，保证分页稳定
+        messages.sort_by_key(|(shard, msg)| (msg.create_time, *shard, msg.local_id));
+        let skip = if single_shard { 0 } else { start_index.max(0) as usize };
 
         // 为消息分配ID
-        let mut messages_with_id = Vec::new();
-        for (idx, mut msg) in messages.into_iter().enumerate() {
-            msg.id = (start_index + idx as i64) as i64;
-            messages_with_id.push(msg);
-        }
+        let mut messages_with_id: Vec<Message> = messages
+            .into_iter()
+            .skip(skip)
+            .take(page_size.max(0) as usize)
+            .enumerate()
+            .map(|(idx, (_, mut msg))| {
+                msg.id = start_index + idx as i64;
+                msg
+            })
+            .collect();
+        self.link_related(&mut messages_with_id)?;
 
         Ok(messages_with_id)
     }
 
+    /// 按游标在每个分片上取`limit`条，归并后按翻页方向排序
+    fn keyset_query(
+        &self,
+        filter: &MsgFilter,
+        cursor: Option<&MsgCursor>,
+        direction: PageDirection,
+        limit: i64,
+    ) -> Result<Vec<(usize, Message)>> {
+        let mut messages = Vec::new();
+        for (shard, db) in self.shards.iter().enumerate() {
+            if !db.table_exists("MSG") {
+                continue;
+            }
+
+            let mut sql = format!("SELECT {} FROM MSG WHERE 1=1", MESSAGE_COLUMNS);
+            let mut params: Vec<&dyn rusqlite::ToSql> = Vec::new();
+
+            if let Some(wxid) = &filter.wxid {
+                sql.push_str(" AND StrTalker = ?");
+                params.push(wxid);
+            }
+
+            if !filter.types.is_empty() {
+                sql.push_str(" AND ");
+                sql.push_str(&type_clause(filter.types, &mut params));
+            }
+
+            if let Some(start) = &filter.start_time {
+                sql.push_str(" AND CreateTime >= ?");
+                params.push(start);
+            }
+
+            if let Some(end) = &filter.end_time {
+                sql.push_str(" AND CreateTime <= ?");
+                params.push(end);
+            }
+
+            // 分片序号位于CreateTime和localId之间，与游标所在分片比较后只需比较时间
+            if let Some(cursor) = cursor {
+                let shard_order = (shard as u32).cmp(&cursor.shard);
+                sql.push_str(match (direction, shard_order) {
+                    (PageDirection::Forward, Ordering::Less) => " AND CreateTime > ?",
+                    (PageDirection::Forward, Ordering::Equal) => " AND (CreateTime, localId) > (?, ?)",
+                    (PageDirection::Forward, Ordering::Greater) => " AND CreateTime >= ?",
+                    (PageDirection::Backward, Ordering::Less) => " AND CreateTime <= ?",
+                    (PageDirection::Backward, Ordering::Equal) => " AND (CreateTime, localId) < (?, ?)",
+                    (PageDirection::Backward, Ordering::Greater) => " AND CreateTime < ?",
+                });
+                params.push(&cursor.create_time);
+                if shard_order == Ordering::Equal {
+                    params.push(&cursor.local_id);
+                }
+            }
+
+            sql.push_str(match direction {
+                PageDirection::Forward => " ORDER BY CreateTime ASC, localId ASC LIMIT ?",
+                PageDirection::Backward => " ORDER BY CreateTime DESC, localId DESC LIMIT ?",
+            });
+            params.push(&limit);
+
+            let rows = db.execute_query(&sql, &params, Self::map_message)?;
+            messages.extend(rows.into_iter().map(|msg| (shard, msg)));
+        }
+
+        messages.sort_by_key(|(shard, msg)| MsgCursor::of(*shard, msg));
+        if direction == PageDirection::Backward {
+            messages.reverse();
+        }
+        messages.truncate(limit.max(0) as usize);
+        Ok(messages)
+    }
+
+    /// 按游标翻页，不使用OFFSET，翻到聊天深处也只扫描一页的数据
+    /// 没有游标时`Forward`从最早的消息开始，`Backward`从最新的消息开始
+    pub fn get_msg_page(
+        &self,
+        wxid: Option<&str>,
+        cursor: Option<&MsgCursor>,
+        direction: PageDirection,
+        page_size: i64,
+        start_time: Option<i64>,
+        end_time: Option<i64>,
+    ) -> Result<MsgPage> {
+        let filter = MsgFilter {
+            wxid,
+            types: &[],
+            start_time,
+            end_time,
+        };
+
+        // 多取一条用于判断该方向是否还有更多消息
+        let mut rows = self.keyset_query(&filter, cursor, direction, page_size + 1)?;
+        let has_more = rows.len() as i64 > page_size;
+        rows.truncate(page_size.max(0) as usize);
+
+        // 反方向是否还有消息：从本页最靠近游标的一条（空页时为游标本身）反向取一条
+        let edge = rows.first().map(|(shard, msg)| MsgCursor::of(*shard, msg)).or(cursor.copied());
+        let has_before = match edge {
+            Some(edge) => !self
+                .keyset_query(&filter, Some(&edge), direction.reverse(), 1)?
+                .is_empty(),
+            None => false,
+        };
+
+        if direction == PageDirection::Backward {
+            rows.reverse();
+        }
+        let first = rows.first().map(|(shard, msg)| MsgCursor::of(*shard, msg)).or(cursor.copied());
+        let last = rows.last().map(|(shard, msg)| MsgCursor::of(*shard, msg)).or(cursor.copied());
+        let (has_prev, has_next) = match direction {
+            PageDirection::Forward => (has_before, has_more),
+            PageDirection::Backward => (has_more, has_before),
+        };
+
+        let mut messages: Vec<Message> = rows
+            .into_iter()
+            .enumerate()
+            .map(|(idx, (_, mut msg))| {
+                msg.id = idx as i64;
+                msg
+            })
+            .collect();
+        self.link_related(&mut messages)?;
+
+        Ok(MsgPage {
+            messages,
+            prev_cursor: first.filter(|_| has_prev),
+            next_cursor: last.filter(|_| has_next),
+        })
+    }
+
+    /// 跳转到指定时间：返回从该时间起的一页消息；之后没有消息时返回该时间之前最近的一页
+    pub fn get_msg_page_at(
+        &self,
+        wxid: Option<&str>,
+        timestamp: i64,
+        page_size: i64,
+        start_time: Option<i64>,
+        end_time: Option<i64>,
+    ) -> Result<MsgPage> {
+        let cursor = MsgCursor::before_time(timestamp);
+        let page = self.get_msg_page(wxid, Some(&cursor), PageDirection::Forward, page_size, start_time, end_time)?;
+        if !page.messages.is_empty() {
+            return Ok(page);
+        }
+        self.get_msg_page(wxid, Some(&cursor), PageDirection::Backward, page_size, start_time, end_time)
+    }
+
     /// 解析消息内容
     fn parse_message_content(
         msg_type: i32,
@@ -137,79 +581,80 @@ impl MsgList {
             (3, 0) => {
                 // 图片消息
                 let src = bytes_extra
-                    .and_then(|b| MessageParser::parse_image_path(b))
+                    .and_then(MessageParser::parse_image_path)
                     .unwrap_or_default();
-                ("图片".to_string(), src, serde_json::json!({}))
+                let extra = ImgMsg::parse(content).map(MessageContent::Image);
+                ("图片".to_string(), src, Self::extra_of(extra))
             }
             (34, 0) => {
                 // 语音消息
-                let voice_info = MessageParser::parse_voice_message(content);
-                let voicelength = voice_info.get("voicelength").cloned().unwrap_or_default();
-                let transtext = voice_info.get("transtext").cloned().unwrap_or_default();
-                let msg = if !transtext.is_empty() {
-                    format!("语音时长：{}秒\n翻译结果：{}", voicelength, transtext)
+                let voice = MessageParser::parse_voice_message(content).unwrap_or_default();
+                let msg = if !voice.transtext.is_empty() {
+                    format!("语音时长：{:.2}秒\n翻译结果：{}", voice.voicelength, voice.transtext)
                 } else {
-                    format!("语音时长：{}秒", voicelength)
+                    format!("语音时长：{:.2}秒", voice.voicelength)
                 };
-                (msg, String::new(), serde_json::json!(voice_info))
+                (msg, String::new(), MessageContent::Voice(voice).to_extra())
             }
             (43, 0) => {
                 // 视频消息
                 let src = bytes_extra
-                    .and_then(|b| MessageParser::parse_video_message(b))
+                    .and_then(MessageParser::parse_video_message)
                     .unwrap_or_default();
-                ("视频".to_string(), src, serde_json::json!({}))
+                let extra = VideoMsg::parse(content).map(MessageContent::Video);
+                ("视频".to_string(), src, Self::extra_of(extra))
             }
             (47, 0) => {
                 // 动画表情
-                let cdnurl = MessageParser::parse_emoji_message(content, bytes_extra.unwrap_or(&[]))
-                    .unwrap_or_default();
-                ("表情".to_string(), cdnurl, serde_json::json!({}))
+                let (emoji, cdnurl) =
+                    MessageParser::parse_emoji_message(content, bytes_extra.unwrap_or(&[]));
+                let extra = emoji.map(MessageContent::Emoji);
+                ("表情".to_string(), cdnurl.unwrap_or_default(), Self::extra_of(extra))
             }
             (48, 0) => {
                 // 位置消息
-                let location = MessageParser::parse_location_message(content);
+                let location = MessageParser::parse_location_message(content).unwrap_or_default();
                 let msg = format!(
                     "纬度:【{}】 经度:【{}】\n位置：{} {}",
-                    location.get("latitude").unwrap_or(&String::new()),
-                    location.get("longitude").unwrap_or(&String::new()),
-                    location.get("label").unwrap_or(&String::new()),
-                    location.get("poiname").unwrap_or(&String::new())
+                    location.latitude, location.longitude, location.label, location.poiname
                 );
-                (msg, String::new(), serde_json::json!(location))
+                (msg, String::new(), MessageContent::Location(location).to_extra())
             }
-            (49, 0) | (49, 6) => {
-                // 文件消息
-                let src = bytes_extra
-                    .and_then(|b| MessageParser::parse_file_message(b))
-                    .unwrap_or_default();
-                let file_name = std::path::Path::new(&src)
-                    .file_name()
-                    .and_then(|n| n.to_str())
-                    .unwrap_or("文件")
-                    .to_string();
-                (file_name, src, serde_json::json!({}))
+            (49, _) => {
+                // 应用消息，XML结构见`AppMsg`
+                let app = MessageParser::parse_app_message(compress_content.unwrap_or(&[]), content);
+                let extra = Self::extra_of(app.clone().map(|app| MessageContent::App(Box::new(app))));
+                let app = app.unwrap_or_default();
+                match sub_type {
+                    0 | 6 => {
+                        // 文件消息
+                        let src = bytes_extra
+                            .and_then(MessageParser::parse_file_message)
+                            .unwrap_or_default();
+                        let file_name = if !app.title.is_empty() {
+                            app.title
+                        } else {
+                            std::path::Path::new(&src)
+                                .file_name()
+                                .and_then(|n| n.to_str())
+                                .unwrap_or("文件")
+                                .to_string()
+                        };
+                        (file_name, src, extra)
+                    }
+                    5 => {
+                        // 分享卡片式链接
+                        let msg = format!("{}\n{}\n\n链接：{}", app.title, app.des, app.url);
+                        (msg, app.url, extra)
+                    }
+                    _ if !app.title.is_empty() => (app.title, app.url, extra),
+                    _ => (content.to_string(), app.url, extra),
+                }
             }
-            (49, 5) => {
-                // 分享卡片式链接
-                let share_info = MessageParser::parse_share_message(
-                    compress_content.unwrap_or(&[]),
-                    content,
-                );
-                let title = share_info.get("title")
-                    .and_then(|v| v.as_str())
-                    .unwrap_or("")
-                    .to_string();
-                let des = share_info.get("des")
-                    .and_then(|v| v.as_str())
-                    .unwrap_or("")
-                    .to_string();
-                let url = share_info.get("url")
-                    .and_then(|v| v.as_str())
-                    .unwrap_or("")
-                    .to_string();
-                let msg = format!("{}\n{}\n\n链接：{}", title, des, url);
-                (msg, url, serde_json::json!(share_info))
+            (10000, _) | (10002, _) => {
+                // 系统消息
+                let sys = MessageParser::parse_system_message(content);
+                (sys.text.clone(), String::new(), MessageContent::System(sys).to_extra())
             }
             _ => {
                 // 其他类型
@@ -218,7 +663,80 @@ impl MsgList {
         }
     }
 
-    /// 搜索消息
+    /// 未能解析出结构化内容时`extra`为空对象
+    fn extra_of(content: Option<MessageContent>) -> serde_json::Value {
+        content
+            .map(|c| c.to_extra())
+            .unwrap_or_else(|| serde_json::json!({}))
+    }
+
+    /// 获取指定类型`(Type, SubType)`的全部消息，按时间升序；SubType为空时匹配该Type的所有子类型
+    pub fn get_msgs_by_type(
+        &self,
+        wxid: Option<&str>,
+        types: &[(i32, Option<i32>)],
+        start_time: Option<i64>,
+        end_time: Option<i64>,
+    ) -> Result<Vec<Message>> {
+        if types.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut params: Vec<&dyn rusqlite::ToSql> = Vec::new();
+        let mut sql = format!(
+            "SELECT {} FROM MSG WHERE {}",
+            MESSAGE_COLUMNS,
+            type_clause(types, &mut params)
+        );
+
+        if let Some(wxid) = &wxid {
+            sql.push_str(" AND StrTalker = ?");
+            params.push(wxid);
+        }
+
+        if let Some(start) = &start_time {
+            sql.push_str(" AND CreateTime >= ?");
+            params.push(start);
+        }
+
+        if let Some(end) = &end_time {
+            sql.push_str(" AND CreateTime <= ?");
+            params.push(end);
+        }
+
+        sql.push_str(" ORDER BY CreateTime ASC, localId ASC");
+
+        let mut messages = self.query_shards(&sql, &params)?;
+        messages.sort_by_key(|(shard, msg)| (msg.create_time, *shard, msg.local_id));
+        Ok(messages.into_iter().map(|(_, msg)| msg).collect())
+    }
+
+    /// 按游标读取指定类型的消息，从游标之后按时间升序取至多`limit`条，每条附带其游标
+    pub fn get_msgs_by_type_after(
+        &self,
+        wxid: Option<&str>,
+        types: &[(i32, Option<i32>)],
+        cursor: Option<&MsgCursor>,
+        limit: i64,
+        start_time: Option<i64>,
+        end_time: Option<i64>,
+    ) -> Result<Vec<(MsgCursor, Message)>> {
+        if types.is_empty() {
+            return Ok(Vec::new());
+        }
+        let filter = MsgFilter {
+            wxid,
+            types,
+            start_time,
+            end_time,
+        };
+        let rows = self.keyset_query(&filter, cursor, PageDirection::Forward, limit)?;
+        Ok(rows
+            .into_iter()
+            .map(|(shard, msg)| (MsgCursor::of(shard, &msg), msg))
+            .collect())
+    }
+
+    /// 搜索消息，按时间倒序返回所有分片中最新的`limit`条
     pub fn search_messages(
         &self,
         wxid: Option<&str>,
@@ -227,83 +745,66 @@ impl MsgList {
         end_time: Option<i64>,
         limit: i64,
     ) -> Result<Vec<Message>> {
-        if !self.db.table_exists("MSG") {
-            return Ok(Vec::new());
-        }
+        let query = SearchQuery {
+            root: QueryNode::Term(Term::Text {
+                text: keyword.to_string(),
+                quoted: true,
+            }),
+        };
+        self.search_query(&query.to_sql(), wxid, start_time, end_time, limit)
+    }
 
-        let mut sql = String::from(
-            "SELECT localId, MsgSvrID, Type, SubType, CreateTime, IsSender, 
-                    TalkerId, StrTalker, StrContent, DisplayContent, BytesExtra, CompressContent
-             FROM MSG WHERE (StrContent LIKE ? OR DisplayContent LIKE ?)"
+    /// 按编译后的搜索条件查询，按时间倒序返回所有分片中最新的`limit`条
+    pub fn search_query(
+        &self,
+        condition: &CompiledQuery,
+        wxid: Option<&str>,
+        start_time: Option<i64>,
+        end_time: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<Message>> {
+        let mut sql = format!(
+            "SELECT {} FROM MSG WHERE {}",
+            MESSAGE_COLUMNS, condition.where_clause
         );
 
-        let mut params: Vec<&dyn rusqlite::ToSql> = Vec::new();
-        let search_pattern = format!("%{}%", keyword);
-        params.push(&search_pattern);
-        params.push(&search_pattern);
+        let mut params = condition.params();
 
-        if let Some(wxid) = wxid {
+        if let Some(wxid) = &wxid {
             sql.push_str(" AND StrTalker = ?");
-            params.push(&wxid);
+            params.push(wxid);
         }
 
-        if let Some(start) = start_time {
+        if let Some(start) = &start_time {
             sql.push_str(" AND CreateTime >= ?");
-            params.push(&start);
+            params.push(start);
         }
 
-        if let Some(end) = end_time {
+        if let Some(end) = &end_time {
             sql.push_str(" AND CreateTime <= ?");
-            params.push(&end);
+            params.push(end);
         }
 
-        sql.push_str(" ORDER BY CreateTime DESC LIMIT ?");
+        sql.push_str(" ORDER BY CreateTime DESC, localId DESC LIMIT ?");
         params.push(&limit);
 
-        let messages = self.db.execute_query(&sql, &params, |row| {
-            let msg_type: i32 = row.get(2)?;
-            let sub_type: i32 = row.get(3)?;
-            let create_time: i64 = row.get(4)?;
-            let content: String = row.get(8)?;
-            let bytes_extra: Option<Vec<u8>> = row.get(10).ok();
-            let compress_content: Option<Vec<u8>> = row.get(11).ok();
-
-            // 解析消息内容
-            let (parsed_content, src, extra) = Self::parse_message_content(
-                msg_type,
-                sub_type,
-                &content,
-                bytes_extra.as_deref(),
-                compress_content.as_deref(),
-            );
-
-            Ok(Message {
-                id: 0,
-                local_id: row.get(0)?,
-                msg_svr_id: row.get(1)?,
-                msg_type,
-                sub_type,
-                type_name: crate::db::utils::get_message_type_name(msg_type, sub_type).to_string(),
-                create_time,
-                create_time_str: timestamp_to_string(create_time),
-                is_sender: row.get(5)?,
-                talker: row.get(6)?,
-                str_talker: row.get(7)?,
-                content: parsed_content,
-                display_content: row.get(9)?,
-                src,
-                extra,
-            })
-        })?;
+        let mut messages = self.query_shards(&sql, &params)?;
+        messages.sort_by_key(|(shard, msg)| {
+            std::cmp::Reverse((msg.create_time, *shard, msg.local_id))
+        });
 
         // 为消息分配ID
-        let mut messages_with_id = Vec::new();
-        for (idx, mut msg) in messages.into_iter().enumerate() {
-            msg.id = idx as i64;
-            messages_with_id.push(msg);
-        }
+        let mut messages_with_id: Vec<Message> = messages
+            .into_iter()
+            .take(limit.max(0) as usize)
+            .enumerate()
+            .map(|(idx, (_, mut msg))| {
+                msg.id = idx as i64;
+                msg
+            })
+            .collect();
+        self.link_related(&mut messages_with_id)?;
 
         Ok(messages_with_id)
     }
 }
-
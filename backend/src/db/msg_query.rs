@@ -6,57 +6,74 @@ use std::sync::LazyLock;
 // 全局缓存管理器
 static CACHE: LazyLock<CacheManager> = LazyLock::new(|| CacheManager::new());
 
-/// 消息查询相关功能
+/// 消息查询相关功能，统计结果汇总所有MSG分片
 pub struct MsgQuery {
-    db: DatabaseBase,
+    shards: Vec<DatabaseBase>,
 }
 
 impl MsgQuery {
-    pub fn new(db: DatabaseBase) -> Self {
-        Self { db }
+    pub fn new(shards: Vec<DatabaseBase>) -> Self {
+        Self { shards }
+    }
+
+    /// 含MSG表的分片
+    fn msg_shards(&self) -> impl Iterator<Item = &DatabaseBase> {
+        self.shards.iter().filter(|db| db.table_exists("MSG"))
+    }
+
+    /// 缓存键使用的路径，多个分片时用`|`连接
+    fn cache_path(&self) -> String {
+        self.shards
+            .iter()
+            .map(|db| db.get_db_path())
+            .collect::<Vec<_>>()
+            .join("|")
     }
 
     /// 获取消息数量（带缓存）
     pub fn get_msg_count(&self, wxid: Option<&str>) -> Result<HashMap<String, i64>> {
-        if !self.db.table_exists("MSG") {
+        if self.msg_shards().next().is_none() {
             return Ok(HashMap::new());
         }
 
         // 尝试从缓存获取
-        let cache_key = CacheManager::msg_count_key(self.db.get_db_path(), wxid);
+        let cache_key = CacheManager::msg_count_key(&self.cache_path(), wxid);
         if let Some(cached) = CACHE.msg_count.get(&cache_key) {
             return Ok(cached);
         }
 
         // 使用索引优化查询
-        let sql = if let Some(wxid) = wxid {
+        let sql = if wxid.is_some() {
             "SELECT StrTalker, COUNT(*) FROM MSG WHERE StrTalker = ? GROUP BY StrTalker"
         } else {
-            "SELECT StrTalker, COUNT(*) FROM MSG GROUP BY StrTalker ORDER BY COUNT(*) DESC"
-        };
-
-        let mut result = HashMap::new();
-        let rows = if let Some(wxid) = wxid {
-            self.db.execute_query(sql, &[&wxid], |row| {
-                Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
-            })?
-        } else {
-            self.db.execute_query(sql, &[], |row| {
-                Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
-            })?
+            "SELECT StrTalker, COUNT(*) FROM MSG GROUP BY StrTalker"
         };
 
-        for (talker, count) in rows {
-            result.insert(talker, count);
+        let mut result: HashMap<String, i64> = HashMap::new();
+        let mut total = 0;
+        for db in self.msg_shards() {
+            let rows = if let Some(wxid) = &wxid {
+                db.execute_query(sql, &[wxid], |row| {
+                    Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+                })?
+            } else {
+                db.execute_query(sql, &[], |row| {
+                    Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+                })?
+            };
+
+            for (talker, count) in rows {
+                *result.entry(talker).or_insert(0) += count;
+            }
+
+            // 获取总数（使用索引）
+            total += db.execute_query(
+                "SELECT COUNT(*) FROM MSG",
+                &[],
+                |row| row.get::<_, i64>(0)
+            )?.first().copied().unwrap_or(0);
         }
 
-        // 获取总数（使用索引）
-        let total: i64 = self.db.execute_query(
-            "SELECT COUNT(*) FROM MSG",
-            &[],
-            |row| Ok(row.get::<_, i64>(0)?)
-        )?.first().copied().unwrap_or(0);
-
         result.insert("total".to_string(), total);
         
         // 存入缓存
@@ -65,6 +82,33 @@ impl MsgQuery {
         Ok(result)
     }
 
+    /// 在每个分片上执行分组统计（分组键、总数、发送数、接收数），按分组键累加
+    fn sum_grouped(
+        &self,
+        sql: &str,
+        params: &[&dyn rusqlite::ToSql],
+    ) -> Result<HashMap<String, (i64, i64, i64)>> {
+        let mut result: HashMap<String, (i64, i64, i64)> = HashMap::new();
+        for db in self.msg_shards() {
+            let rows = db.execute_query(sql, params, |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, i64>(1)?,
+                    row.get::<_, i64>(2)?,
+                    row.get::<_, i64>(3)?,
+                ))
+            })?;
+
+            for (key, total, sender, receiver) in rows {
+                let entry = result.entry(key).or_insert((0, 0, 0));
+                entry.0 += total;
+                entry.1 += sender;
+                entry.2 += receiver;
+            }
+        }
+        Ok(result)
+    }
+
     /// 获取日期聊天统计（带缓存）
     pub fn get_date_count(
         &self,
@@ -72,12 +116,12 @@ impl MsgQuery {
         start_time: Option<i64>,
         end_time: Option<i64>,
     ) -> Result<HashMap<String, serde_json::Value>> {
-        if !self.db.table_exists("MSG") {
+        if self.msg_shards().next().is_none() {
             return Ok(HashMap::new());
         }
 
         // 尝试从缓存获取
-        let cache_key = CacheManager::date_stats_key(self.db.get_db_path(), wxid, start_time, end_time);
+        let cache_key = CacheManager::date_stats_key(&self.cache_path(), wxid, start_time, end_time);
         if let Some(cached) = CACHE.date_stats.get(&cache_key) {
             return Ok(cached);
         }
@@ -93,34 +137,25 @@ impl MsgQuery {
 
         let mut params: Vec<&dyn rusqlite::ToSql> = Vec::new();
 
-        if let Some(wxid) = wxid {
+        if let Some(wxid) = &wxid {
             sql.push_str(" AND StrTalker = ?");
-            params.push(&wxid);
+            params.push(wxid);
         }
 
-        if let Some(start) = start_time {
+        if let Some(start) = &start_time {
             sql.push_str(" AND CreateTime >= ?");
-            params.push(&start);
+            params.push(start);
         }
 
-        if let Some(end) = end_time {
+        if let Some(end) = &end_time {
             sql.push_str(" AND CreateTime <= ?");
-            params.push(&end);
+            params.push(end);
         }
 
-        sql.push_str(" GROUP BY date ORDER BY date ASC");
-
-        let rows = self.db.execute_query(&sql, &params, |row| {
-            Ok((
-                row.get::<_, String>(0)?,
-                row.get::<_, i64>(1)?,
-                row.get::<_, i64>(2)?,
-                row.get::<_, i64>(3)?,
-            ))
-        })?;
+        sql.push_str(" GROUP BY date");
 
         let mut result = HashMap::new();
-        for (date, total, sender, receiver) in rows {
+        for (date, (total, sender, receiver)) in self.sum_grouped(&sql, &params)? {
             result.insert(
                 date,
                 serde_json::json!({
@@ -144,7 +179,7 @@ impl MsgQuery {
         start_time: Option<i64>,
         end_time: Option<i64>,
     ) -> Result<HashMap<String, serde_json::Value>> {
-        if !self.db.table_exists("MSG") {
+        if self.msg_shards().next().is_none() {
             return Ok(HashMap::new());
         }
 
@@ -157,30 +192,25 @@ impl MsgQuery {
 
         let mut params: Vec<&dyn rusqlite::ToSql> = Vec::new();
 
-        if let Some(start) = start_time {
+        if let Some(start) = &start_time {
             sql.push_str(" AND CreateTime >= ?");
-            params.push(&start);
+            params.push(start);
         }
 
-        if let Some(end) = end_time {
+        if let Some(end) = &end_time {
             sql.push_str(" AND CreateTime <= ?");
-            params.push(&end);
+            params.push(end);
         }
 
-        sql.push_str(" GROUP BY StrTalker ORDER BY count DESC LIMIT ?");
-        params.push(&top);
+        // 同一联系人的消息可能分布在多个分片，先汇总再取前N名
+        sql.push_str(" GROUP BY StrTalker");
 
-        let rows = self.db.execute_query(&sql, &params, |row| {
-            Ok((
-                row.get::<_, String>(0)?,
-                row.get::<_, i64>(1)?,
-                row.get::<_, i64>(2)?,
-                row.get::<_, i64>(3)?,
-            ))
-        })?;
+        let mut talkers: Vec<_> = self.sum_grouped(&sql, &params)?.into_iter().collect();
+        talkers.sort_by(|a, b| b.1 .0.cmp(&a.1 .0).then_with(|| a.0.cmp(&b.0)));
+        talkers.truncate(top.max(0) as usize);
 
         let mut result = HashMap::new();
-        for (talker, total, sender, receiver) in rows {
+        for (talker, (total, sender, receiver)) in talkers {
             result.insert(
                 talker,
                 serde_json::json!({
@@ -194,4 +224,3 @@ impl MsgQuery {
         Ok(result)
     }
 }
-
@@ -1,213 +1,442 @@
 use crate::utils::Result;
 use anyhow::Context;
-use std::collections::HashMap;
+use prost::Message as _;
+use serde::{Deserialize, Serialize};
 
-/// Protobuf解析器
-/// 用于解析微信消息中的BytesExtra字段（protobuf格式）
-pub struct ProtobufParser;
+/// 解析嵌套消息的最大深度，防止异常数据导致过深递归
+const MAX_DEPTH: usize = 16;
 
-impl ProtobufParser {
-    /// 解析BytesExtra字段（protobuf格式）
-    /// 微信的BytesExtra通常包含文件路径、URL等信息
-    pub fn parse_bytes_extra(bytes: &[u8]) -> Result<HashMap<String, String>> {
-        let mut result = HashMap::new();
+/// 未知结构的protobuf字段
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProtoField {
+    pub number: u32,
+    pub value: ProtoValue,
+}
+
+/// 字段值；长度前缀字段依次尝试按字符串、子消息解析，都不符合时保留原始字节
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProtoValue {
+    Varint(u64),
+    Fixed64(u64),
+    Fixed32(u32),
+    String(String),
+    Message { fields: Vec<ProtoField>, raw: Vec<u8> },
+    Bytes(Vec<u8>),
+}
 
-        if bytes.is_empty() {
-            return Ok(result);
+impl ProtoValue {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            ProtoValue::String(s) => Some(s),
+            _ => None,
         }
+    }
 
-        // 尝试解析protobuf格式
-        // 微信的BytesExtra通常是一个repeated字段的protobuf消息
-        // 由于没有公开的.proto文件，我们使用启发式方法解析
+    pub fn as_message(&self) -> Option<&[ProtoField]> {
+        match self {
+            ProtoValue::Message { fields, .. } => Some(fields),
+            _ => None,
+        }
+    }
 
-        // 方法1: 尝试解析常见的protobuf字段
-        if let Ok(parsed) = Self::parse_protobuf_fields(bytes) {
-            return Ok(parsed);
+    /// 长度前缀字段的原始字节
+    pub fn raw_bytes(&self) -> Option<&[u8]> {
+        match self {
+            ProtoValue::String(s) => Some(s.as_bytes()),
+            ProtoValue::Message { raw, .. } => Some(raw),
+            ProtoValue::Bytes(bytes) => Some(bytes),
+            _ => None,
         }
+    }
 
-        // 方法2: 如果protobuf解析失败，回退到字符串搜索
-        // 这可以处理一些简单的protobuf消息
-        if let Ok(parsed) = Self::parse_fallback(bytes) {
-            return Ok(parsed);
+    /// 按packed repeated varint解析长度前缀字段
+    pub fn packed_varints(&self) -> Option<Vec<u64>> {
+        let mut bytes = self.raw_bytes()?;
+        let mut values = Vec::new();
+        while !bytes.is_empty() {
+            let (value, consumed) = ProtobufParser::read_varint(bytes).ok()?;
+            values.push(value);
+            bytes = &bytes[consumed..];
         }
+        Some(values)
+    }
+}
+
+/// 按字段号查找第一个字段
+pub fn find_field(fields: &[ProtoField], number: u32) -> Option<&ProtoValue> {
+    fields.iter().find(|f| f.number == number).map(|f| &f.value)
+}
+
+/// BytesExtra中条目的类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum BytesExtraType {
+    Unknown = 0,
+    /// 群聊消息的发送者wxid
+    Sender = 1,
+    /// 缩略图路径
+    Thumb = 3,
+    /// 原图、视频或文件路径
+    Source = 4,
+    /// msgsource XML
+    MsgSource = 7,
+}
+
+/// BytesExtra的头部，含义未知
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct BytesExtraHeader {
+    #[prost(int32, tag = "1")]
+    pub field1: i32,
+    #[prost(int32, tag = "2")]
+    pub field2: i32,
+}
+
+/// BytesExtra中的一个条目
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct BytesExtraItem {
+    #[prost(enumeration = "BytesExtraType", tag = "1")]
+    pub item_type: i32,
+    #[prost(bytes = "vec", tag = "2")]
+    pub value: Vec<u8>,
+}
+
+impl BytesExtraItem {
+    pub fn value_str(&self) -> String {
+        String::from_utf8_lossy(&self.value)
+            .trim_matches('\0')
+            .trim()
+            .to_string()
+    }
+}
+
+/// MSG表BytesExtra列的结构：{1: 头部, 3: [{1: 类型, 2: 值}, ...]}
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct BytesExtra {
+    #[prost(message, optional, tag = "1")]
+    pub header: Option<BytesExtraHeader>,
+    #[prost(message, repeated, tag = "3")]
+    pub items: Vec<BytesExtraItem>,
+}
 
-        Ok(result)
+impl BytesExtra {
+    /// 第一个指定类型的非空条目
+    pub fn value_of(&self, item_type: BytesExtraType) -> Option<String> {
+        self.items
+            .iter()
+            .filter(|item| item.item_type() == item_type)
+            .map(BytesExtraItem::value_str)
+            .find(|value| !value.is_empty())
     }
+}
+
+/// 群成员，含义同ChatRoom表的UserNameList/DisplayNameList
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct RoomMember {
+    #[prost(string, tag = "1")]
+    pub wxid: String,
+    /// 群昵称，未设置时为空
+    #[prost(string, tag = "2")]
+    pub display_name: String,
+    #[prost(int32, tag = "3")]
+    pub state: i32,
+}
 
-    /// 解析protobuf字段
-    /// 使用protobuf的wire format解析
-    fn parse_protobuf_fields(bytes: &[u8]) -> Result<HashMap<String, String>> {
-        let mut result = HashMap::new();
+/// ChatRoom表RoomData列的结构：{1: [成员, ...]}，其余字段忽略
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct RoomData {
+    #[prost(message, repeated, tag = "1")]
+    pub members: Vec<RoomMember>,
+}
+
+/// 从BytesExtra中解析出的消息附加信息
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BytesExtraInfo {
+    /// 群聊消息的实际发送者
+    pub sender_wxid: Option<String>,
+    pub thumb_path: Option<String>,
+    pub source_path: Option<String>,
+    pub msg_source: Option<String>,
+}
+
+/// Protobuf解析器
+/// 用于解析微信消息中的BytesExtra字段（protobuf格式）
+pub struct ProtobufParser;
+
+impl ProtobufParser {
+    /// 按wire format解析未知结构的消息，返回字段树
+    pub fn decode_fields(bytes: &[u8]) -> Result<Vec<ProtoField>> {
+        Self::decode_at_depth(bytes, 0)
+    }
+
+    fn decode_at_depth(bytes: &[u8], depth: usize) -> Result<Vec<ProtoField>> {
+        let mut fields = Vec::new();
         let mut offset = 0;
 
         while offset < bytes.len() {
-            // 读取field number和wire type
-            if offset >= bytes.len() {
-                break;
-            }
+            let (tag, consumed) = Self::read_varint(&bytes[offset..])?;
+            offset += consumed;
 
-            let tag = bytes[offset];
-            offset += 1;
+            let number = u32::try_from(tag >> 3)
+                .ok()
+                .filter(|n| *n > 0)
+                .context("Invalid field number")?;
 
-            let field_number = tag >> 3;
-            let wire_type = tag & 0x07;
-
-            match wire_type {
+            let value = match tag & 0x07 {
                 0 => {
-                    // Varint
                     let (value, consumed) = Self::read_varint(&bytes[offset..])?;
                     offset += consumed;
-                    // 这里可以根据field_number存储值
+                    ProtoValue::Varint(value)
                 }
                 1 => {
-                    // 64-bit
-                    if offset + 8 > bytes.len() {
-                        break;
-                    }
+                    let data = bytes.get(offset..offset + 8).context("Truncated fixed64 field")?;
                     offset += 8;
+                    ProtoValue::Fixed64(u64::from_le_bytes(data.try_into().expect("8 bytes")))
                 }
                 2 => {
-                    // Length-delimited (string, bytes, embedded message)
                     let (length, consumed) = Self::read_varint(&bytes[offset..])?;
                     offset += consumed;
-
-                    if offset + length as usize > bytes.len() {
-                        break;
-                    }
-
-                    let data = &bytes[offset..offset + length as usize];
-                    offset += length as usize;
-
-                    // 尝试解析为字符串
-                    if let Ok(text) = String::from_utf8(data.to_vec()) {
-                        // 检查是否是文件路径
-                        if text.contains("FileStorage") {
-                            result.insert("file_path".to_string(), text);
-                        } else if text.starts_with("http://") || text.starts_with("https://") {
-                            result.insert("url".to_string(), text);
-                        } else if field_number == 2 {
-                            // 常见的文件路径字段
-                            result.insert("path".to_string(), text);
-                        }
-                    }
+                    let end = offset
+                        .checked_add(length as usize)
+                        .filter(|end| *end <= bytes.len())
+                        .context("Truncated length-delimited field")?;
+                    let data = &bytes[offset..end];
+                    offset = end;
+                    Self::decode_length_delimited(data, depth)
                 }
                 5 => {
-                    // 32-bit
-                    if offset + 4 > bytes.len() {
-                        break;
-                    }
+                    let data = bytes.get(offset..offset + 4).context("Truncated fixed32 field")?;
                     offset += 4;
+                    ProtoValue::Fixed32(u32::from_le_bytes(data.try_into().expect("4 bytes")))
                 }
-                _ => {
-                    // 未知类型，跳过
-                    break;
-                }
-            }
+                wire => return Err(anyhow::anyhow!("Unsupported wire type {}", wire).into()),
+            };
+            fields.push(ProtoField { number, value });
         }
 
-        Ok(result)
+        Ok(fields)
+    }
+
+    /// 可打印的UTF-8视为字符串，否则尝试按子消息解析
+    fn decode_length_delimited(data: &[u8], depth: usize) -> ProtoValue {
+        if let Ok(text) = std::str::from_utf8(data) {
+            if text.chars().all(|c| !c.is_control() || matches!(c, '\n' | '\r' | '\t')) {
+                return ProtoValue::String(text.to_string());
+            }
+        }
+        if !data.is_empty() && depth < MAX_DEPTH {
+            if let Ok(fields) = Self::decode_at_depth(data, depth + 1) {
+                return ProtoValue::Message {
+                    fields,
+                    raw: data.to_vec(),
+                };
+            }
+        }
+        ProtoValue::Bytes(data.to_vec())
     }
 
     /// 读取varint值
     fn read_varint(bytes: &[u8]) -> Result<(u64, usize)> {
         let mut value = 0u64;
-        let mut shift = 0;
-        let mut consumed = 0;
-
-        for &byte in bytes.iter().take(10) {
-            consumed += 1;
-            value |= ((byte & 0x7F) as u64) << shift;
 
+        for (i, &byte) in bytes.iter().take(10).enumerate() {
+            value |= ((byte & 0x7F) as u64) << (7 * i);
             if (byte & 0x80) == 0 {
-                break;
-            }
-
-            shift += 7;
-            if shift >= 64 {
-                return Err(anyhow::anyhow!("Varint too long").into());
+                return Ok((value, i + 1));
             }
         }
 
-        Ok((value, consumed))
+        Err(anyhow::anyhow!("Truncated or overlong varint").into())
     }
 
-    /// 回退解析方法（当protobuf解析失败时使用）
-    fn parse_fallback(bytes: &[u8]) -> Result<HashMap<String, String>> {
-        let mut result = HashMap::new();
+    /// 按BytesExtra的结构解析
+    pub fn decode_bytes_extra(bytes: &[u8]) -> Result<BytesExtra> {
+        BytesExtra::decode(bytes)
+            .map_err(|e| anyhow::anyhow!("Invalid BytesExtra: {}", e).into())
+    }
 
-        // 尝试将bytes转换为字符串
-        let text = String::from_utf8_lossy(bytes);
+    pub fn decode_room_data(bytes: &[u8]) -> Result<RoomData> {
+        RoomData::decode(bytes)
+            .map_err(|e| anyhow::anyhow!("Invalid RoomData: {}", e).into())
+    }
 
-        // 使用正则表达式提取路径和URL
-        use regex::Regex;
+    /// 按条目类型提取发送者、缩略图、源文件路径和msgsource
+    pub fn parse_bytes_extra_info(bytes: &[u8]) -> Result<BytesExtraInfo> {
+        let extra = Self::decode_bytes_extra(bytes)?;
+        let path_of = |item_type| extra.value_of(item_type).map(|p| p.replace('\\', "/"));
+        Ok(BytesExtraInfo {
+            sender_wxid: extra.value_of(BytesExtraType::Sender),
+            thumb_path: path_of(BytesExtraType::Thumb),
+            source_path: path_of(BytesExtraType::Source),
+            msg_source: extra.value_of(BytesExtraType::MsgSource),
+        })
+    }
 
-        // 提取FileStorage路径
-        if let Ok(re) = Regex::new(r"FileStorage[^\x00]*") {
-            for cap in re.find_iter(&text) {
-                let path = cap.as_str().trim_matches('\x00').trim();
-                if !path.is_empty() {
-                    result.insert("file_path".to_string(), path.to_string());
-                    break;
-                }
+    /// 深度优先遍历字段树中的所有字符串
+    fn visit_strings<'a>(fields: &'a [ProtoField], out: &mut Vec<&'a str>) {
+        for field in fields {
+            match &field.value {
+                ProtoValue::String(s) => out.push(s),
+                ProtoValue::Message { fields, .. } => Self::visit_strings(fields, out),
+                _ => {}
             }
         }
+    }
 
-        // 提取URL
-        if let Ok(re) = Regex::new(r"https?://[^\x00\s]+") {
-            for cap in re.find_iter(&text) {
-                let url = cap.as_str().trim_matches('\x00').trim();
-                if !url.is_empty() {
-                    result.insert("url".to_string(), url.to_string());
-                    break;
-                }
+    /// 未知结构时，在字段树中查找第一个满足条件的字符串
+    fn find_string(bytes: &[u8], predicate: impl Fn(&str) -> bool) -> Option<String> {
+        let fields = Self::decode_fields(bytes).ok()?;
+        let mut strings = Vec::new();
+        Self::visit_strings(&fields, &mut strings);
+        strings
+            .into_iter()
+            .map(|s| s.trim_matches('\0').trim())
+            .find(|s| predicate(s))
+            .map(|s| s.replace('\\', "/"))
+    }
+
+    /// 从protobuf解析中提取源文件路径（FileStorage下的路径）
+    pub fn extract_file_path(bytes: &[u8]) -> Option<String> {
+        if let Ok(info) = Self::parse_bytes_extra_info(bytes) {
+            if let Some(path) = info.source_path {
+                return Some(path);
             }
         }
-
-        Ok(result)
+        Self::find_string(bytes, |s| s.contains("FileStorage"))
     }
 
     /// 从protobuf解析中提取图片路径
     pub fn extract_image_path(bytes: &[u8]) -> Option<String> {
-        if let Ok(parsed) = Self::parse_bytes_extra(bytes) {
-            // 优先使用file_path
-            if let Some(path) = parsed.get("file_path") {
-                if path.contains("Image") {
-                    return Some(path.clone());
-                }
-            }
-            // 其次使用path
-            if let Some(path) = parsed.get("path") {
-                if path.contains("Image") {
-                    return Some(path.clone());
-                }
+        if let Ok(info) = Self::parse_bytes_extra_info(bytes) {
+            if let Some(path) = info.source_path.or(info.thumb_path) {
+                return Some(path);
             }
         }
-        None
+        Self::find_string(bytes, |s| s.contains("FileStorage") && s.contains("Image"))
     }
 
     /// 从protobuf解析中提取视频路径
     pub fn extract_video_path(bytes: &[u8]) -> Option<String> {
-        if let Ok(parsed) = Self::parse_bytes_extra(bytes) {
-            if let Some(path) = parsed.get("file_path") {
-                if path.contains("mp4") || path.contains("Video") {
-                    return Some(path.clone());
-                }
-            }
-            if let Some(path) = parsed.get("path") {
-                if path.contains("mp4") || path.contains("Video") {
-                    return Some(path.clone());
-                }
+        if let Ok(info) = Self::parse_bytes_extra_info(bytes) {
+            if let Some(path) = info.source_path.filter(|p| p.contains("mp4") || p.contains("Video")) {
+                return Some(path);
             }
         }
-        None
+        Self::find_string(bytes, |s| s.contains("FileStorage") && (s.contains("mp4") || s.contains("Video")))
     }
 
     /// 从protobuf解析中提取文件URL
     pub fn extract_file_url(bytes: &[u8]) -> Option<String> {
-        if let Ok(parsed) = Self::parse_bytes_extra(bytes) {
-            parsed.get("url").cloned()
-        } else {
-            None
+        Self::find_string(bytes, |s| s.starts_with("http://") || s.starts_with("https://"))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    fn varint(mut value: u64, out: &mut Vec<u8>) {
+        while value >= 0x80 {
+            out.push((value as u8) | 0x80);
+            value >>= 7;
+        }
+        out.push(value as u8);
+    }
+
+    fn bytes_field(field: u64, data: &[u8], out: &mut Vec<u8>) {
+        varint(field << 3 | 2, out);
+        varint(data.len() as u64, out);
+        out.extend_from_slice(data);
+    }
+
+    /// 按微信的结构构造BytesExtra：{1: {1: 1, 2: 0}, 3: [{1: 类型, 2: 值}, ...]}
+    pub(crate) fn build_bytes_extra(items: &[(u64, &str)]) -> Vec<u8> {
+        let mut out = Vec::new();
+        bytes_field(1, &[0x08, 0x01, 0x10, 0x00], &mut out);
+        for (item_type, value) in items {
+            let mut item = Vec::new();
+            varint(1 << 3, &mut item);
+            varint(*item_type, &mut item);
+            bytes_field(2, value.as_bytes(), &mut item);
+            bytes_field(3, &item, &mut out);
         }
+        out
+    }
+
+    /// 按微信的结构构造RoomData：{1: [{1: wxid, 2: 群昵称, 3: 状态}, ...], 2: 0}
+    pub(crate) fn build_room_data(members: &[(&str, &str)]) -> Vec<u8> {
+        let mut out = Vec::new();
+        for (wxid, display_name) in members {
+            let mut member = Vec::new();
+            bytes_field(1, wxid.as_bytes(), &mut member);
+            if !display_name.is_empty() {
+                bytes_field(2, display_name.as_bytes(), &mut member);
+            }
+            varint(3 << 3, &mut member);
+            varint(0, &mut member);
+            bytes_field(1, &member, &mut out);
+        }
+        varint(2 << 3, &mut out);
+        varint(0, &mut out);
+        out
+    }
+
+    #[test]
+    fn test_parse_bytes_extra_info() {
+        let bytes = build_bytes_extra(&[
+            (1, "wxid_sender"),
+            (3, "wxid_me\\FileStorage\\MsgAttach\\abc\\Thumb\\2023-01\\a_t.dat"),
+            (4, "wxid_me\\FileStorage\\MsgAttach\\abc\\Image\\2023-01\\a.dat"),
+            (7, "<msgsource><silence>1</silence></msgsource>"),
+            (12, "unknown"),
+        ]);
+
+        let extra = ProtobufParser::decode_bytes_extra(&bytes).unwrap();
+        assert_eq!(extra.items.len(), 5);
+        assert_eq!(extra.items[0].item_type(), BytesExtraType::Sender);
+        assert_eq!(extra.items[4].item_type(), BytesExtraType::Unknown);
+
+        let info = ProtobufParser::parse_bytes_extra_info(&bytes).unwrap();
+        assert_eq!(info.sender_wxid.as_deref(), Some("wxid_sender"));
+        assert_eq!(
+            info.thumb_path.as_deref(),
+            Some("wxid_me/FileStorage/MsgAttach/abc/Thumb/2023-01/a_t.dat")
+        );
+        assert!(info.source_path.unwrap().ends_with("Image/2023-01/a.dat"));
+        assert!(info.msg_source.unwrap().starts_with("<msgsource>"));
+
+        // 截断的数据返回错误而不是越界
+        assert!(ProtobufParser::parse_bytes_extra_info(&bytes[..bytes.len() - 3]).is_err());
+    }
+
+    #[test]
+    fn test_decode_field_tree() {
+        let mut bytes = Vec::new();
+        // 字段号大于15时tag占两个字节
+        varint(100 << 3, &mut bytes);
+        varint(300, &mut bytes);
+        let mut nested = Vec::new();
+        bytes_field(2, b"https://example.com/a.png", &mut nested);
+        bytes_field(20, &nested, &mut bytes);
+        // packed repeated varint
+        let mut packed = Vec::new();
+        for v in [1, 150, 3] {
+            varint(v, &mut packed);
+        }
+        bytes_field(5, &packed, &mut bytes);
+        varint(6 << 3 | 5, &mut bytes);
+        bytes.extend_from_slice(&7u32.to_le_bytes());
+
+        let fields = ProtobufParser::decode_fields(&bytes).unwrap();
+        assert_eq!(fields[0], ProtoField { number: 100, value: ProtoValue::Varint(300) });
+        let nested = find_field(&fields, 20).and_then(ProtoValue::as_message).unwrap();
+        assert_eq!(find_field(nested, 2).and_then(ProtoValue::as_str), Some("https://example.com/a.png"));
+        assert_eq!(find_field(&fields, 5).unwrap().packed_varints(), Some(vec![1, 150, 3]));
+        assert_eq!(find_field(&fields, 6), Some(&ProtoValue::Fixed32(7)));
+
+        assert_eq!(
+            ProtobufParser::extract_file_url(&bytes).as_deref(),
+            Some("https://example.com/a.png")
+        );
+        assert!(ProtobufParser::decode_fields(&[0x08]).is_err());
     }
 }
@@ -0,0 +1,534 @@
+use crate::utils::{AppError, Result};
+use chrono::{Local, NaiveDate, TimeZone};
+use rusqlite::types::Value;
+
+/// `type:`可用的消息类型名及对应的(Type, SubType)
+const MSG_TYPES: &[(&str, i32, Option<i32>)] = &[
+    ("text", 1, None),
+    ("image", 3, None),
+    ("voice", 34, None),
+    ("card", 42, None),
+    ("video", 43, None),
+    ("emoji", 47, None),
+    ("location", 48, None),
+    ("app", 49, None),
+    ("link", 49, Some(5)),
+    ("file", 49, Some(6)),
+    ("forward", 49, Some(19)),
+    ("miniprogram", 49, Some(33)),
+    ("quote", 49, Some(57)),
+    ("transfer", 49, Some(2000)),
+    ("redpacket", 49, Some(2001)),
+    ("call", 50, None),
+    ("system", 10000, None),
+];
+
+/// `has:`可用的内容特征
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HasFilter {
+    /// 文本中带网址或分享链接
+    Link,
+    /// 图片、语音、视频或文件
+    Media,
+    File,
+}
+
+/// `is:`可用的消息状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IsFilter {
+    Sent,
+    Received,
+    /// 群聊消息
+    Group,
+    /// 单聊消息
+    Private,
+}
+
+/// 查询中的单个条件
+#[derive(Debug, Clone, PartialEq)]
+pub enum Term {
+    /// 关键词或`"短语"`，匹配消息内容
+    Text { text: String, quoted: bool },
+    /// `from:` 发送者，`me`表示自己发送
+    From(String),
+    /// `in:` 会话（好友wxid或群聊id）
+    In(String),
+    /// `type:` 消息类型
+    Type { msg_type: i32, sub_type: Option<i32> },
+    /// `after:` 该日期（含）之后，本地时间的时间戳
+    After(i64),
+    /// `before:` 该日期之前，本地时间的时间戳
+    Before(i64),
+    Has(HasFilter),
+    Is(IsFilter),
+}
+
+/// 查询语法树
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueryNode {
+    And(Vec<QueryNode>),
+    Or(Vec<QueryNode>),
+    Not(Box<QueryNode>),
+    Term(Term),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    LParen,
+    RParen,
+    Or,
+    Not,
+    Word(String),
+    Phrase(String),
+    Field { key: String, value: String, value_pos: usize },
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    pos: usize,
+}
+
+fn syntax_error(pos: usize, message: impl std::fmt::Display) -> AppError {
+    AppError::ValidationFailed(format!("{} at position {}", message, pos))
+}
+
+const FIELDS: &[&str] = &["from", "in", "type", "after", "before", "has", "is"];
+
+/// 读取引号中的短语，`start`指向左引号，返回短语和右引号之后的位置
+fn read_phrase(chars: &[char], start: usize) -> Result<(String, usize)> {
+    let end = chars[start + 1..]
+        .iter()
+        .position(|&c| c == '"')
+        .map(|i| start + 1 + i)
+        .ok_or_else(|| syntax_error(start, "Unterminated quote"))?;
+    Ok((chars[start + 1..end].iter().collect(), end + 1))
+}
+
+fn is_word_char(c: char) -> bool {
+    !c.is_whitespace() && !matches!(c, '(' | ')' | '"')
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+
+    while pos < chars.len() {
+        let c = chars[pos];
+        let start = pos;
+        let kind = match c {
+            c if c.is_whitespace() => {
+                pos += 1;
+                continue;
+            }
+            '(' => {
+                pos += 1;
+                TokenKind::LParen
+            }
+            ')' => {
+                pos += 1;
+                TokenKind::RParen
+            }
+            '-' if chars.get(pos + 1).is_some_and(|&c| !c.is_whitespace()) => {
+                pos += 1;
+                TokenKind::Not
+            }
+            '"' => {
+                let (phrase, next) = read_phrase(&chars, start)?;
+                pos = next;
+                TokenKind::Phrase(phrase)
+            }
+            _ => {
+                while pos < chars.len() && is_word_char(chars[pos]) {
+                    pos += 1;
+                }
+                let word: String = chars[start..pos].iter().collect();
+                let field = word
+                    .split_once(':')
+                    .map(|(key, _)| key.to_ascii_lowercase())
+                    .filter(|key| FIELDS.contains(&key.as_str()));
+
+                match (word.as_str(), field) {
+                    (_, Some(key)) => {
+                        let value_pos = start + key.chars().count() + 1;
+                        let value = if pos == value_pos && chars.get(pos) == Some(&'"') {
+                            let (phrase, next) = read_phrase(&chars, pos)?;
+                            pos = next;
+                            phrase
+                        } else {
+                            chars[value_pos..pos].iter().collect()
+                        };
+                        if value.trim().is_empty() {
+                            return Err(syntax_error(value_pos, format!("Missing value for '{}:'", key)));
+                        }
+                        TokenKind::Field { key, value, value_pos }
+                    }
+                    ("OR" | "|", None) => TokenKind::Or,
+                    ("AND", None) => continue,
+                    ("NOT", None) => TokenKind::Not,
+                    (_, None) => TokenKind::Word(word),
+                }
+            }
+        };
+        tokens.push(Token { kind, pos: start });
+    }
+    Ok(tokens)
+}
+
+fn parse_date(value: &str, pos: usize) -> Result<i64> {
+    let date = NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map_err(|_| syntax_error(pos, format!("Invalid date '{}', expected YYYY-MM-DD", value)))?;
+    let midnight = date.and_hms_opt(0, 0, 0).expect("midnight is valid");
+    Local
+        .from_local_datetime(&midnight)
+        .earliest()
+        .map(|t| t.timestamp())
+        .ok_or_else(|| syntax_error(pos, format!("Invalid local date '{}'", value)))
+}
+
+fn parse_field(key: &str, value: &str, pos: usize) -> Result<Term> {
+    let lower = value.to_ascii_lowercase();
+    let term = match key {
+        "from" => Term::From(value.to_string()),
+        "in" => Term::In(value.to_string()),
+        "type" => {
+            let (msg_type, sub_type) = MSG_TYPES
+                .iter()
+                .find(|(name, _, _)| *name == lower)
+                .map(|(_, t, s)| (*t, *s))
+                .or_else(|| lower.parse().ok().map(|t| (t, None)))
+                .ok_or_else(|| syntax_error(pos, format!("Unknown message type '{}'", value)))?;
+            Term::Type { msg_type, sub_type }
+        }
+        "after" => Term::After(parse_date(value, pos)?),
+        "before" => Term::Before(parse_date(value, pos)?),
+        "has" => Term::Has(match lower.as_str() {
+            "link" => HasFilter::Link,
+            "media" => HasFilter::Media,
+            "file" => HasFilter::File,
+            _ => return Err(syntax_error(pos, format!("Unknown has: value '{}'", value))),
+        }),
+        "is" => Term::Is(match lower.as_str() {
+            "sent" => IsFilter::Sent,
+            "received" => IsFilter::Received,
+            "group" => IsFilter::Group,
+            "private" => IsFilter::Private,
+            _ => return Err(syntax_error(pos, format!("Unknown is: value '{}'", value))),
+        }),
+        _ => unreachable!("tokenizer only emits known fields"),
+    };
+    Ok(term)
+}
+
+/// 递归下降解析：and := or+；or := unary ("OR" unary)*；unary := ("-" | "NOT") unary | primary
+/// 与Gmail相同，OR比隐式AND结合得更紧：`from:a cat OR dog`即`from:a (cat OR dog)`
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&TokenKind> {
+        self.tokens.get(self.pos).map(|t| &t.kind)
+    }
+
+    /// 当前记号的位置，已到末尾时为输入长度
+    fn position(&self) -> usize {
+        self.tokens.get(self.pos).map(|t| t.pos).unwrap_or(self.end)
+    }
+
+    fn parse_and(&mut self) -> Result<QueryNode> {
+        let mut nodes = Vec::new();
+        while let Some(kind) = self.peek() {
+            if matches!(kind, TokenKind::Or | TokenKind::RParen) {
+                break;
+            }
+            nodes.push(self.parse_or()?);
+        }
+        match nodes.len() {
+            0 => Err(syntax_error(self.position(), "Expected a search term")),
+            1 => Ok(nodes.remove(0)),
+            _ => Ok(QueryNode::And(nodes)),
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<QueryNode> {
+        let mut nodes = vec![self.parse_unary()?];
+        while self.peek() == Some(&TokenKind::Or) {
+            self.pos += 1;
+            nodes.push(self.parse_unary()?);
+        }
+        Ok(if nodes.len() == 1 { nodes.remove(0) } else { QueryNode::Or(nodes) })
+    }
+
+    fn parse_unary(&mut self) -> Result<QueryNode> {
+        if self.peek() == Some(&TokenKind::Not) {
+            self.pos += 1;
+            return Ok(QueryNode::Not(Box::new(self.parse_unary()?)));
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<QueryNode> {
+        let pos = self.position();
+        let Some(token) = self.tokens.get(self.pos).cloned() else {
+            return Err(syntax_error(pos, "Expected a search term"));
+        };
+        self.pos += 1;
+
+        match token.kind {
+            TokenKind::LParen => {
+                let node = self.parse_and()?;
+                if self.peek() != Some(&TokenKind::RParen) {
+                    return Err(syntax_error(self.position(), "Missing ')'"));
+                }
+                self.pos += 1;
+                Ok(node)
+            }
+            TokenKind::Word(text) => Ok(QueryNode::Term(Term::Text { text, quoted: false })),
+            TokenKind::Phrase(text) => Ok(QueryNode::Term(Term::Text { text, quoted: true })),
+            TokenKind::Field { key, value, value_pos } => {
+                Ok(QueryNode::Term(parse_field(&key, &value, value_pos)?))
+            }
+            TokenKind::RParen => Err(syntax_error(pos, "Unexpected ')'")),
+            TokenKind::Or | TokenKind::Not => Err(syntax_error(pos, "Expected a search term")),
+        }
+    }
+}
+
+/// 编译后的SQL条件，参数与占位符一一对应
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompiledQuery {
+    pub where_clause: String,
+    pub params: Vec<Value>,
+}
+
+impl CompiledQuery {
+    pub fn params(&self) -> Vec<&dyn rusqlite::ToSql> {
+        self.params.iter().map(|v| v as &dyn rusqlite::ToSql).collect()
+    }
+}
+
+/// 转义LIKE中的通配符，配合`ESCAPE '\'`使用
+fn like_pattern(text: &str) -> String {
+    let escaped = text
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+/// 消息搜索查询，如`from:wxid_abc type:image after:2023-01-01 "短语" -排除词 has:link is:sent`
+/// 空格分隔的条件之间为AND，支持`OR`（优先于AND）、`-`/`NOT`和括号
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchQuery {
+    pub root: QueryNode,
+}
+
+impl SearchQuery {
+    /// 解析查询，语法错误返回`ValidationFailed`，位置为字符下标
+    pub fn parse(input: &str) -> Result<Self> {
+        let tokens = tokenize(input)?;
+        let mut parser = Parser {
+            tokens,
+            pos: 0,
+            end: input.chars().count(),
+        };
+        let root = parser.parse_and()?;
+        if parser.pos < parser.tokens.len() {
+            return Err(syntax_error(parser.position(), "Unexpected ')'"));
+        }
+        Ok(Self { root })
+    }
+
+    /// 是否只包含关键词（可以交给全文索引处理）
+    pub fn is_text_only(&self) -> bool {
+        fn visit(node: &QueryNode) -> bool {
+            match node {
+                QueryNode::And(nodes) | QueryNode::Or(nodes) => nodes.iter().all(visit),
+                QueryNode::Not(node) => visit(node),
+                QueryNode::Term(term) => matches!(term, Term::Text { .. }),
+            }
+        }
+        visit(&self.root)
+    }
+
+    /// 用于高亮的关键词（不含排除的词）
+    pub fn highlight_terms(&self) -> Vec<String> {
+        fn visit(node: &QueryNode, terms: &mut Vec<String>) {
+            match node {
+                QueryNode::And(nodes) | QueryNode::Or(nodes) => {
+                    nodes.iter().for_each(|n| visit(n, terms))
+                }
+                QueryNode::Not(_) => {}
+                QueryNode::Term(Term::Text { text, .. }) => terms.push(text.clone()),
+                QueryNode::Term(_) => {}
+            }
+        }
+        let mut terms = Vec::new();
+        visit(&self.root, &mut terms);
+        terms
+    }
+
+    /// 编译为MSG表上的参数化WHERE条件
+    pub fn to_sql(&self) -> CompiledQuery {
+        let mut params = Vec::new();
+        let where_clause = Self::compile(&self.root, &mut params);
+        CompiledQuery { where_clause, params }
+    }
+
+    fn compile(node: &QueryNode, params: &mut Vec<Value>) -> String {
+        match node {
+            QueryNode::And(nodes) => {
+                let parts: Vec<String> = nodes.iter().map(|n| Self::compile(n, params)).collect();
+                format!("({})", parts.join(" AND "))
+            }
+            QueryNode::Or(nodes) => {
+                let parts: Vec<String> = nodes.iter().map(|n| Self::compile(n, params)).collect();
+                format!("({})", parts.join(" OR "))
+            }
+            QueryNode::Not(node) => format!("NOT {}", Self::compile(node, params)),
+            QueryNode::Term(term) => Self::compile_term(term, params),
+        }
+    }
+
+    fn compile_term(term: &Term, params: &mut Vec<Value>) -> String {
+        match term {
+            Term::Text { text, .. } => {
+                let pattern = like_pattern(text);
+                params.push(Value::Text(pattern.clone()));
+                params.push(Value::Text(pattern));
+                // 列为NULL时LIKE的结果也是NULL，取反后会漏掉这些消息
+                "(IFNULL(StrContent, '') LIKE ? ESCAPE '\\' OR IFNULL(DisplayContent, '') LIKE ? ESCAPE '\\')".to_string()
+            }
+            Term::From(wxid) if wxid.eq_ignore_ascii_case("me") => "(IsSender = 1)".to_string(),
+            Term::From(wxid) => {
+                // 群聊中发送者wxid保存在BytesExtra中；instr只用于快速排除，
+                // @提及和文件路径中也可能出现该wxid，需解析出发送者后精确比较；
+                // BytesExtra为NULL或无法解析时按不匹配处理，取反时不会漏掉
+                params.push(Value::Text(wxid.clone()));
+                params.push(Value::Blob(wxid.as_bytes().to_vec()));
+                params.push(Value::Text(wxid.clone()));
+                "(IsSender = 0 AND (StrTalker = ? OR (StrTalker LIKE '%@chatroom' AND instr(IFNULL(BytesExtra, X''), ?) > 0 AND IFNULL(bytes_extra_sender(BytesExtra), '') = ?)))"
+                    .to_string()
+            }
+            Term::In(talker) => {
+                params.push(Value::Text(talker.clone()));
+                "(StrTalker = ?)".to_string()
+            }
+            Term::Type { msg_type, sub_type } => {
+                params.push(Value::Integer(*msg_type as i64));
+                match sub_type {
+                    Some(sub_type) => {
+                        params.push(Value::Integer(*sub_type as i64));
+                        "(Type = ? AND SubType = ?)".to_string()
+                    }
+                    None => "(Type = ?)".to_string(),
+                }
+            }
+            Term::After(ts) => {
+                params.push(Value::Integer(*ts));
+                "(CreateTime >= ?)".to_string()
+            }
+            Term::Before(ts) => {
+                params.push(Value::Integer(*ts));
+                "(CreateTime < ?)".to_string()
+            }
+            Term::Has(HasFilter::Link) => {
+                "(IFNULL(StrContent, '') LIKE '%http://%' OR IFNULL(StrContent, '') LIKE '%https://%' OR (Type = 49 AND SubType IN (4, 5)))"
+                    .to_string()
+            }
+            Term::Has(HasFilter::Media) => {
+                "(Type IN (3, 34, 43, 62) OR (Type = 49 AND SubType = 6))".to_string()
+            }
+            Term::Has(HasFilter::File) => "(Type = 49 AND SubType IN (0, 6, 74))".to_string(),
+            Term::Is(IsFilter::Sent) => "(IsSender = 1)".to_string(),
+            Term::Is(IsFilter::Received) => "(IsSender = 0)".to_string(),
+            Term::Is(IsFilter::Group) => "(StrTalker LIKE '%@chatroom')".to_string(),
+            Term::Is(IsFilter::Private) => "(StrTalker NOT LIKE '%@chatroom')".to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(s: &str) -> QueryNode {
+        QueryNode::Term(Term::Text { text: s.to_string(), quoted: false })
+    }
+
+    #[test]
+    fn test_parse_ast() {
+        let query = SearchQuery::parse("from:wxid_abc type:image \"exact phrase\" -excluded (a OR b) is:sent").unwrap();
+        assert_eq!(
+            query.root,
+            QueryNode::And(vec![
+                QueryNode::Term(Term::From("wxid_abc".to_string())),
+                QueryNode::Term(Term::Type { msg_type: 3, sub_type: None }),
+                QueryNode::Term(Term::Text { text: "exact phrase".to_string(), quoted: true }),
+                QueryNode::Not(Box::new(text("excluded"))),
+                QueryNode::Or(vec![text("a"), text("b")]),
+                QueryNode::Term(Term::Is(IsFilter::Sent)),
+            ])
+        );
+        assert!(!query.is_text_only());
+        assert_eq!(query.highlight_terms(), vec!["exact phrase", "a", "b"]);
+
+        // OR比隐式AND结合得更紧，过滤条件作用于整个OR分组
+        let query = SearchQuery::parse("from:alice cat OR dog -bird").unwrap();
+        assert_eq!(
+            query.root,
+            QueryNode::And(vec![
+                QueryNode::Term(Term::From("alice".to_string())),
+                QueryNode::Or(vec![text("cat"), text("dog")]),
+                QueryNode::Not(Box::new(text("bird"))),
+            ])
+        );
+
+        // 未知的字段按普通关键词处理
+        let query = SearchQuery::parse("https://example.com in:\"123@chatroom\"").unwrap();
+        assert_eq!(
+            query.root,
+            QueryNode::And(vec![
+                text("https://example.com"),
+                QueryNode::Term(Term::In("123@chatroom".to_string())),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_compile_sql() {
+        let query = SearchQuery::parse("in:room@chatroom type:link -50%").unwrap();
+        let compiled = query.to_sql();
+        assert_eq!(
+            compiled.where_clause,
+            "((StrTalker = ?) AND (Type = ? AND SubType = ?) AND NOT (IFNULL(StrContent, '') LIKE ? ESCAPE '\\' OR IFNULL(DisplayContent, '') LIKE ? ESCAPE '\\'))"
+        );
+        assert_eq!(compiled.params[0], Value::Text("room@chatroom".to_string()));
+        assert_eq!(compiled.params[2], Value::Integer(5));
+        assert_eq!(compiled.params[3], Value::Text("%50\\%%".to_string()));
+        assert_eq!(compiled.params().len(), 5);
+
+        let after = SearchQuery::parse("after:2023-01-01").unwrap().to_sql();
+        let expected = Local.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap().timestamp();
+        assert_eq!(after.params, vec![Value::Integer(expected)]);
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        let position = |input: &str| match SearchQuery::parse(input) {
+            Err(AppError::ValidationFailed(msg)) => msg,
+            other => panic!("expected error for {:?}, got {:?}", input, other),
+        };
+        assert!(position("hello \"world").ends_with("position 6"));
+        assert!(position("type:sticker").contains("Unknown message type 'sticker' at position 5"));
+        assert!(position("after:2023-13-01").ends_with("position 6"));
+        assert!(position("(a OR b").ends_with("position 7"));
+        assert!(position("a OR").ends_with("position 4"));
+        assert!(position("a ) b").contains("Unexpected ')' at position 2"));
+        assert!(position("from: x").contains("Missing value for 'from:' at position 5"));
+    }
+}
@@ -1,4 +1,5 @@
 use chrono::{DateTime, Local, TimeZone};
+use crate::db::msg_content::{ForwardedItem, ReferMsg, SystemEvent, WcPayInfo};
 use serde::{Deserialize, Serialize};
 
 /// 消息类型映射
@@ -37,6 +38,7 @@ pub fn get_message_type_name(msg_type: i32, sub_type: i32) -> &'static str {
         (49, 87) => "群公告",
         (49, 88) => "视频号直播或直播回放等",
         (49, 2000) => "转账",
+        (49, 2001) => "红包",
         (49, 2003) => "赠送红包封面",
         (50, 0) => "语音通话",
         (62, 0) => "小视频",
@@ -87,18 +89,91 @@ pub struct Message {
     pub is_sender: i32,
     pub talker: String,
     pub str_talker: String,
+    /// 实际发送者：群聊为BytesExtra中的wxid，单聊为对方wxid，自己发送时为空
+    #[serde(default)]
+    pub sender_wxid: String,
     pub content: String,
     pub display_content: String,
     pub src: String,
     pub extra: serde_json::Value,
+    /// 引用回复（49,57）所引用的消息
+    #[serde(default)]
+    pub reply_to: Option<ReplyTo>,
+    /// 系统消息（10000/10002）中的撤回、拍一拍、进退群事件
+    #[serde(default)]
+    pub event: Option<SystemEvent>,
+}
+
+/// 引用回复所引用的消息
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ReplyTo {
+    pub msg_svr_id: i64,
+    pub msg_type: i32,
+    pub sender_wxid: String,
+    pub display_name: String,
+    pub content: String,
+    /// 在同一数据库中找到被引用消息时为其localId，否则为空
+    pub local_id: Option<i64>,
+    pub create_time: i64,
+}
+
+impl From<&ReferMsg> for ReplyTo {
+    fn from(refer: &ReferMsg) -> Self {
+        Self {
+            msg_svr_id: refer.svrid,
+            msg_type: refer.msg_type,
+            sender_wxid: refer.sender_wxid().to_string(),
+            display_name: refer.display_name.clone(),
+            content: refer.summary(),
+            local_id: None,
+            create_time: refer.create_time,
+        }
+    }
+}
+
+/// 是否为群聊会话
+pub fn is_chatroom(talker: &str) -> bool {
+    talker.ends_with("@chatroom")
 }
 
 impl Message {
+    /// 用于显示名称的wxid：自己发送时为空，否则为实际发送者
+    pub fn display_wxid(&self) -> Option<&str> {
+        if self.is_sender == 1 {
+            return None;
+        }
+        if !self.sender_wxid.is_empty() {
+            Some(&self.sender_wxid)
+        } else {
+            Some(&self.str_talker)
+        }
+    }
+
+    /// 合并转发的聊天记录，取自`extra.forwarded`
+    pub fn forwarded(&self) -> Vec<ForwardedItem> {
+        self.extra
+            .get("forwarded")
+            .and_then(|v| serde_json::from_value(v.clone()).ok())
+            .unwrap_or_default()
+    }
+
+    /// 转账/红包信息，取自`extra.pay`
+    pub fn pay_info(&self) -> Option<WcPayInfo> {
+        serde_json::from_value(self.extra.get("pay")?.clone()).ok()
+    }
+
     pub fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
         let msg_type: i32 = row.get(3)?;
         let sub_type: i32 = row.get(4)?;
         let create_time: i64 = row.get(5)?;
-        
+        let is_sender: i32 = row.get(6)?;
+        let str_talker: String = row.get(8)?;
+        let sender_wxid = if is_sender == 0 && !is_chatroom(&str_talker) {
+            str_talker.clone()
+        } else {
+            String::new()
+        };
+
         Ok(Self {
             id: row.get(0)?,
             local_id: row.get(1)?,
@@ -108,13 +183,16 @@ impl Message {
             type_name: get_message_type_name(msg_type, sub_type).to_string(),
             create_time,
             create_time_str: timestamp_to_string(create_time),
-            is_sender: row.get(6)?,
+            is_sender,
             talker: row.get(7)?,
-            str_talker: row.get(8)?,
+            str_talker,
+            sender_wxid,
             content: row.get(9)?,
             display_content: row.get(10)?,
             src: String::new(),
             extra: serde_json::json!({}),
+            reply_to: None,
+            event: None,
         })
     }
 }
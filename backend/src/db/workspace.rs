@@ -0,0 +1,532 @@
+use crate::core::discovery::{classify, discover, AccountManifest, DbRole, DiscoveredDb};
+use crate::db::chatroom::ChatroomHandler;
+use crate::db::contact::ContactHandler;
+use crate::db::favorite::FavoriteHandler;
+use crate::db::head_image::HeadImageHandler;
+use crate::db::media::MediaHandler;
+use crate::db::msg::MsgHandler;
+use crate::db::sns::SnsHandler;
+use crate::utils::{validation, AppError, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// 账号目录下全文索引的文件名
+const MSG_INDEX_FILE: &str = "msg_index.db";
+/// 通过`merge_path`隐式注册的工作区上限，超出时注销最早注册的
+const MAX_IMPLICIT_WORKSPACES: usize = 8;
+
+/// 已注册的工作区，进程内共享
+static WORKSPACES: LazyLock<RwLock<HashMap<String, Arc<Workspace>>>> =
+    LazyLock::new(|| RwLock::new(HashMap::new()));
+
+/// 工作区概要信息
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkspaceInfo {
+    pub id: String,
+    pub root: PathBuf,
+    /// 合并后的消息库，为空时统一查询各MSG分片
+    pub merged_msg: Option<PathBuf>,
+    pub created_at: i64,
+    pub databases: Vec<DiscoveredDb>,
+    /// 由请求中的`merge_path`隐式注册：不建立全文索引，数量超过上限时被注销
+    #[serde(default)]
+    pub implicit: bool,
+}
+
+/// 一个账号的已解密数据库，各数据库句柄在注册时打开并在请求间复用
+pub struct Workspace {
+    info: WorkspaceInfo,
+    msg: Option<MsgHandler>,
+    contact: Option<ContactHandler>,
+    chatroom: Option<ChatroomHandler>,
+    head_image: Option<HeadImageHandler>,
+    media: Vec<MediaHandler>,
+    favorite: Option<FavoriteHandler>,
+    sns: Option<SnsHandler>,
+}
+
+impl Workspace {
+    /// 打开已解密的账号目录（`decrypt_account`的输出）
+    /// 目录下有清单时只使用解密成功的数据库，否则按文件名识别
+    fn open_dir(id: String, root: &Path, merged_msg: Option<&Path>) -> Result<Self> {
+        let databases = match AccountManifest::load(root) {
+            Ok(manifest) => manifest
+                .databases
+                .into_iter()
+                .filter(|e| e.result.is_success())
+                .map(|e| {
+                    let path = root.join(&e.relative_path);
+                    let size = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+                    DiscoveredDb {
+                        role: e.role,
+                        shard: e.shard,
+                        path,
+                        relative_path: e.relative_path,
+                        size,
+                    }
+                })
+                .filter(|db| db.path.is_file())
+                .collect(),
+            Err(_) => discover(root)?,
+        };
+
+        let first = |role: DbRole| databases.iter().find(|db| db.role == role);
+        let all = |role: DbRole| databases.iter().filter(move |db| db.role == role);
+
+        let msg_paths: Vec<&Path> = match merged_msg {
+            Some(path) => {
+                if !path.is_file() {
+                    return Err(AppError::NotFound(format!("Database not found: {:?}", path)));
+                }
+                vec![path]
+            }
+            None => all(DbRole::Message).map(|db| db.path.as_path()).collect(),
+        };
+        let msg = if msg_paths.is_empty() {
+            None
+        } else {
+            Some(open_msg(&msg_paths, Some(&index_path(root, merged_msg)))?)
+        };
+        let media = all(DbRole::Media)
+            .map(|db| MediaHandler::new(&path_str(&db.path)))
+            .collect::<Result<_>>()?;
+        let contact = first(DbRole::Contact)
+            .map(|db| ContactHandler::new(&path_str(&db.path)))
+            .transpose()?;
+        let chatroom = first(DbRole::Contact)
+            .map(|db| ChatroomHandler::new(&path_str(&db.path)))
+            .transpose()?;
+        let head_image = first(DbRole::Misc)
+            .map(|db| HeadImageHandler::new(&path_str(&db.path)))
+            .transpose()?;
+        let favorite = first(DbRole::Favorite)
+            .map(|db| FavoriteHandler::new(&path_str(&db.path)))
+            .transpose()?;
+        let sns = first(DbRole::Sns)
+            .map(|db| SnsHandler::new(&path_str(&db.path)))
+            .transpose()?;
+
+        Ok(Self {
+            info: WorkspaceInfo {
+                id,
+                root: root.to_path_buf(),
+                merged_msg: merged_msg.map(Path::to_path_buf),
+                created_at: chrono::Local::now().timestamp(),
+                databases,
+                implicit: false,
+            },
+            msg,
+            contact,
+            chatroom,
+            head_image,
+            media,
+            favorite,
+            sns,
+        })
+    }
+
+    /// 打开单个数据库文件（兼容直接传`merge_path`的旧接口）
+    /// 所有句柄都指向该文件，联系人和群聊优先使用同级或上两级目录中的MicroMsg.db，
+    /// 头像只使用同样位置的Misc.db；隐式注册时不在文件旁创建全文索引
+    fn open_file(id: String, path: &Path, implicit: bool) -> Result<Self> {
+        let contact_path = sibling_db(path, "MicroMsg.db").unwrap_or_else(|| path.to_path_buf());
+        let head_image = sibling_db(path, "Misc.db")
+            .map(|p| HeadImageHandler::new(&path_str(&p)))
+            .transpose()?;
+
+        let file_name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let (role, shard) = classify(&file_name);
+        let size = fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+        let db_path = path_str(path);
+        let index = (!implicit).then(|| index_path(path, Some(path)));
+
+        Ok(Self {
+            info: WorkspaceInfo {
+                id,
+                root: path.to_path_buf(),
+                merged_msg: Some(path.to_path_buf()),
+                created_at: chrono::Local::now().timestamp(),
+                databases: vec![DiscoveredDb {
+                    role,
+                    shard,
+                    path: path.to_path_buf(),
+                    relative_path: PathBuf::from(file_name),
+                    size,
+                }],
+                implicit,
+            },
+            msg: Some(open_msg(&[path], index.as_deref())?),
+            contact: Some(ContactHandler::new(&path_str(&contact_path))?),
+            chatroom: Some(ChatroomHandler::new(&path_str(&contact_path))?),
+            head_image,
+            media: vec![MediaHandler::new(&db_path)?],
+            favorite: Some(FavoriteHandler::new(&db_path)?),
+            sns: Some(SnsHandler::new(&db_path)?),
+        })
+    }
+
+    pub fn id(&self) -> &str {
+        &self.info.id
+    }
+
+    pub fn info(&self) -> &WorkspaceInfo {
+        &self.info
+    }
+
+    /// 消息库；未指定合并库时统一查询所有MSG分片
+    pub fn msg(&self) -> Result<&MsgHandler> {
+        self.msg.as_ref().ok_or_else(|| self.missing("MSG"))
+    }
+
+    /// 联系人库（MicroMsg.db）
+    pub fn contact(&self) -> Result<&ContactHandler> {
+        self.contact.as_ref().ok_or_else(|| self.missing("MicroMsg"))
+    }
+
+    /// 群聊信息，与联系人同在MicroMsg.db
+    pub fn chatroom(&self) -> Result<&ChatroomHandler> {
+        self.chatroom.as_ref().ok_or_else(|| self.missing("MicroMsg"))
+    }
+
+    /// 头像库（Misc.db / head_image.db）
+    pub fn head_image(&self) -> Result<&HeadImageHandler> {
+        self.head_image.as_ref().ok_or_else(|| self.missing("Misc"))
+    }
+
+    /// 媒体库各分片（MediaMSG*.db），按分片序号排序
+    pub fn media(&self) -> Result<&[MediaHandler]> {
+        if self.media.is_empty() {
+            return Err(self.missing("MediaMSG"));
+        }
+        Ok(&self.media)
+    }
+
+    pub fn favorite(&self) -> Result<&FavoriteHandler> {
+        self.favorite.as_ref().ok_or_else(|| self.missing("Favorite"))
+    }
+
+    pub fn sns(&self) -> Result<&SnsHandler> {
+        self.sns.as_ref().ok_or_else(|| self.missing("Sns"))
+    }
+
+    fn missing(&self, name: &str) -> AppError {
+        AppError::NotFound(format!("{} database not found in workspace {}", name, self.info.id))
+    }
+}
+
+fn path_str(path: &Path) -> String {
+    path.to_string_lossy().to_string()
+}
+
+/// 单个数据库文件同级或上两级目录中的其他数据库
+fn sibling_db(path: &Path, name: &str) -> Option<PathBuf> {
+    [path.parent(), path.parent().and_then(Path::parent)]
+        .into_iter()
+        .flatten()
+        .map(|dir| dir.join(name))
+        .find(|p| p.is_file() && p != path)
+}
+
+/// 打开消息库各分片并建立索引，每个工作区只执行一次
+/// 全文索引在注册后于后台建立；索引文件无法创建（如目录只读）时仅记录警告
+fn open_msg(paths: &[&Path], index_path: Option<&Path>) -> Result<MsgHandler> {
+    let paths: Vec<String> = paths.iter().map(|p| path_str(p)).collect();
+    let mut handler = MsgHandler::with_shards(&paths)?;
+    handler.add_indexes()?;
+    if let Some(index_path) = index_path {
+        if let Err(e) = handler.enable_index(index_path) {
+            tracing::warn!("Full-text index disabled for {:?}: {}", index_path, e);
+        }
+    }
+    Ok(handler)
+}
+
+/// 全文索引文件：合并库旁的`<文件名>.index.db`，或账号目录下的`msg_index.db`
+fn index_path(root: &Path, merged_msg: Option<&Path>) -> PathBuf {
+    match merged_msg {
+        Some(path) => path.with_extension("index.db"),
+        None => root.join(MSG_INDEX_FILE),
+    }
+}
+
+fn new_id() -> String {
+    hex::encode(rand::random::<[u8; 8]>())
+}
+
+/// 锁中毒时仍可继续使用：注册表只在插入和删除时修改，不会处于中间状态
+fn read_workspaces() -> RwLockReadGuard<'static, HashMap<String, Arc<Workspace>>> {
+    WORKSPACES.read().unwrap_or_else(|e| e.into_inner())
+}
+
+fn write_workspaces() -> RwLockWriteGuard<'static, HashMap<String, Arc<Workspace>>> {
+    WORKSPACES.write().unwrap_or_else(|e| e.into_inner())
+}
+
+/// 查找根路径和合并库都相同的已注册工作区
+fn find_existing(root: &Path, merged_msg: Option<&Path>) -> Option<Arc<Workspace>> {
+    let workspaces = read_workspaces();
+    workspaces
+        .values()
+        .find(|ws| ws.info.root == root && ws.info.merged_msg.as_deref() == merged_msg)
+        .cloned()
+}
+
+fn insert(workspace: Workspace) -> Arc<Workspace> {
+    let workspace = Arc::new(workspace);
+    let mut workspaces = write_workspaces();
+    workspaces.insert(workspace.info.id.clone(), workspace.clone());
+
+    if workspace.info.implicit {
+        let mut older: Vec<_> = workspaces
+            .values()
+            .filter(|ws| ws.info.implicit && ws.info.id != workspace.info.id)
+            .map(|ws| (ws.info.created_at, ws.info.id.clone()))
+            .collect();
+        if older.len() >= MAX_IMPLICIT_WORKSPACES {
+            older.sort();
+            for (_, id) in older.iter().take(older.len() + 1 - MAX_IMPLICIT_WORKSPACES) {
+                workspaces.remove(id);
+            }
+        }
+    }
+    workspace
+}
+
+/// 打开了`path`（数据库文件，或目录下任一数据库）的工作区ID，`path`须已规范化
+fn holding(path: &Path) -> Option<String> {
+    let workspaces = read_workspaces();
+    workspaces
+        .values()
+        .find(|ws| {
+            ws.info.merged_msg.as_deref().is_some_and(|p| p.starts_with(path))
+                || ws.info.databases.iter().any(|db| db.path.starts_with(path))
+        })
+        .map(|ws| ws.info.id.clone())
+}
+
+/// 解密输出（文件或目录）正被某个工作区打开时返回错误
+/// 覆盖写这些文件（或删除其`-wal`/`-shm`）会让仍打开的连接读到损坏的数据
+pub fn ensure_output_free(out_path: &Path) -> Result<()> {
+    // 尚不存在的路径不可能被打开
+    let Ok(canonical) = fs::canonicalize(out_path) else {
+        return Ok(());
+    };
+    match holding(&canonical) {
+        Some(id) => Err(AppError::BadRequest(format!(
+            "Output {:?} is open in workspace {}, remove the workspace before decrypting over it",
+            out_path, id
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// 在后台线程中为新注册的工作区建立全文索引，搜索时只需补充新增的消息
+fn index_in_background(workspace: &Arc<Workspace>) {
+    if workspace.msg.as_ref().is_none_or(|msg| msg.index().is_none()) {
+        return;
+    }
+    let workspace = workspace.clone();
+    std::thread::spawn(move || {
+        let Ok(msg) = workspace.msg() else {
+            return;
+        };
+        match msg.update_index() {
+            Ok(added) => tracing::info!("Indexed {} messages for workspace {}", added, workspace.info.id),
+            Err(e) => tracing::warn!("Failed to index workspace {}: {}", workspace.info.id, e),
+        }
+    });
+}
+
+/// 注册已解密的账号目录或单个数据库文件，重复注册同一路径时返回已有的工作区
+pub fn register(root: &Path, merged_msg: Option<&Path>) -> Result<Arc<Workspace>> {
+    let root = fs::canonicalize(root)
+        .map_err(|e| AppError::NotFound(format!("Path not found: {:?} ({})", root, e)))?;
+    let merged_msg = merged_msg
+        .map(|p| {
+            fs::canonicalize(p)
+                .map_err(|e| AppError::NotFound(format!("Database not found: {:?} ({})", p, e)))
+        })
+        .transpose()?;
+
+    if root.is_file() {
+        if merged_msg.as_ref().is_some_and(|p| *p != root) {
+            return Err(AppError::BadRequest(
+                "merged_msg is only supported for directory workspaces".to_string(),
+            ));
+        }
+        if let Some(existing) = find_existing(&root, Some(&root)) {
+            return Ok(existing);
+        }
+        let workspace = insert(Workspace::open_file(new_id(), &root, false)?);
+        index_in_background(&workspace);
+        return Ok(workspace);
+    }
+
+    if let Some(existing) = find_existing(&root, merged_msg.as_deref()) {
+        return Ok(existing);
+    }
+    let workspace = Workspace::open_dir(new_id(), &root, merged_msg.as_deref())?;
+    tracing::info!(
+        "Registered workspace {} at {:?} ({} databases)",
+        workspace.info.id,
+        root,
+        workspace.info.databases.len()
+    );
+    let workspace = insert(workspace);
+    index_in_background(&workspace);
+    Ok(workspace)
+}
+
+/// 按ID获取工作区
+pub fn get(id: &str) -> Result<Arc<Workspace>> {
+    read_workspaces()
+        .get(id)
+        .cloned()
+        .ok_or_else(|| AppError::NotFound(format!("Workspace not found: {}", id)))
+}
+
+/// 注销工作区，正在处理的请求结束后句柄随之释放
+pub fn remove(id: &str) -> Option<Arc<Workspace>> {
+    write_workspaces().remove(id)
+}
+
+/// 所有已注册的工作区，按创建时间排序
+pub fn list() -> Vec<Arc<Workspace>> {
+    let mut workspaces: Vec<_> = read_workspaces().values().cloned().collect();
+    workspaces.sort_by_key(|ws| ws.info.created_at);
+    workspaces
+}
+
+/// 解析请求中的工作区：优先使用`workspace_id`，否则把`merge_path`隐式注册为单文件工作区
+/// 同一文件已显式注册时复用该工作区
+pub fn resolve(workspace_id: Option<&str>, merge_path: Option<&str>) -> Result<Arc<Workspace>> {
+    match (workspace_id, merge_path) {
+        (Some(id), _) => get(id),
+        (None, Some(path)) => {
+            if !Path::new(path).is_file() {
+                return Err(AppError::NotFound(format!("Database not found: {:?}", path)));
+            }
+            validation::validate_db_path(path)?;
+            let path = fs::canonicalize(path)
+                .map_err(|e| AppError::NotFound(format!("Database not found: {:?} ({})", path, e)))?;
+            if let Some(existing) = find_existing(&path, Some(&path)) {
+                return Ok(existing);
+            }
+            Ok(insert(Workspace::open_file(new_id(), &path, true)?))
+        }
+        (None, None) => Err(AppError::BadRequest(
+            "Either workspace_id or merge_path is required".to_string(),
+        )),
+    }
+}
+
+/// 在阻塞线程池中使用工作区句柄执行查询，避免SQLite调用阻塞tokio工作线程
+pub async fn blocking<T, F>(workspace: Arc<Workspace>, f: F) -> Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce(&Workspace) -> Result<T> + Send + 'static,
+{
+    tokio::task::spawn_blocking(move || f(&workspace))
+        .await
+        .map_err(|e| anyhow::anyhow!("Query task failed: {}", e))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::Connection;
+    use tempfile::TempDir;
+
+    const MSG_SCHEMA: &str = "CREATE TABLE MSG (localId INTEGER, StrTalker TEXT, CreateTime INTEGER, MsgSvrID INTEGER, IsSender INTEGER, Type INTEGER);";
+
+    fn create_db(path: &Path, sql: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let conn = Connection::open(path).unwrap();
+        conn.execute_batch(sql).unwrap();
+    }
+
+    #[test]
+    fn test_register_account_dir() {
+        let temp_dir = TempDir::new().unwrap();
+        let msg_dir = temp_dir.path().join("Msg");
+        create_db(
+            &msg_dir.join("MicroMsg.db"),
+            "CREATE TABLE Contact (UserName TEXT, NickName TEXT, Remark TEXT, Alias TEXT, HeadImgUrl TEXT, Type INTEGER);",
+        );
+        create_db(&msg_dir.join("Multi").join("MSG0.db"), MSG_SCHEMA);
+        create_db(&msg_dir.join("Favorite.db"), "CREATE TABLE FavItems (id INTEGER);");
+
+        let workspace = register(temp_dir.path(), None).unwrap();
+        assert!(workspace.msg().is_ok());
+        assert!(workspace.contact().is_ok());
+        assert!(workspace.chatroom().is_ok());
+        assert!(workspace.favorite().is_ok());
+        assert!(matches!(workspace.sns(), Err(AppError::NotFound(_))));
+        assert!(matches!(workspace.head_image(), Err(AppError::NotFound(_))));
+        assert_eq!(workspace.info().databases.len(), 3);
+
+        // 重复注册返回同一个工作区，句柄不会重新打开
+        let again = register(temp_dir.path(), None).unwrap();
+        assert!(Arc::ptr_eq(&workspace, &again));
+        assert!(Arc::ptr_eq(&get(workspace.id()).unwrap(), &workspace));
+
+        // 多个分片时统一查询
+        create_db(&msg_dir.join("Multi").join("MSG1.db"), MSG_SCHEMA);
+        remove(workspace.id()).unwrap();
+        let sharded = register(temp_dir.path(), None).unwrap();
+        assert_eq!(sharded.msg().unwrap().db_paths().len(), 2);
+        assert!(matches!(get(workspace.id()), Err(AppError::NotFound(_))));
+        remove(sharded.id());
+    }
+
+    #[test]
+    fn test_resolve_legacy_merge_path() {
+        let temp_dir = TempDir::new().unwrap();
+        let merge_path = temp_dir.path().join("merge_all.db");
+        create_db(&merge_path, MSG_SCHEMA);
+
+        let path = merge_path.to_str().unwrap();
+        let workspace = resolve(None, Some(path)).unwrap();
+        assert!(workspace.msg().is_ok());
+        assert!(Arc::ptr_eq(&workspace, &resolve(None, Some(path)).unwrap()));
+        assert!(Arc::ptr_eq(&workspace, &resolve(Some(workspace.id()), None).unwrap()));
+
+        // 隐式注册不在数据库旁创建全文索引
+        assert!(workspace.info().implicit);
+        assert!(workspace.msg().unwrap().index().is_none());
+        assert!(!merge_path.with_extension("index.db").exists());
+
+        assert!(matches!(resolve(None, None), Err(AppError::BadRequest(_))));
+        let missing = temp_dir.path().join("missing.db");
+        assert!(matches!(
+            resolve(None, Some(missing.to_str().unwrap())),
+            Err(AppError::NotFound(_))
+        ));
+        let not_db = temp_dir.path().join("notes.txt");
+        fs::write(&not_db, b"text").unwrap();
+        assert!(resolve(None, Some(not_db.to_str().unwrap())).is_err());
+        remove(workspace.id());
+    }
+
+    #[test]
+    fn test_output_held_by_workspace() {
+        let temp_dir = TempDir::new().unwrap();
+        let merge_path = temp_dir.path().join("open.db");
+        create_db(&merge_path, MSG_SCHEMA);
+
+        let workspace = register(&merge_path, None).unwrap();
+        assert!(matches!(ensure_output_free(&merge_path), Err(AppError::BadRequest(_))));
+        // 输出目录中含有已打开的数据库
+        assert!(matches!(ensure_output_free(temp_dir.path()), Err(AppError::BadRequest(_))));
+        assert!(ensure_output_free(&temp_dir.path().join("other.db")).is_ok());
+
+        remove(workspace.id());
+        assert!(ensure_output_free(&merge_path).is_ok());
+    }
+}
@@ -1,5 +1,10 @@
 use serde::{Deserialize, Serialize};
 
+use crate::core::cipher_profile::CipherProfile;
+use crate::core::discovery::{AccountManifest, DiscoveredDb};
+use crate::utils::{AppError, Result};
+use crate::core::decryption::{BatchJobResult, CorruptPageMode, DecryptReport, WalMode};
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WxInfoResponse {
     pub pid: u32,
@@ -15,9 +20,48 @@ pub struct WxInfoResponse {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DecryptRequest {
+    /// 64位十六进制密钥，或已派生的原始密钥`x'<64 hex>[32 hex salt]'`
     pub key: String,
     pub db_path: String,
     pub out_path: String,
+    /// 损坏页处理方式：fail / skip / zero_fill，默认fail
+    /// skip保留损坏页解密出的原始字节，输出可能无法被SQLite正常打开；zero_fill以全零页代替
+    #[serde(default)]
+    pub on_corrupt: CorruptPageMode,
+    /// 加密参数，可传预设名称（wechat_v3 / wechat_v4）或完整参数，为空时自动检测
+    pub profile: Option<CipherProfileParam>,
+    /// 增量解密，只重新解密上次之后变化的页
+    #[serde(default)]
+    pub incremental: bool,
+    /// WAL处理方式：ignore / decrypt / checkpoint，默认decrypt
+    #[serde(default)]
+    pub wal: WalMode,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum CipherProfileParam {
+    Preset(String),
+    Custom(CipherProfile),
+}
+
+impl CipherProfileParam {
+    pub fn resolve(&self) -> Option<CipherProfile> {
+        match self {
+            CipherProfileParam::Preset(name) => CipherProfile::from_name(name),
+            CipherProfileParam::Custom(profile) => Some(profile.clone()),
+        }
+    }
+
+    /// 解析可选的加密参数，未知预设名称返回校验错误
+    pub fn resolve_optional(param: Option<&Self>) -> Result<Option<CipherProfile>> {
+        match param {
+            Some(param) => Ok(Some(param.resolve().ok_or_else(|| {
+                AppError::ValidationFailed(format!("Unknown cipher profile: {:?}", param))
+            })?)),
+            None => Ok(None),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -25,5 +69,54 @@ pub struct DecryptResponse {
     pub success: bool,
     pub message: String,
     pub out_path: Option<String>,
+    pub report: Option<DecryptReport>,
+}
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchDecryptRequest {
+    pub key: String,
+    pub db_paths: Vec<String>,
+    pub out_dir: String,
+    pub profile: Option<CipherProfileParam>,
+    /// 并发数，为空或0时按CPU核数决定
+    #[serde(default)]
+    pub workers: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchDecryptResponse {
+    pub success: bool,
+    pub message: String,
+    pub results: Vec<BatchJobResult>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiscoverRequest {
+    /// 账号目录（WeChat Files/<wxid>）
+    pub wx_dir: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiscoverResponse {
+    pub success: bool,
+    pub message: String,
+    pub databases: Vec<DiscoveredDb>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountDecryptRequest {
+    pub key: String,
+    pub wx_dir: String,
+    pub out_dir: String,
+    pub profile: Option<CipherProfileParam>,
+    #[serde(default)]
+    pub workers: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountDecryptResponse {
+    pub success: bool,
+    pub message: String,
+    pub manifest: Option<AccountManifest>,
+}
@@ -1,6 +1,7 @@
-use crate::utils::Result;
+use crate::utils::{AppError, Result};
 use anyhow::anyhow;
-use std::path::Path;
+use std::fs;
+use std::path::{Path, PathBuf};
 
 /// 验证数据库路径
 pub fn validate_db_path(path: &str) -> Result<()> {
@@ -21,6 +22,42 @@ pub fn validate_db_path(path: &str) -> Result<()> {
     Ok(())
 }
 
+/// 验证输出路径不是源文件（或源目录及其子路径），避免创建输出时截断未解密的原始数据
+pub fn validate_output_path(source: &Path, output: &Path) -> Result<()> {
+    let source = canonicalize_existing_prefix(source);
+    let output = canonicalize_existing_prefix(output);
+    if output.starts_with(&source) {
+        return Err(AppError::BadRequest(format!(
+            "Output {} must not be the source or inside it ({})",
+            output.display(),
+            source.display()
+        )));
+    }
+    Ok(())
+}
+
+/// 规范化可能尚不存在的路径：规范化最近的已存在上级目录，再拼接其余部分
+fn canonicalize_existing_prefix(path: &Path) -> PathBuf {
+    let mut existing = path;
+    let mut rest = Vec::new();
+    loop {
+        if let Ok(canonical) = fs::canonicalize(existing) {
+            return rest.iter().rev().fold(canonical, |acc, name| acc.join(name));
+        }
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(name)) => {
+                rest.push(name);
+                existing = if parent.as_os_str().is_empty() {
+                    Path::new(".")
+                } else {
+                    parent
+                };
+            }
+            _ => return path.to_path_buf(),
+        }
+    }
+}
+
 /// 验证密钥格式
 pub fn validate_key(key: &str) -> Result<()> {
     if key.is_empty() {
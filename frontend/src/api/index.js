@@ -13,6 +13,7 @@ export const wxApi = {
 export const chatApi = {
   getContacts: (data) => api.post('/chat/contacts', data),
   getContactDetail: (wxid, data) => api.get(`/chat/contacts/${wxid}`, { data }),
+  getContactLabels: (data) => api.post('/chat/labels', data),
   getMsgCount: (data) => api.post('/chat/msg/count', data),
   getMsgList: (data) => api.post('/chat/msg/list', data),
   searchMessages: (data) => api.post('/chat/msg/search', data),